@@ -0,0 +1,319 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Logical plan analyzer
+//!
+//! Expressions can refer to columns by name with `Expr::ColumnRef`, optionally qualified by
+//! the name of the table that the column belongs to. The analyzer resolves each reference to
+//! the position of the column in the input of the plan that contains it, using the same
+//! case-folding rule as unquoted SQL identifiers unless the session is case-sensitive.
+//! References to missing columns are reported with the closest matching name, if there is
+//! one.
+
+use std::rc::Rc;
+
+use arrow::datatypes::Schema;
+
+use super::errors::*;
+use super::logical::{Expr, LogicalPlan};
+use super::sqlplanner::resolve_identifier;
+use super::visitor::{rewrite_expr, rewrite_plan, ExprRewriter, PlanRewriter};
+
+/// Resolve all of the column references in a plan, matching names case-sensitively when
+/// `case_sensitive` is true
+pub fn analyze(plan: &Rc<LogicalPlan>, case_sensitive: bool) -> Result<Rc<LogicalPlan>> {
+    rewrite_plan(plan, &mut PlanResolver { case_sensitive })
+}
+
+/// Resolves the column references in the expressions of each plan
+struct PlanResolver {
+    case_sensitive: bool,
+}
+
+impl PlanRewriter for PlanResolver {
+    fn rewrite(&mut self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        let expr = plan.expressions();
+        if expr.is_empty() {
+            return Ok(plan.clone());
+        }
+        // scan filters refer to the columns of the table
+        let relation = match plan.inputs().first() {
+            Some(input) => relation_name(input),
+            None => relation_name(plan),
+        };
+        let mut resolver = ColumnResolver {
+            schema: plan.expression_schema(),
+            relation,
+            case_sensitive: self.case_sensitive,
+        };
+        let expr = expr
+            .into_iter()
+            .map(|e| rewrite_expr(e, &mut resolver))
+            .collect::<Result<Vec<Expr>>>()?;
+        Ok(Rc::new(plan.with_new_expressions(expr)))
+    }
+}
+
+/// Resolve the column references in expressions that are evaluated against the output of a
+/// plan
+pub fn resolve_exprs(
+    expr: &[Expr],
+    input: &LogicalPlan,
+    case_sensitive: bool,
+) -> Result<Vec<Expr>> {
+    expr.iter()
+        .map(|e| resolve_expr(e, input.schema(), relation_name(input), case_sensitive))
+        .collect()
+}
+
+/// Resolve the column references in an expression that is evaluated against rows with the
+/// specified schema, whose columns belong to the table `relation` if it is known
+pub fn resolve_expr(
+    expr: &Expr,
+    schema: &Schema,
+    relation: Option<&str>,
+    case_sensitive: bool,
+) -> Result<Expr> {
+    rewrite_expr(
+        expr,
+        &mut ColumnResolver {
+            schema,
+            relation,
+            case_sensitive,
+        },
+    )
+}
+
+/// Replaces column references with the positions of the columns that they refer to
+struct ColumnResolver<'a> {
+    schema: &'a Schema,
+    relation: Option<&'a str>,
+    case_sensitive: bool,
+}
+
+impl<'a> ExprRewriter for ColumnResolver<'a> {
+    fn rewrite(&mut self, expr: Expr) -> Result<Expr> {
+        match expr {
+            Expr::ColumnRef {
+                relation: ref qualifier,
+                ref name,
+            } => {
+                let qualifier = qualifier.as_ref().map(|q| q.as_str());
+                match resolve_column(
+                    self.schema,
+                    self.relation,
+                    qualifier,
+                    name,
+                    self.case_sensitive,
+                ) {
+                    Ok(i) => Ok(Expr::Column(i)),
+                    Err(e) => Err(ExecutionError::InvalidColumn(e)),
+                }
+            }
+            other => Ok(other),
+        }
+    }
+}
+
+/// Find the position of the column in a schema that a name refers to. The columns of the
+/// schema belong to the table `relation`, if it is known, and a name that is qualified with a
+/// table name only matches columns of that table. Names are matched case-sensitively when
+/// `case_sensitive` is true, otherwise in the same way as unquoted SQL identifiers.
+pub fn resolve_column(
+    schema: &Schema,
+    relation: Option<&str>,
+    qualifier: Option<&str>,
+    name: &str,
+    case_sensitive: bool,
+) -> ::std::result::Result<usize, String> {
+    if let Some(qualifier) = qualifier {
+        let matches = match relation {
+            Some(relation) => resolve_identifier(&[relation], qualifier, case_sensitive)?.is_some(),
+            None => false,
+        };
+        if !matches {
+            return Err(format!(
+                "Invalid table '{}' in column reference '{}.{}'{}",
+                qualifier,
+                qualifier,
+                name,
+                suggestion(qualifier, &relation.into_iter().collect::<Vec<&str>>())
+            ));
+        }
+    }
+    let names: Vec<&str> = schema.columns().iter().map(|c| c.name().as_str()).collect();
+    match resolve_identifier(&names, name, case_sensitive)? {
+        Some(i) => Ok(i),
+        None => Err(format!(
+            "Invalid identifier '{}' for schema {}{}",
+            name,
+            schema.to_string(),
+            suggestion(name, &names)
+        )),
+    }
+}
+
+/// Get the name of the table that all of the columns of a plan belong to, if there is one
+pub fn relation_name(plan: &LogicalPlan) -> Option<&str> {
+    match plan {
+        LogicalPlan::TableScan { ref table_name, .. } => Some(table_name),
+        LogicalPlan::Selection { ref input, .. }
+        | LogicalPlan::Sort { ref input, .. }
+        | LogicalPlan::Limit { ref input, .. } => relation_name(input),
+        // a projection of columns keeps their table
+        LogicalPlan::Projection {
+            ref expr,
+            ref input,
+            ..
+        } if expr.iter().all(|e| match e {
+            Expr::Column(_) => true,
+            _ => false,
+        }) =>
+        {
+            relation_name(input)
+        }
+        _ => None,
+    }
+}
+
+/// Suggest the closest of a list of names to a name that could not be found, as the end of an
+/// error message
+fn suggestion(name: &str, names: &[&str]) -> String {
+    match closest_name(name, names) {
+        Some(closest) => format!(". Did you mean '{}'?", closest),
+        None => String::new(),
+    }
+}
+
+/// Find the name with the smallest edit distance to a name, ignoring case, if it is close
+/// enough to be a likely misspelling
+pub fn closest_name<'a>(name: &str, names: &[&'a str]) -> Option<&'a str> {
+    let name = name.to_lowercase();
+    names
+        .iter()
+        .map(|n| (edit_distance(&name, &n.to_lowercase()), *n))
+        .filter(|(d, _)| *d <= name.chars().count() / 2)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, n)| n)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push(
+                (previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::*;
+
+    fn column_ref(relation: Option<&str>, name: &str) -> Expr {
+        Expr::ColumnRef {
+            relation: relation.map(|r| r.to_string()),
+            name: name.to_string(),
+        }
+    }
+
+    fn scan() -> Rc<LogicalPlan> {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("first_name", DataType::Utf8, false),
+            Field::new("Name", DataType::Utf8, false),
+            Field::new("NAME", DataType::Utf8, false),
+        ]));
+        Rc::new(LogicalPlan::TableScan {
+            schema_name: "default".to_string(),
+            table_name: "person".to_string(),
+            schema: schema.clone(),
+            projection: None,
+            projected_schema: schema,
+            filters: vec![],
+        })
+    }
+
+    fn resolve(expr: Expr) -> Result<Expr> {
+        let plan = Rc::new(LogicalPlan::Selection {
+            expr,
+            input: scan(),
+        });
+        match analyze(&plan, false)?.as_ref() {
+            LogicalPlan::Selection { ref expr, .. } => Ok(expr.clone()),
+            _ => panic!(),
+        }
+    }
+
+    fn error_message(expr: Expr) -> String {
+        match resolve(expr) {
+            Err(ExecutionError::InvalidColumn(msg)) => msg,
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolve_column_refs() {
+        let expr = column_ref(None, "first_name").eq(&column_ref(Some("Person"), "ID"));
+        assert_eq!("#1 Eq #0", format!("{:?}", resolve(expr).unwrap()));
+        // an exact match is preferred to a case-insensitive match
+        assert_eq!(Expr::Column(2), resolve(column_ref(None, "Name")).unwrap());
+
+        // names must match exactly in a case-sensitive session
+        let plan = Rc::new(LogicalPlan::Selection {
+            expr: column_ref(None, "ID"),
+            input: scan(),
+        });
+        assert!(analyze(&plan, true).is_err());
+        assert!(analyze(&plan, false).is_ok());
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        assert_eq!(
+            "Ambiguous identifier 'name' could refer to any of [\"Name\", \"NAME\"]",
+            error_message(column_ref(None, "name"))
+        );
+        assert!(error_message(column_ref(None, "frist_name"))
+            .starts_with("Invalid identifier 'frist_name' for schema "));
+        assert!(
+            error_message(column_ref(None, "frist_name")).ends_with("Did you mean 'first_name'?")
+        );
+        // no suggestion is made for names that are not similar to any column
+        assert!(error_message(column_ref(None, "salary")).ends_with("nullable: false }]"));
+        assert_eq!(
+            "Invalid table 'persons' in column reference 'persons.id'. Did you mean 'person'?",
+            error_message(column_ref(Some("persons"), "id"))
+        );
+    }
+
+    #[test]
+    fn test_closest_name() {
+        let names = vec!["id", "first_name", "last_name"];
+        assert_eq!(Some("last_name"), closest_name("LastName", &names));
+        assert_eq!(Some("id"), closest_name("ix", &names));
+        assert_eq!(None, closest_name("age", &names));
+    }
+}
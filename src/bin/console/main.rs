@@ -16,19 +16,19 @@ extern crate clap;
 extern crate datafusion;
 
 use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::io::Read;
 use std::rc::Rc;
 use std::str;
 use std::time::Instant;
 
 use clap::{App, Arg};
+use datafusion::errors::Result;
 use datafusion::exec::*;
 use datafusion::functions::geospatial::st_astext::*;
 use datafusion::functions::geospatial::st_point::*;
 use datafusion::functions::math::*;
-use datafusion::sqlast::ASTNode::SQLCreateTable;
-use datafusion::sqlparser::*;
+use datafusion::logical::LogicalPlan;
+use datafusion::sqldialect::dialect_from_name;
 
 mod linereader;
 
@@ -40,25 +40,23 @@ fn setup_console(cmdline: clap::ArgMatches) {
     //let etcd_endpoints = cmdline.value_of("ETCD").unwrap();
     let mut console = Console::new(/*etcd_endpoints.to_string()*/);
 
+    if let Some(name) = cmdline.value_of("DIALECT") {
+        match dialect_from_name(name) {
+            Some(dialect) => console.ctx.set_dialect(dialect),
+            None => {
+                println!("Unknown SQL dialect {}", name);
+                return;
+            }
+        }
+    }
+
     match cmdline.value_of("SCRIPT") {
         Some(filename) => match File::open(filename) {
-            Ok(f) => {
-                let mut cmd_buffer = String::new();
-                let mut reader = BufReader::new(&f);
-                for line in reader.lines() {
-                    match line {
-                        Ok(cmd) => {
-                            cmd_buffer.push_str(&cmd);
-                            if cmd_buffer.as_str().ends_with(";") {
-                                console.execute(&cmd_buffer[0..cmd_buffer.len() - 2]);
-                                cmd_buffer = String::new();
-                            }
-                        }
-                        Err(e) => println!("Error: {}", e),
-                    }
-                }
-                if cmd_buffer.as_str().ends_with(";") {
-                    console.execute(&cmd_buffer[0..cmd_buffer.len() - 2]);
+            Ok(mut f) => {
+                let mut script = String::new();
+                match f.read_to_string(&mut script) {
+                    Ok(_) => console.execute(&script),
+                    Err(e) => println!("Error: {}", e),
                 }
             }
             Err(e) => println!("Could not open file {}: {}", filename, e),
@@ -109,6 +107,14 @@ fn main() {
                     .required(false)
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("DIALECT")
+                    .help("SQL dialect (ansi, postgresql or hive)")
+                    .short("d")
+                    .long("dialect")
+                    .required(false)
+                    .takes_value(true),
+            )
             .get_matches();
     setup_console(cmdline);
 }
@@ -128,56 +134,67 @@ impl Console {
         Console { ctx }
     }
 
-    /// Execute a SQL statement or console command
+    /// Execute one or more SQL statements separated by semicolons. The results of each
+    /// statement are printed before the next statement is run, and the first statement that
+    /// fails stops the script.
     fn execute(&mut self, sql: &str) {
         println!("Executing query ...");
 
-        let timer = Instant::now();
-
-        // parse the SQL
-        match Parser::parse_sql(String::from(sql)) {
-            Ok(ast) => match ast {
-                SQLCreateTable { .. } => {
-                    self.ctx.sql(&sql).unwrap();
-                    //println!("Registered schema with execution context");
-                    ()
+        let statements = match self.ctx.parse_script(sql) {
+            Ok(statements) => statements,
+            Err(e) => {
+                println!("Error: {}", e);
+                return;
+            }
+        };
+
+        for (ast, start) in statements {
+            let statement = ast.to_string();
+            // the time includes planning the statement as well as running it
+            let timer = Instant::now();
+            let result = self
+                .ctx
+                .sql_statement(sql, ast)
+                .and_then(|df| self.execute_plan(df.plan()));
+            match result {
+                Ok(result) => print_result(result, timer),
+                Err(e) => {
+                    println!("Error in statement at {}: {}\nError: {}", start, statement, e);
+                    return;
                 }
-                _ => match self.ctx.create_logical_plan(sql) {
-                    Ok(logical_plan) => {
-                        let physical_plan = PhysicalPlan::Interactive {
-                            plan: logical_plan.clone(),
-                        };
-
-                        let result = self.ctx.execute(&physical_plan);
-
-                        match result {
-                            Ok(result) => {
-                                let elapsed = timer.elapsed();
-                                let elapsed_seconds = elapsed.as_secs() as f64
-                                    + elapsed.subsec_nanos() as f64 / 1000000000.0;
-
-                                match result {
-                                    ExecutionResult::Unit => {
-                                        println!("Query executed in {} seconds", elapsed_seconds);
-                                    }
-                                    ExecutionResult::Count(n) => {
-                                        println!(
-                                            "Query executed in {} seconds and updated {} rows",
-                                            elapsed_seconds, n
-                                        );
-                                    }
-                                    ExecutionResult::Str(_) => {
-                                        println!("Query executed in {} seconds", elapsed_seconds);
-                                    }
-                                }
-                            }
-                            Err(e) => println!("Error: {:?}", e),
-                        }
-                    }
-                    Err(e) => println!("Error: {:?}", e),
-                },
-            },
-            Err(e) => println!("Error: {:?}", e),
+            }
+        }
+    }
+
+    /// Execute a logical plan, which prints the rows of queries
+    fn execute_plan(&self, logical_plan: &Rc<LogicalPlan>) -> Result<ExecutionResult> {
+        match logical_plan.as_ref() {
+            // statements such as CREATE EXTERNAL TABLE have already been run
+            LogicalPlan::EmptyRelation { .. } => Ok(ExecutionResult::Unit),
+            _ => self.ctx.execute(&PhysicalPlan::Interactive {
+                plan: logical_plan.clone(),
+            }),
+        }
+    }
+}
+
+/// Print how long a statement took, and the number of rows that it updated
+fn print_result(result: ExecutionResult, timer: Instant) {
+    let elapsed = timer.elapsed();
+    let elapsed_seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1000000000.0;
+
+    match result {
+        ExecutionResult::Unit => {
+            println!("Query executed in {} seconds", elapsed_seconds);
+        }
+        ExecutionResult::Count(n) => {
+            println!(
+                "Query executed in {} seconds and updated {} rows",
+                elapsed_seconds, n
+            );
+        }
+        ExecutionResult::Str(_) => {
+            println!("Query executed in {} seconds", elapsed_seconds);
         }
     }
 }
@@ -0,0 +1,428 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Type coercion
+//!
+//! The operands of a binary expression are evaluated with a single type, so the coercion pass
+//! wraps operands of a different type in an `Expr::Cast` when the plan is built. The rules
+//! for the operand type are:
+//!
+//! - `AND` and `OR` require two `Boolean` operands, and `NOT` requires a `Boolean` operand
+//! - `LIKE` and `ILIKE` require two `Utf8` operands
+//! - comparisons accept two operands of the same type, which may be `Boolean` or `Utf8`
+//! - numeric operands are widened to the smallest type that can represent both of them (see
+//!   `get_supertype`). An unsigned integer combined with a signed integer is widened to a
+//!   signed integer with more bits than the unsigned type, and `UInt64` combined with a
+//!   signed integer is widened to `Float64` because no integer type can hold both ranges
+//! - a `Utf8` operand combined with a numeric operand is parsed as the numeric type, so
+//!   `id = '3'` compares integers. Values that cannot be parsed are reported when the cast
+//!   is evaluated
+//!
+//! Any other combination of types is an error, which is reported before the plan is
+//! executed. There are no date or time types, so no rules are needed for them.
+
+use std::rc::Rc;
+
+use arrow::datatypes::{DataType, Field, Schema};
+
+use super::errors::*;
+use super::logical::{Expr, LogicalPlan, Operator};
+use super::types::get_supertype;
+
+/// Insert the casts that the expressions of a plan need to be evaluated. Column references
+/// must already have been resolved by the analyzer.
+pub fn coerce(plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+    let new_plan = match plan.as_ref() {
+        LogicalPlan::Projection {
+            ref expr,
+            ref input,
+            ref schema,
+        } => {
+            let input = coerce(input)?;
+            let expr = coerce_exprs(expr, input.schema())?;
+            LogicalPlan::Projection {
+                schema: refresh_schema(schema, &expr, input.schema())?,
+                expr,
+                input,
+            }
+        }
+        LogicalPlan::Selection {
+            ref expr,
+            ref input,
+        } => {
+            let input = coerce(input)?;
+            LogicalPlan::Selection {
+                expr: coerce_predicate(expr, input.schema())?,
+                input,
+            }
+        }
+        LogicalPlan::Aggregate {
+            ref input,
+            ref group_expr,
+            ref aggr_expr,
+            ref schema,
+        } => {
+            let input = coerce(input)?;
+            let group_expr = coerce_exprs(group_expr, input.schema())?;
+            let aggr_expr = coerce_exprs(aggr_expr, input.schema())?;
+            let all_expr: Vec<Expr> = group_expr.iter().chain(aggr_expr.iter()).cloned().collect();
+            LogicalPlan::Aggregate {
+                schema: refresh_schema(schema, &all_expr, input.schema())?,
+                group_expr,
+                aggr_expr,
+                input,
+            }
+        }
+        LogicalPlan::Sort {
+            ref expr,
+            ref input,
+            ref schema,
+        } => {
+            let input = coerce(input)?;
+            LogicalPlan::Sort {
+                expr: coerce_exprs(expr, input.schema())?,
+                input,
+                schema: schema.clone(),
+            }
+        }
+        LogicalPlan::Limit {
+            limit,
+            ref input,
+            ref schema,
+        } => LogicalPlan::Limit {
+            limit: *limit,
+            input: coerce(input)?,
+            schema: schema.clone(),
+        },
+        LogicalPlan::TableScan {
+            ref schema,
+            ref filters,
+            ..
+        }
+        | LogicalPlan::CsvFile {
+            ref schema,
+            ref filters,
+            ..
+        }
+        | LogicalPlan::NdJsonFile {
+            ref schema,
+            ref filters,
+            ..
+        }
+        | LogicalPlan::ParquetFile {
+            ref schema,
+            ref filters,
+            ..
+        } => {
+            // scan filters refer to the columns of the table
+            let filters = filters
+                .iter()
+                .map(|f| coerce_predicate(f, schema))
+                .collect::<Result<Vec<Expr>>>()?;
+            plan.with_filters(filters)
+        }
+        LogicalPlan::InMemory { .. } | LogicalPlan::EmptyRelation { .. } => return Ok(plan.clone()),
+    };
+    Ok(Rc::new(new_plan))
+}
+
+/// Insert the casts that expressions need to be evaluated against rows with the specified
+/// schema
+pub fn coerce_exprs(expr: &[Expr], schema: &Schema) -> Result<Vec<Expr>> {
+    expr.iter().map(|e| coerce_expr(e, schema)).collect()
+}
+
+/// Insert the casts that a predicate needs to be evaluated against rows with the specified
+/// schema, and check that it is a boolean expression
+pub fn coerce_predicate(expr: &Expr, schema: &Schema) -> Result<Expr> {
+    let expr = coerce_expr(expr, schema)?;
+    match expr.get_type(schema)? {
+        DataType::Boolean => Ok(expr),
+        other => Err(ExecutionError::General(format!(
+            "Filter expression {:?} must be Boolean but is {:?}",
+            expr, other
+        ))),
+    }
+}
+
+/// Insert the casts that an expression needs to be evaluated against rows with the specified
+/// schema
+pub fn coerce_expr(expr: &Expr, schema: &Schema) -> Result<Expr> {
+    let coerce = |e: &Expr| coerce_expr(e, schema);
+    Ok(match expr {
+        Expr::Column(_) | Expr::ColumnRef { .. } | Expr::Literal(_) => expr.clone(),
+        Expr::Cast {
+            ref expr,
+            ref data_type,
+        } => Expr::Cast {
+            expr: Rc::new(coerce(expr)?),
+            data_type: data_type.clone(),
+        },
+        Expr::IsNull(ref expr) => Expr::IsNull(Rc::new(coerce(expr)?)),
+        Expr::IsNotNull(ref expr) => Expr::IsNotNull(Rc::new(coerce(expr)?)),
+        Expr::Not(ref expr) => {
+            let expr = coerce(expr)?;
+            match expr.get_type(schema)? {
+                DataType::Boolean => Expr::Not(Rc::new(expr)),
+                other => {
+                    return Err(ExecutionError::General(format!(
+                        "NOT requires a boolean expression but found {:?}",
+                        other
+                    )))
+                }
+            }
+        }
+        Expr::BinaryExpr {
+            ref left,
+            ref op,
+            ref right,
+        } => {
+            let left = coerce(left)?;
+            let right = coerce(right)?;
+            let operand_type =
+                binary_operand_type(&left.get_type(schema)?, op, &right.get_type(schema)?)?;
+            Expr::BinaryExpr {
+                left: Rc::new(cast_expr(left, &operand_type, schema)?),
+                op: op.clone(),
+                right: Rc::new(cast_expr(right, &operand_type, schema)?),
+            }
+        }
+        Expr::Sort { ref expr, asc } => Expr::Sort {
+            expr: Rc::new(coerce(expr)?),
+            asc: *asc,
+        },
+        // The declared argument types of a function are not known here, so arguments are not
+        // cast to them. The SQL planner casts arguments when it creates the call, and calls
+        // with arguments of other types are rejected when the expression is compiled.
+        Expr::ScalarFunction {
+            ref name,
+            ref args,
+            ref return_type,
+        } => Expr::ScalarFunction {
+            name: name.clone(),
+            args: coerce_exprs(args, schema)?,
+            return_type: return_type.clone(),
+        },
+        Expr::AggregateFunction {
+            ref name,
+            ref args,
+            ref return_type,
+        } => Expr::AggregateFunction {
+            name: name.clone(),
+            args: coerce_exprs(args, schema)?,
+            return_type: return_type.clone(),
+        },
+    })
+}
+
+/// Get the type that both operands of a binary operator are cast to before it is evaluated
+pub fn binary_operand_type(
+    left: &DataType,
+    op: &Operator,
+    right: &DataType,
+) -> ::std::result::Result<DataType, String> {
+    let operand_type = match op {
+        Operator::And | Operator::Or => match (left, right) {
+            (DataType::Boolean, DataType::Boolean) => Some(DataType::Boolean),
+            _ => None,
+        },
+        Operator::Like | Operator::ILike => match (left, right) {
+            (DataType::Utf8, DataType::Utf8) => Some(DataType::Utf8),
+            _ => None,
+        },
+        Operator::Eq
+        | Operator::NotEq
+        | Operator::Lt
+        | Operator::LtEq
+        | Operator::Gt
+        | Operator::GtEq => {
+            if left == right {
+                Some(left.clone())
+            } else {
+                numeric_operand_type(left, right)
+            }
+        }
+        Operator::Plus
+        | Operator::Minus
+        | Operator::Multiply
+        | Operator::Divide
+        | Operator::Modulus => numeric_operand_type(left, right),
+    };
+    operand_type.ok_or_else(|| {
+        format!(
+            "Operator {:?} cannot be applied to types {:?} and {:?}",
+            op, left, right
+        )
+    })
+}
+
+/// Get the type that two numeric operands are cast to, where a `Utf8` operand takes the type
+/// of the other operand
+fn numeric_operand_type(left: &DataType, right: &DataType) -> Option<DataType> {
+    match (left, right) {
+        (DataType::Utf8, other) | (other, DataType::Utf8) if is_numeric(other) => {
+            Some(other.clone())
+        }
+        _ if is_numeric(left) && is_numeric(right) => get_supertype(left, right),
+        _ => None,
+    }
+}
+
+/// Determine whether values of a type are numbers
+pub fn is_numeric(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => true,
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => true,
+        DataType::Float32 | DataType::Float64 => true,
+        _ => false,
+    }
+}
+
+/// Cast an expression to a type, unless it already has that type
+pub fn cast_expr(
+    expr: Expr,
+    data_type: &DataType,
+    schema: &Schema,
+) -> ::std::result::Result<Expr, String> {
+    if expr.get_type(schema)? == *data_type {
+        Ok(expr)
+    } else {
+        Ok(Expr::Cast {
+            expr: Rc::new(expr),
+            data_type: data_type.clone(),
+        })
+    }
+}
+
+/// Update the types of the fields of a schema that is computed from expressions
+fn refresh_schema(schema: &Rc<Schema>, expr: &[Expr], input_schema: &Schema) -> Result<Rc<Schema>> {
+    if schema.columns().len() != expr.len() {
+        return Ok(schema.clone());
+    }
+    let fields = schema
+        .columns()
+        .iter()
+        .zip(expr.iter())
+        .map(|(f, e)| {
+            Ok(Field::new(
+                f.name(),
+                e.get_type(input_schema)?,
+                f.is_nullable(),
+            ))
+        })
+        .collect::<Result<Vec<Field>>>()?;
+    if fields
+        .iter()
+        .zip(schema.columns().iter())
+        .all(|(a, b)| a.data_type() == b.data_type())
+    {
+        Ok(schema.clone())
+    } else {
+        Ok(Rc::new(Schema::new(fields)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::ScalarValue;
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::UInt32, false),
+            Field::new("c", DataType::Utf8, false),
+            Field::new("d", DataType::UInt64, false),
+            Field::new("e", DataType::Boolean, false),
+        ])
+    }
+
+    fn binary(left: Expr, op: Operator, right: Expr) -> Expr {
+        Expr::BinaryExpr {
+            left: Rc::new(left),
+            op,
+            right: Rc::new(right),
+        }
+    }
+
+    #[test]
+    fn test_operand_types() {
+        use self::DataType::*;
+        let plus = |l, r| binary_operand_type(&l, &Operator::Plus, &r);
+        assert_eq!(Ok(Int64), plus(Int32, Int64));
+        assert_eq!(Ok(Float64), plus(Float32, Float64));
+        assert_eq!(Ok(Int16), plus(UInt8, Int8));
+        assert_eq!(Ok(Int64), plus(Int32, UInt32));
+        assert_eq!(Ok(Float64), plus(UInt64, Int8));
+        assert_eq!(Ok(UInt64), plus(UInt64, UInt8));
+        assert_eq!(Ok(Int32), plus(Utf8, Int32));
+        assert!(plus(Utf8, Utf8).is_err());
+        assert!(plus(Boolean, Int32).is_err());
+
+        let eq = |l, r| binary_operand_type(&l, &Operator::Eq, &r);
+        assert_eq!(Ok(Utf8), eq(Utf8, Utf8));
+        assert_eq!(Ok(Boolean), eq(Boolean, Boolean));
+        assert_eq!(Ok(Float32), eq(Float32, Utf8));
+        assert!(eq(Boolean, Utf8).is_err());
+
+        assert!(binary_operand_type(&Int32, &Operator::And, &Boolean).is_err());
+        assert!(binary_operand_type(&Int32, &Operator::Like, &Utf8).is_err());
+    }
+
+    #[test]
+    fn test_coerce_expr() {
+        let schema = schema();
+        let expr = binary(Expr::Column(0), Operator::Lt, Expr::Column(1));
+        assert_eq!(
+            "CAST(#0 AS Int64) Lt CAST(#1 AS Int64)",
+            format!("{:?}", coerce_expr(&expr, &schema).unwrap())
+        );
+
+        let expr = binary(
+            Expr::Column(2),
+            Operator::Eq,
+            Expr::Literal(ScalarValue::Int64(3)),
+        );
+        assert_eq!(
+            "CAST(#2 AS Int64) Eq Int64(3)",
+            format!("{:?}", coerce_expr(&expr, &schema).unwrap())
+        );
+
+        let expr = binary(Expr::Column(3), Operator::Plus, Expr::Column(0));
+        let coerced = coerce_expr(&expr, &schema).unwrap();
+        assert_eq!(Ok(DataType::Float64), coerced.get_type(&schema));
+    }
+
+    #[test]
+    fn test_coerce_errors() {
+        let schema = schema();
+        let expr = binary(Expr::Column(0), Operator::And, Expr::Column(4));
+        assert_eq!(
+            "General(\"Operator And cannot be applied to types Int32 and Boolean\")",
+            format!("{:?}", coerce_expr(&expr, &schema).unwrap_err())
+        );
+        assert!(coerce_expr(&Expr::Not(Rc::new(Expr::Column(2))), &schema).is_err());
+        assert!(coerce_predicate(&Expr::Column(0), &schema).is_err());
+        assert!(coerce_predicate(&Expr::Column(4), &schema).is_ok());
+
+        // the types of invalid expressions are errors rather than panics
+        let expr = binary(Expr::Column(2), Operator::Plus, Expr::Column(4));
+        assert!(expr.get_type(&schema).is_err());
+        let missing = Expr::ColumnRef {
+            relation: None,
+            name: "f".to_string(),
+        };
+        assert!(missing.get_type(&schema).is_err());
+    }
+}
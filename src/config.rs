@@ -0,0 +1,200 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Session configuration
+//!
+//! Settings can be changed through the `SessionConfig` API or with the SQL statement
+//! `SET key = value`, and are listed with `SHOW key` or `SHOW ALL`. Keys are case-insensitive.
+
+use super::errors::*;
+
+/// Names of the settings, in the order that `SHOW ALL` lists them
+const KEYS: [&str; 6] = [
+    "batch_size",
+    "target_partitions",
+    "memory_limit",
+    "case_sensitive",
+    "csv_has_header",
+    "csv_delimiter",
+];
+
+/// Settings for an `ExecutionContext`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionConfig {
+    /// Maximum number of rows in each batch read from a file, or None to use the default of
+    /// each file format, which is 1024 rows for CSV and NDJSON files and 64K rows for Parquet
+    /// files. `SET batch_size = default` restores the defaults.
+    pub batch_size: Option<usize>,
+    /// Number of partitions that a query should be split into. Queries currently run in a
+    /// single thread, so this is recorded for data sources and future parallel execution.
+    pub target_partitions: usize,
+    /// Maximum number of bytes that a query can hold in memory when its results are collected
+    /// into a table, or None for no limit
+    pub memory_limit: Option<usize>,
+    /// Whether unquoted identifiers must match table and column names exactly, as quoted
+    /// identifiers always do
+    pub case_sensitive: bool,
+    /// Whether CSV files read by COPY have a header row, unless the statement says otherwise
+    pub csv_has_header: bool,
+    /// Field delimiter for CSV files read and written by COPY, unless the statement says
+    /// otherwise
+    pub csv_delimiter: u8,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            batch_size: None,
+            target_partitions: 1,
+            memory_limit: None,
+            case_sensitive: false,
+            csv_has_header: false,
+            csv_delimiter: b',',
+        }
+    }
+}
+
+impl SessionConfig {
+    /// Create a configuration with the default settings
+    pub fn new() -> Self {
+        SessionConfig::default()
+    }
+
+    /// Change a setting, parsing the value from a string
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key.to_lowercase().as_str() {
+            "batch_size" => {
+                self.batch_size = match value.to_lowercase().as_str() {
+                    "default" => None,
+                    _ => Some(parse_positive(key, value)?),
+                }
+            }
+            "target_partitions" => self.target_partitions = parse_positive(key, value)?,
+            "memory_limit" => {
+                self.memory_limit = match value.to_lowercase().as_str() {
+                    "unlimited" | "0" => None,
+                    _ => Some(parse_positive(key, value)?),
+                }
+            }
+            "case_sensitive" => self.case_sensitive = parse_bool(key, value)?,
+            "csv_has_header" => self.csv_has_header = parse_bool(key, value)?,
+            "csv_delimiter" => {
+                if value.len() != 1 || !value.is_ascii() {
+                    return Err(ExecutionError::General(format!(
+                        "Setting '{}' must be a single character, not '{}'",
+                        key, value
+                    )));
+                }
+                self.csv_delimiter = value.as_bytes()[0];
+            }
+            _ => {
+                return Err(ExecutionError::General(format!(
+                    "Unknown setting '{}'",
+                    key
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the value of a setting as a string
+    pub fn get(&self, key: &str) -> Result<String> {
+        match key.to_lowercase().as_str() {
+            "batch_size" => Ok(match self.batch_size {
+                Some(n) => n.to_string(),
+                None => "default".to_string(),
+            }),
+            "target_partitions" => Ok(self.target_partitions.to_string()),
+            "memory_limit" => Ok(match self.memory_limit {
+                Some(n) => n.to_string(),
+                None => "unlimited".to_string(),
+            }),
+            "case_sensitive" => Ok(self.case_sensitive.to_string()),
+            "csv_has_header" => Ok(self.csv_has_header.to_string()),
+            "csv_delimiter" => Ok((self.csv_delimiter as char).to_string()),
+            _ => Err(ExecutionError::General(format!(
+                "Unknown setting '{}'",
+                key
+            ))),
+        }
+    }
+
+    /// Get the name and value of every setting
+    pub fn entries(&self) -> Vec<(String, String)> {
+        KEYS.iter()
+            .map(|k| (k.to_string(), self.get(k).unwrap()))
+            .collect()
+    }
+}
+
+fn parse_positive(key: &str, value: &str) -> Result<usize> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(ExecutionError::General(format!(
+            "Setting '{}' must be a positive integer, not '{}'",
+            key, value
+        ))),
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(ExecutionError::General(format!(
+            "Setting '{}' must be true or false, not '{}'",
+            key, value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_get() {
+        let mut config = SessionConfig::new();
+        config.set("BATCH_SIZE", "100").unwrap();
+        config.set("target_partitions", "4").unwrap();
+        config.set("memory_limit", "1000000").unwrap();
+        config.set("case_sensitive", "TRUE").unwrap();
+        config.set("csv_delimiter", "|").unwrap();
+        assert_eq!(Some(100), config.batch_size);
+        assert_eq!(4, config.target_partitions);
+        assert_eq!(Some(1000000), config.memory_limit);
+        assert!(config.case_sensitive);
+        assert_eq!("|", config.get("csv_delimiter").unwrap());
+
+        config.set("memory_limit", "unlimited").unwrap();
+        assert_eq!("unlimited", config.get("memory_limit").unwrap());
+        config.set("batch_size", "Default").unwrap();
+        assert_eq!(None, config.batch_size);
+        assert_eq!("default", config.get("batch_size").unwrap());
+        assert_eq!(KEYS.len(), config.entries().len());
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let mut config = SessionConfig::new();
+        assert!(config.set("batch_size", "0").is_err());
+        assert!(config.set("batch_size", "ten").is_err());
+        assert!(config.set("target_partitions", "0").is_err());
+        assert!(config.set("case_sensitive", "yes").is_err());
+        assert!(config.set("csv_delimiter", "||").is_err());
+        assert!(config.set("foo", "1").is_err());
+        assert!(config.get("foo").is_err());
+        assert_eq!(SessionConfig::new(), config);
+    }
+}
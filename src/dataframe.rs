@@ -20,9 +20,11 @@ use std::str;
 
 use arrow::datatypes::*;
 
+use super::analyzer::{relation_name, resolve_column};
 use super::errors::*;
 use super::exec::*;
 use super::logical::*;
+use super::planbuilder::LogicalPlanBuilder;
 
 /// DataFrame is an abstraction of a logical plan and a schema
 pub trait DataFrame {
@@ -32,7 +34,9 @@ pub trait DataFrame {
     /// Selection
     fn filter(&self, expr: Expr) -> Result<Rc<DataFrame>>;
 
-    /// Return an expression representing the specified column
+    /// Return an expression that refers to the specified column by name. The reference is
+    /// resolved against the input of the plan that it is used in, so it can be used with other
+    /// DataFrames that have a column with this name.
     fn col(&self, column_name: &str) -> Result<Expr>;
 
     fn schema(&self) -> &Rc<Schema>;
@@ -41,6 +45,9 @@ pub trait DataFrame {
 
     /// show N rows (useful for debugging)
     fn show(&self, count: usize);
+
+    /// Explain the plan for this DataFrame, optionally executing it to collect metrics
+    fn explain(&self, analyze: bool) -> Result<Rc<DataFrame>>;
 }
 
 pub struct DF {
@@ -56,20 +63,20 @@ impl DF {
     pub fn with_plan(&self, plan: Rc<LogicalPlan>) -> Self {
         DF::new(self.ctx.clone(), plan)
     }
+
+    /// Whether column names are matched case-sensitively in the context's session
+    fn case_sensitive(&self) -> bool {
+        self.ctx.session_config().case_sensitive
+    }
 }
 
 impl DataFrame for DF {
     fn select(&self, expr: Vec<Expr>) -> Result<Rc<DataFrame>> {
-        use sqlplanner::exprlist_to_fields;
-        let projection_schema = Rc::new(Schema::new(exprlist_to_fields(&expr, self.schema())));
-
-        let plan = LogicalPlan::Projection {
-            expr: expr,
-            input: self.plan.clone(),
-            schema: projection_schema,
-        };
-
-        Ok(Rc::new(self.with_plan(Rc::new(plan))))
+        let plan = LogicalPlanBuilder::new(self.plan.clone())
+            .with_case_sensitive(self.case_sensitive())
+            .project(expr)?
+            .build()?;
+        Ok(Rc::new(self.with_plan(plan)))
     }
 
     //    fn sort(&self, expr: Vec<Expr>) -> Result<Rc<DataFrame>> {
@@ -83,18 +90,28 @@ impl DataFrame for DF {
     //    }
 
     fn filter(&self, expr: Expr) -> Result<Rc<DataFrame>> {
-        let plan = LogicalPlan::Selection {
-            expr: expr,
-            input: self.plan.clone(),
-        };
-
-        Ok(Rc::new(self.with_plan(Rc::new(plan))))
+        let plan = LogicalPlanBuilder::new(self.plan.clone())
+            .with_case_sensitive(self.case_sensitive())
+            .filter(expr)?
+            .build()?;
+        Ok(Rc::new(self.with_plan(plan)))
     }
 
     fn col(&self, column_name: &str) -> Result<Expr> {
-        match self.plan.schema().column_with_name(column_name) {
-            Some((i, _)) => Ok(Expr::Column(i)),
-            _ => Err(ExecutionError::InvalidColumn(column_name.to_string())),
+        // check that the column exists so that misspelled names are reported here
+        let relation = relation_name(&self.plan);
+        match resolve_column(
+            self.schema(),
+            relation,
+            None,
+            column_name,
+            self.case_sensitive(),
+        ) {
+            Ok(_) => Ok(Expr::ColumnRef {
+                relation: None,
+                name: column_name.to_string(),
+            }),
+            Err(e) => Err(ExecutionError::InvalidColumn(e)),
         }
     }
 
@@ -109,4 +126,8 @@ impl DataFrame for DF {
     fn show(&self, count: usize) {
         self.ctx.show(self, count).unwrap();
     }
+
+    fn explain(&self, analyze: bool) -> Result<Rc<DataFrame>> {
+        self.ctx.explain(&self.plan, analyze)
+    }
 }
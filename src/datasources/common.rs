@@ -15,13 +15,26 @@
 //! Defines data sources supported by DataFusion (currently CSV and Apache Parquet)
 
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
 use std::rc::Rc;
 use std::str;
 
 //use arrow::array::*;
+use arrow::array::ListArray;
+use arrow::bitmap::Bitmap;
 use arrow::datatypes::*;
+use arrow::list::List;
+
+use flate2::read::GzDecoder;
 
 use super::super::errors::*;
+use super::super::logical::Expr;
+use super::super::sqlplanner::collect_expr;
 use super::super::types::*;
 
 pub trait RecordBatch {
@@ -72,6 +85,15 @@ pub fn get_value(column: &Array, index: usize) -> ScalarValue {
     v
 }
 
+/// Get one value from a column, which is `ScalarValue::Null` if the value is null
+pub fn get_column_value(column: &Value, index: usize) -> ScalarValue {
+    match column {
+        Value::Scalar(ref v) => v.as_ref().clone(),
+        Value::Column(ref v) if v.is_null(index) => ScalarValue::Null,
+        Value::Column(ref v) => get_value(v, index),
+    }
+}
+
 //TODO: remove pub from fields
 pub struct DefaultRecordBatch {
     pub schema: Rc<Schema>,
@@ -104,6 +126,128 @@ impl RecordBatch for DefaultRecordBatch {
 pub trait DataSource {
     fn schema(&self) -> &Rc<Schema>;
     fn next(&mut self) -> Option<Result<Rc<RecordBatch>>>;
+
+    /// Receive the predicates that are applied to the rows of this data source after they are
+    /// read, so that a source that can tell when data cannot match them, for example from
+    /// statistics, may skip that data. Sources do not have to remove any rows.
+    ///
+    /// Column references in the predicates are indices into `schema()`, which is the projected
+    /// schema of the source rather than the schema of its table.
+    fn push_down_filters(&mut self, _filters: &[Expr]) {}
+
+    /// Get the statistics that are known without reading the data, such as the row count
+    /// from the metadata of a file
+    fn statistics(&self) -> Statistics {
+        Statistics::unknown(self.schema().columns().len())
+    }
+}
+
+/// Statistics about the rows of a table or data source, where values that are not known are
+/// `None`. The optimizer does not use them yet: the logical plan has no joins, so there are no
+/// join orders or build sides to choose from cardinality estimates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statistics {
+    pub row_count: Option<usize>,
+    /// Size of the data in bytes, as it is stored
+    pub byte_size: Option<usize>,
+    /// Statistics for each column, in the order of the schema
+    pub columns: Vec<ColumnStatistics>,
+}
+
+/// Statistics about the values of one column
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ColumnStatistics {
+    pub min: Option<ScalarValue>,
+    pub max: Option<ScalarValue>,
+    pub null_count: Option<usize>,
+}
+
+impl Statistics {
+    /// Create statistics for a schema with the specified number of columns, where nothing is
+    /// known
+    pub fn unknown(column_count: usize) -> Self {
+        Statistics {
+            row_count: None,
+            byte_size: None,
+            columns: vec![ColumnStatistics::default(); column_count],
+        }
+    }
+}
+
+/// Computes the statistics of a table by reading all of its rows
+pub struct StatisticsCollector {
+    row_count: usize,
+    byte_size: usize,
+    null_counts: Vec<usize>,
+    min: Vec<Option<ScalarValue>>,
+    max: Vec<Option<ScalarValue>>,
+}
+
+impl StatisticsCollector {
+    pub fn new(schema: &Schema) -> Self {
+        let n = schema.columns().len();
+        StatisticsCollector {
+            row_count: 0,
+            byte_size: 0,
+            null_counts: vec![0; n],
+            min: vec![None; n],
+            max: vec![None; n],
+        }
+    }
+
+    /// Update the statistics with the rows of a batch
+    pub fn update(&mut self, batch: &RecordBatch) {
+        let rows = batch.num_rows();
+        self.row_count += rows;
+        for (i, column) in batch.columns().iter().enumerate() {
+            if let Value::Column(ref array) = column {
+                self.byte_size += array.memory_size();
+            }
+            for row in 0..rows {
+                let v = get_column_value(column, row);
+                if v == ScalarValue::Null {
+                    self.null_counts[i] += 1;
+                    continue;
+                }
+                // values that cannot be ordered, such as structs, have no bounds
+                if v.compare(&v).is_none() {
+                    continue;
+                }
+                if self.min[i]
+                    .as_ref()
+                    .map_or(true, |m| v.compare(m) == Some(Ordering::Less))
+                {
+                    self.min[i] = Some(v.clone());
+                }
+                if self.max[i]
+                    .as_ref()
+                    .map_or(true, |m| v.compare(m) == Some(Ordering::Greater))
+                {
+                    self.max[i] = Some(v);
+                }
+            }
+        }
+    }
+
+    /// Get the statistics of the rows, using the size of the stored data if it is known
+    /// rather than the size of the data in memory
+    pub fn finish(self, byte_size: Option<usize>) -> Statistics {
+        let columns = self
+            .null_counts
+            .iter()
+            .zip(self.min.into_iter().zip(self.max.into_iter()))
+            .map(|(null_count, (min, max))| ColumnStatistics {
+                min,
+                max,
+                null_count: Some(*null_count),
+            })
+            .collect();
+        Statistics {
+            row_count: Some(self.row_count),
+            byte_size: Some(byte_size.unwrap_or(self.byte_size)),
+            columns,
+        }
+    }
 }
 
 pub struct DataSourceIterator {
@@ -123,3 +267,474 @@ impl Iterator for DataSourceIterator {
         self.ds.borrow_mut().next()
     }
 }
+
+/// Data source that reads each of a list of data sources in turn, such as the files in a
+/// directory
+pub struct ChainedDataSource {
+    schema: Rc<Schema>,
+    sources: Vec<Rc<RefCell<DataSource>>>,
+    index: usize,
+}
+
+impl ChainedDataSource {
+    pub fn new(schema: Rc<Schema>, sources: Vec<Rc<RefCell<DataSource>>>) -> Self {
+        ChainedDataSource {
+            schema,
+            sources,
+            index: 0,
+        }
+    }
+}
+
+impl DataSource for ChainedDataSource {
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn next(&mut self) -> Option<Result<Rc<RecordBatch>>> {
+        while self.index < self.sources.len() {
+            match self.sources[self.index].borrow_mut().next() {
+                Some(batch) => return Some(batch),
+                None => self.index += 1,
+            }
+        }
+        None
+    }
+
+    fn push_down_filters(&mut self, filters: &[Expr]) {
+        for source in &self.sources {
+            source.borrow_mut().push_down_filters(filters);
+        }
+    }
+}
+
+/// Data source that adds columns with the same value in every row to the batches of another
+/// data source, such as the partition columns of a file in a partitioned directory
+pub struct PartitionedDataSource {
+    schema: Rc<Schema>,
+    input: Rc<RefCell<DataSource>>,
+    values: Vec<ScalarValue>,
+}
+
+impl PartitionedDataSource {
+    /// Create a data source with the input columns followed by one column for each value
+    pub fn new(
+        schema: Rc<Schema>,
+        input: Rc<RefCell<DataSource>>,
+        values: Vec<ScalarValue>,
+    ) -> Self {
+        PartitionedDataSource {
+            schema,
+            input,
+            values,
+        }
+    }
+}
+
+impl DataSource for PartitionedDataSource {
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn next(&mut self) -> Option<Result<Rc<RecordBatch>>> {
+        let batch = match self.input.borrow_mut().next() {
+            Some(Ok(batch)) => batch,
+            other => return other,
+        };
+        let n = batch.num_rows();
+        let mut data = batch.columns().clone();
+        for v in &self.values {
+            match repeat_value(v, n) {
+                Ok(array) => data.push(Value::Column(Rc::new(array))),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok(Rc::new(DefaultRecordBatch {
+            schema: self.schema.clone(),
+            data,
+            row_count: n,
+        })))
+    }
+
+    fn push_down_filters(&mut self, filters: &[Expr]) {
+        // the input only has the columns that come before the partition columns
+        let input_column_count = self.input.borrow().schema().columns().len();
+        let input_filters: Vec<Expr> = filters
+            .iter()
+            .filter(|f| {
+                let mut columns = HashSet::new();
+                collect_expr(f, &mut columns);
+                columns.iter().all(|i| *i < input_column_count)
+            })
+            .cloned()
+            .collect();
+        self.input.borrow_mut().push_down_filters(&input_filters);
+    }
+}
+
+/// Create an array containing a value `n` times
+pub fn repeat_value(v: &ScalarValue, n: usize) -> Result<Array> {
+    match *v {
+        ScalarValue::Boolean(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::Float32(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::Float64(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::Int8(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::Int16(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::Int32(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::Int64(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::UInt8(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::UInt16(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::UInt32(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::UInt64(x) => Ok(Array::from(vec![x; n])),
+        ScalarValue::Utf8(ref x) => Ok(Array::from(vec![x.as_ref().clone(); n])),
+        _ => Err(ExecutionError::General(format!(
+            "Cannot create a column of {:?} values",
+            v
+        ))),
+    }
+}
+
+/// Create an array of the specified type from a list of values, where `ScalarValue::Null`
+/// values become nulls in the array
+pub fn scalars_to_array(values: &[ScalarValue], data_type: &DataType) -> Result<Array> {
+    let mut bitmap = Bitmap::new(values.len());
+    let mut null_count = 0;
+    for (i, v) in values.iter().enumerate() {
+        if let ScalarValue::Null = v {
+            bitmap.clear(i);
+            null_count += 1;
+        }
+    }
+    macro_rules! build {
+        ($ACCESSOR:ident, $DEFAULT:expr) => {
+            ArrayData::from(
+                values
+                    .iter()
+                    .map(|v| match v {
+                        ScalarValue::Null => Ok($DEFAULT),
+                        _ => v.$ACCESSOR(),
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        };
+    }
+    let data = match *data_type {
+        DataType::Boolean => build!(get_bool, false),
+        DataType::Float32 => build!(get_f32, 0.0),
+        DataType::Float64 => build!(get_f64, 0.0),
+        DataType::Int8 => build!(get_i8, 0),
+        DataType::Int16 => build!(get_i16, 0),
+        DataType::Int32 => build!(get_i32, 0),
+        DataType::Int64 => build!(get_i64, 0),
+        DataType::UInt8 => build!(get_u8, 0),
+        DataType::UInt16 => build!(get_u16, 0),
+        DataType::UInt32 => build!(get_u32, 0),
+        DataType::UInt64 => build!(get_u64, 0),
+        DataType::Utf8 => ArrayData::Utf8(ListArray::from(List::from(
+            values
+                .iter()
+                .map(|v| match v {
+                    ScalarValue::Null => Ok(String::new()),
+                    _ => v.get_string().map(|s| s.clone()),
+                })
+                .collect::<Result<Vec<String>>>()?,
+        ))),
+        _ => {
+            return Err(ExecutionError::General(format!(
+                "Cannot create a column of type {:?}",
+                data_type
+            )))
+        }
+    };
+    if null_count == 0 {
+        Ok(Array::new(values.len(), data))
+    } else {
+        Ok(Array::with_nulls(values.len(), data, null_count, bitmap))
+    }
+}
+
+/// Create an array from rows of other columns. Each row is a pair of the index of a column in
+/// `columns`, which all have the type `data_type`, and the index of the row in that column.
+/// Null values are kept in the validity bitmap of the new array.
+pub fn take_rows(
+    columns: &[Value],
+    rows: &[(usize, usize)],
+    data_type: &DataType,
+) -> Result<Array> {
+    let mut bitmap = Bitmap::new(rows.len());
+    let mut null_count = 0;
+    for (i, &(c, row)) in rows.iter().enumerate() {
+        let is_null = match columns[c] {
+            Value::Column(ref v) => v.is_null(row),
+            Value::Scalar(ref v) => *v.as_ref() == ScalarValue::Null,
+        };
+        if is_null {
+            bitmap.clear(i);
+            null_count += 1;
+        }
+    }
+    let wrong_type = |data: &ArrayData| {
+        ExecutionError::General(format!(
+            "Cannot take a row of type {} as {:?}",
+            data, data_type
+        ))
+    };
+    macro_rules! take {
+        ($VARIANT:ident, $ACCESSOR:ident, $DEFAULT:expr) => {
+            ArrayData::from(
+                rows.iter()
+                    .map(|&(c, row)| match columns[c] {
+                        Value::Column(ref v) => match v.data() {
+                            ArrayData::$VARIANT(ref v) => Ok(*v.get(row)),
+                            other => Err(wrong_type(other)),
+                        },
+                        Value::Scalar(ref v) => match v.as_ref() {
+                            ScalarValue::Null => Ok($DEFAULT),
+                            v => v.$ACCESSOR(),
+                        },
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        };
+    }
+    let data = match *data_type {
+        DataType::Boolean => take!(Boolean, get_bool, false),
+        DataType::Float32 => take!(Float32, get_f32, 0.0),
+        DataType::Float64 => take!(Float64, get_f64, 0.0),
+        DataType::Int8 => take!(Int8, get_i8, 0),
+        DataType::Int16 => take!(Int16, get_i16, 0),
+        DataType::Int32 => take!(Int32, get_i32, 0),
+        DataType::Int64 => take!(Int64, get_i64, 0),
+        DataType::UInt8 => take!(UInt8, get_u8, 0),
+        DataType::UInt16 => take!(UInt16, get_u16, 0),
+        DataType::UInt32 => take!(UInt32, get_u32, 0),
+        DataType::UInt64 => take!(UInt64, get_u64, 0),
+        DataType::Utf8 => ArrayData::Utf8(ListArray::from(List::from(
+            rows.iter()
+                .map(|&(c, row)| match columns[c] {
+                    Value::Column(ref v) => match v.data() {
+                        ArrayData::Utf8(ref v) => {
+                            Ok(String::from_utf8_lossy(v.get(row)).into_owned())
+                        }
+                        other => Err(wrong_type(other)),
+                    },
+                    Value::Scalar(ref v) => match v.as_ref() {
+                        ScalarValue::Null => Ok(String::new()),
+                        v => v.get_string().map(|s| s.clone()),
+                    },
+                })
+                .collect::<Result<Vec<String>>>()?,
+        ))),
+        DataType::Struct(ref fields) => {
+            // take the rows of each field from the fields of the columns
+            let mut arrays = Vec::with_capacity(fields.len());
+            for (i, field) in fields.iter().enumerate() {
+                let field_columns = columns
+                    .iter()
+                    .map(|column| match column {
+                        Value::Column(ref v) => match v.data() {
+                            ArrayData::Struct(ref v) => Ok(Value::Column(v[i].clone())),
+                            other => Err(wrong_type(other)),
+                        },
+                        Value::Scalar(ref v) => match v.as_ref() {
+                            ScalarValue::Null => Ok(Value::Scalar(Rc::new(ScalarValue::Null))),
+                            v => Ok(Value::Scalar(Rc::new(v.get_struct()?[i].clone()))),
+                        },
+                    })
+                    .collect::<Result<Vec<Value>>>()?;
+                arrays.push(Rc::new(take_rows(&field_columns, rows, field.data_type())?));
+            }
+            ArrayData::Struct(arrays)
+        }
+        _ => {
+            return Err(ExecutionError::General(format!(
+                "Cannot create a column of type {:?}",
+                data_type
+            )))
+        }
+    };
+    if null_count == 0 {
+        Ok(Array::new(rows.len(), data))
+    } else {
+        Ok(Array::with_nulls(rows.len(), data, null_count, bitmap))
+    }
+}
+
+/// Parse the value of a partition column from a directory name
+pub fn parse_partition_value(value: &str, data_type: &DataType) -> Result<ScalarValue> {
+    macro_rules! parse {
+        ($TY:ty, $SV:ident) => {
+            value.parse::<$TY>().map(ScalarValue::$SV).map_err(|_| {
+                ExecutionError::General(format!(
+                    "Cannot parse partition value '{}' as {:?}",
+                    value, data_type
+                ))
+            })
+        };
+    }
+    match *data_type {
+        DataType::Boolean => parse!(bool, Boolean),
+        DataType::Float32 => parse!(f32, Float32),
+        DataType::Float64 => parse!(f64, Float64),
+        DataType::Int8 => parse!(i8, Int8),
+        DataType::Int16 => parse!(i16, Int16),
+        DataType::Int32 => parse!(i32, Int32),
+        DataType::Int64 => parse!(i64, Int64),
+        DataType::UInt8 => parse!(u8, UInt8),
+        DataType::UInt16 => parse!(u16, UInt16),
+        DataType::UInt32 => parse!(u32, UInt32),
+        DataType::UInt64 => parse!(u64, UInt64),
+        DataType::Utf8 => Ok(ScalarValue::Utf8(Rc::new(value.to_string()))),
+        _ => Err(ExecutionError::General(format!(
+            "Partition columns cannot have type {:?}",
+            data_type
+        ))),
+    }
+}
+
+/// Compression of the files of an external table
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileCompression {
+    Uncompressed,
+    Gzip,
+}
+
+/// Options for reading the files of an external table
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileOptions {
+    /// Field delimiter for CSV files
+    pub delimiter: u8,
+    /// Compression of CSV and NDJSON files
+    pub compression: FileCompression,
+    /// Number of columns at the end of the table schema whose values are taken from the names
+    /// of directories in the form `column=value`, rather than read from the files
+    pub partition_count: usize,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        FileOptions {
+            delimiter: b',',
+            compression: FileCompression::Uncompressed,
+            partition_count: 0,
+        }
+    }
+}
+
+impl FileOptions {
+    /// Get the columns of a table schema that are read from the files
+    pub fn file_schema(&self, schema: &Schema) -> Rc<Schema> {
+        let n = schema.columns().len() - self.partition_count;
+        Rc::new(Schema::new(schema.columns()[..n].to_vec()))
+    }
+
+    /// Get the columns of a table schema whose values are taken from directory names
+    pub fn partition_columns<'a>(&self, schema: &'a Schema) -> &'a [Field] {
+        let n = schema.columns().len() - self.partition_count;
+        &schema.columns()[n..]
+    }
+
+    /// Create a reader for a file, which decompresses the file if necessary
+    pub fn open_reader(&self, file: File) -> Box<Read> {
+        match self.compression {
+            FileCompression::Uncompressed => Box::new(BufReader::new(file)),
+            FileCompression::Gzip => Box::new(BufReader::new(GzDecoder::new(file))),
+        }
+    }
+}
+
+/// Writes record batches to a file
+pub trait RecordBatchWriter {
+    /// Write the rows of a batch
+    fn write(&mut self, batch: &RecordBatch) -> Result<()>;
+
+    /// Flush any buffered output
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// List the files at a location, which is either a single file or a directory of files. The
+/// files in a directory are sorted by name, and hidden files (with names starting with `.` or
+/// `_`) are skipped.
+pub fn list_files(location: &str) -> Result<Vec<String>> {
+    let path = Path::new(location);
+    if !path.is_dir() {
+        return Ok(vec![location.to_string()]);
+    }
+    let mut files = vec![];
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        let hidden = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.starts_with(".") || name.starts_with("_"),
+            None => true,
+        };
+        if path.is_file() && !hidden {
+            if let Some(name) = path.to_str() {
+                files.push(name.to_string());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// List the files in a directory that is partitioned by the named columns, along with the
+/// partition values of each file. Each level of subdirectories is named `column=value` for the
+/// next partition column, and other files and directories at these levels are skipped.
+pub fn list_partitioned_files(
+    location: &str,
+    columns: &[String],
+) -> Result<Vec<(String, Vec<String>)>> {
+    if columns.len() == 0 {
+        return Ok(list_files(location)?
+            .into_iter()
+            .map(|f| (f, vec![]))
+            .collect());
+    }
+    let mut dirs = vec![];
+    for entry in fs::read_dir(location)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.to_string());
+        if let (Some(name), Some(dir)) = (name, path.to_str()) {
+            let mut parts = name.splitn(2, '=');
+            if let (Some(column), Some(value)) = (parts.next(), parts.next()) {
+                if column.eq_ignore_ascii_case(&columns[0]) {
+                    dirs.push((dir.to_string(), value.to_string()));
+                }
+            }
+        }
+    }
+    dirs.sort();
+
+    let mut files = vec![];
+    for (dir, value) in dirs {
+        for (file, mut values) in list_partitioned_files(&dir, &columns[1..])? {
+            values.insert(0, value.clone());
+            files.push((file, values));
+        }
+    }
+    Ok(files)
+}
+
+/// Choose the name of a new file to add to a directory, in the form `part-00000.csv`
+pub fn next_file_name(directory: &str, extension: &str) -> Result<String> {
+    let mut n = list_files(directory)?.len();
+    loop {
+        let path = Path::new(directory).join(format!("part-{:05}.{}", n, extension));
+        if !path.exists() {
+            return match path.to_str() {
+                Some(name) => Ok(name.to_string()),
+                None => Err(ExecutionError::General(format!(
+                    "Invalid file name in directory {}",
+                    directory
+                ))),
+            };
+        }
+        n += 1;
+    }
+}
@@ -15,7 +15,7 @@
 //! CSV Support
 
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::rc::Rc;
 
 use arrow::array::ListArray;
@@ -28,13 +28,16 @@ use csv;
 use csv::{StringRecord, StringRecordsIntoIter};
 
 use super::super::errors::*;
+use super::super::logical::project_schema;
 use super::super::types::*;
 use super::common::*;
 
 pub struct CsvFile {
     schema: Rc<Schema>,
-    projection: Option<Vec<usize>>,
-    record_iter: StringRecordsIntoIter<BufReader<File>>,
+    /// Indices of the columns that are read, in the order that they appear in each batch
+    projection: Vec<usize>,
+    projected_schema: Rc<Schema>,
+    record_iter: StringRecordsIntoIter<Box<Read>>,
     batch_size: usize,
 }
 
@@ -44,13 +47,39 @@ impl CsvFile {
         schema: Rc<Schema>,
         has_headers: bool,
         projection: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        CsvFile::open_with_delimiter(file, schema, has_headers, b',', projection)
+    }
+
+    /// Open a CSV file with fields that are separated by the specified delimiter
+    pub fn open_with_delimiter(
+        file: File,
+        schema: Rc<Schema>,
+        has_headers: bool,
+        delimiter: u8,
+        projection: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        let reader: Box<Read> = Box::new(BufReader::new(file));
+        CsvFile::from_reader(reader, schema, has_headers, delimiter, projection)
+    }
+
+    /// Read CSV data from a reader, such as a reader that decompresses a file
+    pub fn from_reader(
+        reader: Box<Read>,
+        schema: Rc<Schema>,
+        has_headers: bool,
+        delimiter: u8,
+        projection: Option<Vec<usize>>,
     ) -> Result<Self> {
         let csv_reader = csv::ReaderBuilder::new()
             .has_headers(has_headers)
-            .from_reader(BufReader::new(file));
+            .delimiter(delimiter)
+            .from_reader(reader);
 
         let record_iter = csv_reader.into_records();
+        let projection = projection.unwrap_or_else(|| (0..schema.columns().len()).collect());
         Ok(CsvFile {
+            projected_schema: Rc::new(project_schema(&schema, &projection)),
             schema: schema.clone(),
             projection,
             record_iter,
@@ -63,6 +92,81 @@ impl CsvFile {
     }
 }
 
+/// Infer the schema of CSV data from its first `max_records` records. Column names are taken
+/// from the header row if there is one, and are otherwise `column_1`, `column_2` and so on.
+/// Each column has the first of the types Boolean, Int64, Float64 and Utf8 that can represent
+/// all of its non-empty values, and all columns are nullable.
+pub fn infer_schema(
+    reader: Box<Read>,
+    has_headers: bool,
+    delimiter: u8,
+    max_records: usize,
+) -> Result<Schema> {
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .has_headers(has_headers)
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(reader);
+
+    let mut names: Vec<String> = if has_headers {
+        csv_reader
+            .headers()
+            .map_err(|e| ExecutionError::General(format!("Error reading CSV: {}", e)))?
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    } else {
+        vec![]
+    };
+
+    // for each column, whether all of the values seen so far can be parsed as each type
+    let mut is_bool: Vec<bool> = vec![true; names.len()];
+    let mut is_int: Vec<bool> = vec![true; names.len()];
+    let mut is_float: Vec<bool> = vec![true; names.len()];
+    let mut has_value: Vec<bool> = vec![false; names.len()];
+
+    for record in csv_reader.records().take(max_records) {
+        let record =
+            record.map_err(|e| ExecutionError::General(format!("Error reading CSV: {}", e)))?;
+        while names.len() < record.len() {
+            names.push(format!("column_{}", names.len() + 1));
+            is_bool.push(true);
+            is_int.push(true);
+            is_float.push(true);
+            has_value.push(false);
+        }
+        for (i, value) in record.iter().enumerate() {
+            if value.len() == 0 {
+                continue;
+            }
+            has_value[i] = true;
+            is_bool[i] = is_bool[i] && value.parse::<bool>().is_ok();
+            is_int[i] = is_int[i] && value.parse::<i64>().is_ok();
+            is_float[i] = is_float[i] && value.parse::<f64>().is_ok();
+        }
+    }
+
+    let fields = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let data_type = if !has_value[i] {
+                DataType::Utf8
+            } else if is_bool[i] {
+                DataType::Boolean
+            } else if is_int[i] {
+                DataType::Int64
+            } else if is_float[i] {
+                DataType::Float64
+            } else {
+                DataType::Utf8
+            };
+            Field::new(name, data_type, true)
+        })
+        .collect();
+    Ok(Schema::new(fields))
+}
+
 /// Built an Arrow array from one column in a batch of CSV records
 macro_rules! collect_column {
     ($ROWS:expr, $COL_INDEX:expr, $TY:ty, $LEN:expr, $DEFAULT_VALUE:expr) => {{
@@ -119,80 +223,64 @@ impl DataSource for CsvFile {
 
         //println!("Loaded {} rows", rows.len());
 
-        let column_with_index = self.schema.columns().iter().enumerate();
-
-        let projection = match self.projection {
-            Some(ref v) => v.clone(),
-            None => self
-                .schema
-                .columns()
-                .iter()
-                .enumerate()
-                .map(|(i, _)| i)
-                .collect(),
-        };
-
-        let columns: Vec<Value> = column_with_index
-            .map(|(i, c)| {
-                if projection.contains(&i) {
-                    match c.data_type() {
-                        DataType::Boolean => collect_column!(rows, i, bool, rows.len(), false),
-                        DataType::Int8 => collect_column!(rows, i, i8, rows.len(), 0),
-                        DataType::Int16 => collect_column!(rows, i, i16, rows.len(), 0),
-                        DataType::Int32 => collect_column!(rows, i, i32, rows.len(), 0),
-                        DataType::Int64 => collect_column!(rows, i, i64, rows.len(), 0),
-                        DataType::UInt8 => collect_column!(rows, i, u8, rows.len(), 0),
-                        DataType::UInt16 => collect_column!(rows, i, u16, rows.len(), 0),
-                        DataType::UInt32 => collect_column!(rows, i, u32, rows.len(), 0),
-                        DataType::UInt64 => collect_column!(rows, i, u64, rows.len(), 0),
-                        DataType::Float16 => collect_column!(rows, i, f32, rows.len(), 0_f32),
-                        DataType::Float32 => collect_column!(rows, i, f32, rows.len(), 0_f32),
-                        DataType::Float64 => collect_column!(rows, i, f64, rows.len(), 0_f64),
-                        DataType::Utf8 => {
-                            let mut builder: ListBuilder<u8> =
-                                ListBuilder::with_capacity(rows.len());
-                            let mut bitmap = Bitmap::new(rows.len());
-                            let mut null_count = 0;
-                            for j in 0..rows.len() {
-                                let row = &rows[j];
-                                match row.get(i) {
-                                    Some(s) => {
-                                        builder.push(s.as_bytes());
-                                    }
-                                    _ => {
-                                        //NOTE this never happens because the csv crate treats missing strings as empty strings
-                                        bitmap.clear(j);
-                                        null_count += 1;
-                                    }
+        let schema = &self.schema;
+        let columns: Vec<Value> = self
+            .projection
+            .iter()
+            .map(|&i| {
+                let c = schema.column(i);
+                match c.data_type() {
+                    DataType::Boolean => collect_column!(rows, i, bool, rows.len(), false),
+                    DataType::Int8 => collect_column!(rows, i, i8, rows.len(), 0),
+                    DataType::Int16 => collect_column!(rows, i, i16, rows.len(), 0),
+                    DataType::Int32 => collect_column!(rows, i, i32, rows.len(), 0),
+                    DataType::Int64 => collect_column!(rows, i, i64, rows.len(), 0),
+                    DataType::UInt8 => collect_column!(rows, i, u8, rows.len(), 0),
+                    DataType::UInt16 => collect_column!(rows, i, u16, rows.len(), 0),
+                    DataType::UInt32 => collect_column!(rows, i, u32, rows.len(), 0),
+                    DataType::UInt64 => collect_column!(rows, i, u64, rows.len(), 0),
+                    DataType::Float16 => collect_column!(rows, i, f32, rows.len(), 0_f32),
+                    DataType::Float32 => collect_column!(rows, i, f32, rows.len(), 0_f32),
+                    DataType::Float64 => collect_column!(rows, i, f64, rows.len(), 0_f64),
+                    DataType::Utf8 => {
+                        let mut builder: ListBuilder<u8> = ListBuilder::with_capacity(rows.len());
+                        let mut bitmap = Bitmap::new(rows.len());
+                        let mut null_count = 0;
+                        for j in 0..rows.len() {
+                            let row = &rows[j];
+                            match row.get(i) {
+                                Some(s) => {
+                                    builder.push(s.as_bytes());
+                                }
+                                _ => {
+                                    //NOTE this never happens because the csv crate treats missing strings as empty strings
+                                    bitmap.clear(j);
+                                    null_count += 1;
                                 }
                             }
-                            let buffer = builder.finish();
-                            Value::Column(Rc::new(Array::with_nulls(
-                                rows.len(),
-                                ArrayData::Utf8(ListArray::from(buffer)),
-                                null_count,
-                                bitmap,
-                            )))
                         }
-                        _ => unimplemented!("CSV does not support data type {:?}", c.data_type()),
+                        let buffer = builder.finish();
+                        Value::Column(Rc::new(Array::with_nulls(
+                            rows.len(),
+                            ArrayData::Utf8(ListArray::from(buffer)),
+                            null_count,
+                            bitmap,
+                        )))
                     }
-                } else {
-                    // not in the projection
-                    //println!("Not loading column {} at index {}", c.name(), i);
-                    Value::Scalar(Rc::new(ScalarValue::Null))
+                    _ => unimplemented!("CSV does not support data type {:?}", c.data_type()),
                 }
             })
             .collect();
 
         Some(Ok(Rc::new(DefaultRecordBatch {
-            schema: self.schema.clone(),
+            schema: self.projected_schema.clone(),
             data: columns,
             row_count: rows.len(),
         })))
     }
 
     fn schema(&self) -> &Rc<Schema> {
-        &self.schema
+        &self.projected_schema
     }
 }
 
@@ -255,6 +343,49 @@ impl CsvWriter {
     }
 }
 
+/// Writes record batches to a CSV file, quoting values where necessary so that the file can be
+/// read by `CsvFile`. Null values are written as empty fields.
+pub struct CsvBatchWriter {
+    w: csv::Writer<File>,
+}
+
+impl CsvBatchWriter {
+    /// Create a writer, optionally writing a header row containing the column names
+    pub fn new(file: File, schema: &Schema, has_header: bool, delimiter: u8) -> Result<Self> {
+        let mut w = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(file);
+        if has_header {
+            w.write_record(schema.columns().iter().map(|c| c.name()))
+                .map_err(|e| ExecutionError::General(format!("Error writing CSV: {}", e)))?;
+        }
+        Ok(CsvBatchWriter { w })
+    }
+}
+
+impl RecordBatchWriter for CsvBatchWriter {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        for i in 0..batch.num_rows() {
+            let row: Vec<String> = batch
+                .columns()
+                .iter()
+                .map(|c| match get_column_value(c, i) {
+                    ScalarValue::Null => String::new(),
+                    v => v.to_string(),
+                })
+                .collect();
+            self.w
+                .write_record(&row)
+                .map_err(|e| ExecutionError::General(format!("Error writing CSV: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.w.flush()?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -277,6 +408,27 @@ mod tests {
         assert_eq!(3, batch.num_columns());
     }
 
+    #[test]
+    fn test_projection() {
+        let schema = Schema::new(vec![
+            Field::new("city", DataType::Utf8, false),
+            Field::new("lat", DataType::Float64, false),
+            Field::new("lng", DataType::Float64, false),
+        ]);
+
+        let file = File::open("test/data/uk_cities.csv").unwrap();
+
+        let mut csv = CsvFile::open(file, Rc::new(schema), false, Some(vec![2, 0])).unwrap();
+        let batch = csv.next().unwrap().unwrap();
+        assert_eq!(2, batch.num_columns());
+        assert_eq!("lng", batch.schema().column(0).name());
+        assert_eq!("city", batch.schema().column(1).name());
+        assert_eq!(
+            ScalarValue::Utf8(Rc::new("Elgin, Scotland, the UK".to_string())),
+            get_column_value(batch.column(1), 0)
+        );
+    }
+
     #[test]
     fn test_nulls() {
         let schema = Schema::new(vec![
@@ -0,0 +1,82 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory data source
+
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::super::errors::*;
+use super::common::*;
+
+/// Data source that returns record batches that are already held in memory
+pub struct MemoryDataSource {
+    schema: Rc<Schema>,
+    batches: Rc<Vec<Rc<RecordBatch>>>,
+    index: usize,
+}
+
+impl MemoryDataSource {
+    pub fn new(schema: Rc<Schema>, batches: Rc<Vec<Rc<RecordBatch>>>) -> Self {
+        MemoryDataSource {
+            schema,
+            batches,
+            index: 0,
+        }
+    }
+}
+
+impl DataSource for MemoryDataSource {
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn next(&mut self) -> Option<Result<Rc<RecordBatch>>> {
+        if self.index < self.batches.len() {
+            self.index += 1;
+            Some(Ok(self.batches[self.index - 1].clone()))
+        } else {
+            None
+        }
+    }
+
+    fn statistics(&self) -> Statistics {
+        let mut statistics = Statistics::unknown(self.schema.columns().len());
+        statistics.row_count = Some(self.batches.iter().map(|b| b.num_rows()).sum());
+        statistics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::types::*;
+
+    #[test]
+    fn test_memory_data_source() {
+        let schema = Rc::new(Schema::new(vec![Field::new("a", DataType::UInt32, false)]));
+        let batch: Rc<RecordBatch> = Rc::new(DefaultRecordBatch {
+            schema: schema.clone(),
+            data: vec![Value::Column(Rc::new(Array::from(vec![1_u32, 2, 3])))],
+            row_count: 3,
+        });
+
+        let mut ds = MemoryDataSource::new(schema, Rc::new(vec![batch.clone(), batch]));
+        assert_eq!(3, ds.next().unwrap().unwrap().num_rows());
+        assert_eq!(3, ds.next().unwrap().unwrap().num_rows());
+        assert!(ds.next().is_none());
+        assert_eq!(Some(6), ds.statistics().row_count);
+    }
+}
@@ -17,6 +17,7 @@
 pub mod common;
 pub mod csv;
 pub mod empty;
+pub mod memory;
 pub mod ndjson;
 pub mod parquet;
 //pub mod quiver;
@@ -16,39 +16,122 @@
 
 use std::fs::File;
 use std::io;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::rc::Rc;
 
 use arrow::array::ListArray;
 use arrow::bitmap::*;
 use arrow::builder::*;
-use arrow::datatypes::{DataType, Schema};
+use arrow::datatypes::{DataType, Field, Schema};
 use arrow::list_builder::ListBuilder;
 
 use json;
 
 use super::super::errors::*;
+use super::super::logical::project_schema;
 use super::super::types::*;
 use super::common::*;
 
 pub struct NdJsonFile {
     schema: Rc<Schema>,
-    projection: Option<Vec<usize>>,
+    /// Indices of the columns that are read, in the order that they appear in each batch
+    projection: Vec<usize>,
+    projected_schema: Rc<Schema>,
     lines: Box<Iterator<Item = io::Result<String>>>,
     batch_size: usize,
 }
 
 impl NdJsonFile {
     pub fn open(f: File, schema: Rc<Schema>, projection: Option<Vec<usize>>) -> Result<Self> {
-        let reader = BufReader::new(f);
+        NdJsonFile::from_reader(Box::new(f), schema, projection)
+    }
+
+    /// Read JSON records from a reader, such as a reader that decompresses a file
+    pub fn from_reader(
+        reader: Box<Read>,
+        schema: Rc<Schema>,
+        projection: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        let reader = BufReader::new(reader);
         let it = reader.lines();
+        let projection = projection.unwrap_or_else(|| (0..schema.columns().len()).collect());
         Ok(NdJsonFile {
+            projected_schema: Rc::new(project_schema(&schema, &projection)),
             schema: schema.clone(),
             lines: Box::new(it),
             batch_size: 1024,
             projection,
         })
     }
+
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size
+    }
+}
+
+/// Infer the schema of JSON records from the first `max_records` lines. Fields are listed in
+/// the order that their keys first appear, numbers are Int64 unless any value has a fractional
+/// part or negative exponent, and all fields are nullable. Nested values are not supported.
+pub fn infer_schema(reader: Box<Read>, max_records: usize) -> Result<Schema> {
+    let mut fields: Vec<(String, Option<DataType>)> = vec![];
+    for line in BufReader::new(reader).lines().take(max_records) {
+        let line = line?;
+        if line.trim().len() == 0 {
+            continue;
+        }
+        let record = json::parse(&line)
+            .map_err(|e| ExecutionError::General(format!("Error reading JSON: {}", e)))?;
+        for (key, value) in record.entries() {
+            let data_type = match value {
+                json::JsonValue::Null => None,
+                json::JsonValue::Boolean(_) => Some(DataType::Boolean),
+                json::JsonValue::Short(_) | json::JsonValue::String(_) => Some(DataType::Utf8),
+                json::JsonValue::Number(n) => {
+                    let (_, _, exponent) = n.as_parts();
+                    if exponent >= 0 {
+                        Some(DataType::Int64)
+                    } else {
+                        Some(DataType::Float64)
+                    }
+                }
+                _ => {
+                    return Err(ExecutionError::General(format!(
+                        "Cannot infer a type for nested JSON value in field '{}'",
+                        key
+                    )))
+                }
+            };
+            match fields.iter().position(|(name, _)| name == key) {
+                Some(i) => {
+                    fields[i].1 = match (fields[i].1.clone(), data_type) {
+                        (a, None) => a,
+                        (None, b) => b,
+                        (Some(a), Some(b)) => {
+                            if a == b {
+                                Some(a)
+                            } else if (a == DataType::Int64 && b == DataType::Float64)
+                                || (a == DataType::Float64 && b == DataType::Int64)
+                            {
+                                Some(DataType::Float64)
+                            } else {
+                                return Err(ExecutionError::General(format!(
+                                    "Field '{}' has values of types {:?} and {:?}",
+                                    key, a, b
+                                )));
+                            }
+                        }
+                    }
+                }
+                None => fields.push((key.to_string(), data_type)),
+            }
+        }
+    }
+    Ok(Schema::new(
+        fields
+            .into_iter()
+            .map(|(name, data_type)| Field::new(&name, data_type.unwrap_or(DataType::Utf8), true))
+            .collect(),
+    ))
 }
 
 /// Built an Arrow array from one column in a batch of JSON records
@@ -75,7 +158,7 @@ macro_rules! collect_column {
 
 impl DataSource for NdJsonFile {
     fn schema(&self) -> &Rc<Schema> {
-        unimplemented!()
+        &self.projected_schema
     }
 
     fn next(&mut self) -> Option<Result<Rc<RecordBatch>>> {
@@ -99,101 +182,140 @@ impl DataSource for NdJsonFile {
 
         // now convert to Arrow arrays based on provided schema
 
-        let column_with_index = self.schema.columns().iter().enumerate();
-
-        let projection = match self.projection {
-            Some(ref v) => v.clone(),
-            None => self
-                .schema
-                .columns()
-                .iter()
-                .enumerate()
-                .map(|(i, _)| i)
-                .collect(),
-        };
-
-        let columns: Vec<Value> = column_with_index
-            .map(|(i, c)| {
-                if projection.contains(&i) {
-                    match c.data_type() {
-                        DataType::Boolean => {
-                            collect_column!(rows, c.name(), bool, as_bool, rows.len(), false)
-                        }
-                        DataType::Int8 => collect_column!(rows, c.name(), i8, as_i8, rows.len(), 0),
-                        DataType::Int16 => {
-                            collect_column!(rows, c.name(), i16, as_i16, rows.len(), 0)
-                        }
-                        DataType::Int32 => {
-                            collect_column!(rows, c.name(), i32, as_i32, rows.len(), 0)
-                        }
-                        DataType::Int64 => {
-                            collect_column!(rows, c.name(), i64, as_i64, rows.len(), 0)
-                        }
-                        DataType::UInt8 => {
-                            collect_column!(rows, c.name(), u8, as_u8, rows.len(), 0)
-                        }
-                        DataType::UInt16 => {
-                            collect_column!(rows, c.name(), u16, as_u16, rows.len(), 0)
-                        }
-                        DataType::UInt32 => {
-                            collect_column!(rows, c.name(), u32, as_u32, rows.len(), 0)
-                        }
-                        DataType::UInt64 => {
-                            collect_column!(rows, c.name(), u64, as_u64, rows.len(), 0)
-                        }
-                        DataType::Float16 => {
-                            collect_column!(rows, c.name(), f32, as_f32, rows.len(), 0_f32)
-                        }
-                        DataType::Float32 => {
-                            collect_column!(rows, c.name(), f32, as_f32, rows.len(), 0_f32)
-                        }
-                        DataType::Float64 => {
-                            collect_column!(rows, c.name(), f64, as_f64, rows.len(), 0_f64)
-                        }
-                        DataType::Utf8 => {
-                            let mut b: ListBuilder<u8> = ListBuilder::with_capacity(rows.len());
-                            let mut bitmap = Bitmap::new(rows.len());
-                            let mut null_count = 0;
-                            for row_index in 0..rows.len() {
-                                let field_name: &str = c.name().as_ref();
-                                let json_value: &json::JsonValue = &rows[row_index][field_name];
-                                if json_value.is_null() {
-                                    null_count += 1;
-                                    bitmap.clear(row_index);
-                                    b.push(b"");
-                                } else {
-                                    b.push(json_value.as_str().unwrap().as_bytes());
-                                }
+        let schema = &self.schema;
+        let columns: Vec<Value> = self
+            .projection
+            .iter()
+            .map(|&i| {
+                let c = schema.column(i);
+                match c.data_type() {
+                    DataType::Boolean => {
+                        collect_column!(rows, c.name(), bool, as_bool, rows.len(), false)
+                    }
+                    DataType::Int8 => collect_column!(rows, c.name(), i8, as_i8, rows.len(), 0),
+                    DataType::Int16 => {
+                        collect_column!(rows, c.name(), i16, as_i16, rows.len(), 0)
+                    }
+                    DataType::Int32 => {
+                        collect_column!(rows, c.name(), i32, as_i32, rows.len(), 0)
+                    }
+                    DataType::Int64 => {
+                        collect_column!(rows, c.name(), i64, as_i64, rows.len(), 0)
+                    }
+                    DataType::UInt8 => {
+                        collect_column!(rows, c.name(), u8, as_u8, rows.len(), 0)
+                    }
+                    DataType::UInt16 => {
+                        collect_column!(rows, c.name(), u16, as_u16, rows.len(), 0)
+                    }
+                    DataType::UInt32 => {
+                        collect_column!(rows, c.name(), u32, as_u32, rows.len(), 0)
+                    }
+                    DataType::UInt64 => {
+                        collect_column!(rows, c.name(), u64, as_u64, rows.len(), 0)
+                    }
+                    DataType::Float16 => {
+                        collect_column!(rows, c.name(), f32, as_f32, rows.len(), 0_f32)
+                    }
+                    DataType::Float32 => {
+                        collect_column!(rows, c.name(), f32, as_f32, rows.len(), 0_f32)
+                    }
+                    DataType::Float64 => {
+                        collect_column!(rows, c.name(), f64, as_f64, rows.len(), 0_f64)
+                    }
+                    DataType::Utf8 => {
+                        let mut b: ListBuilder<u8> = ListBuilder::with_capacity(rows.len());
+                        let mut bitmap = Bitmap::new(rows.len());
+                        let mut null_count = 0;
+                        for row_index in 0..rows.len() {
+                            let field_name: &str = c.name().as_ref();
+                            let json_value: &json::JsonValue = &rows[row_index][field_name];
+                            if json_value.is_null() {
+                                null_count += 1;
+                                bitmap.clear(row_index);
+                                b.push(b"");
+                            } else {
+                                b.push(json_value.as_str().unwrap().as_bytes());
                             }
-                            let buffer = b.finish();
-                            Value::Column(Rc::new(Array::with_nulls(
-                                rows.len(),
-                                ArrayData::Utf8(ListArray::from(buffer)),
-                                null_count,
-                                bitmap,
-                            )))
                         }
-                        _ => unimplemented!(
-                            "ndjson reader does not support data type {:?}",
-                            c.data_type()
-                        ),
+                        let buffer = b.finish();
+                        Value::Column(Rc::new(Array::with_nulls(
+                            rows.len(),
+                            ArrayData::Utf8(ListArray::from(buffer)),
+                            null_count,
+                            bitmap,
+                        )))
                     }
-                } else {
-                    // not in the projection
-                    //println!("Not loading column {} at index {}", c.name(), i);
-                    Value::Scalar(Rc::new(ScalarValue::Null))
+                    _ => unimplemented!(
+                        "ndjson reader does not support data type {:?}",
+                        c.data_type()
+                    ),
                 }
             })
             .collect();
 
         Some(Ok(Rc::new(DefaultRecordBatch {
-            schema: self.schema.clone(),
+            schema: self.projected_schema.clone(),
             data: columns,
             row_count: rows.len(),
         })))
     }
 }
 
+/// Writes record batches to a newline-delimited JSON file, with one object per row. Null
+/// values are written as JSON nulls.
+pub struct NdJsonBatchWriter {
+    w: BufWriter<File>,
+    schema: Rc<Schema>,
+}
+
+impl NdJsonBatchWriter {
+    pub fn new(file: File, schema: Rc<Schema>) -> Self {
+        NdJsonBatchWriter {
+            w: BufWriter::new(file),
+            schema,
+        }
+    }
+}
+
+/// Convert a scalar value to JSON
+fn to_json(v: &ScalarValue) -> json::JsonValue {
+    match v {
+        ScalarValue::Null => json::JsonValue::Null,
+        ScalarValue::Boolean(v) => (*v).into(),
+        ScalarValue::Int8(v) => (*v).into(),
+        ScalarValue::Int16(v) => (*v).into(),
+        ScalarValue::Int32(v) => (*v).into(),
+        ScalarValue::Int64(v) => (*v).into(),
+        ScalarValue::UInt8(v) => (*v).into(),
+        ScalarValue::UInt16(v) => (*v).into(),
+        ScalarValue::UInt32(v) => (*v).into(),
+        ScalarValue::UInt64(v) => (*v).into(),
+        ScalarValue::Float32(v) => (*v).into(),
+        ScalarValue::Float64(v) => (*v).into(),
+        ScalarValue::Utf8(ref v) => v.as_str().into(),
+        ScalarValue::Struct(ref v) => json::JsonValue::Array(v.iter().map(to_json).collect()),
+    }
+}
+
+impl RecordBatchWriter for NdJsonBatchWriter {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        for i in 0..batch.num_rows() {
+            let mut row = json::JsonValue::new_object();
+            for (j, c) in self.schema.columns().iter().enumerate() {
+                row[c.name().as_str()] = to_json(&get_column_value(batch.column(j), i));
+            }
+            self.w.write_all(row.dump().as_bytes())?;
+            self.w.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.w.flush()?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
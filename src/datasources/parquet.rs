@@ -29,17 +29,19 @@ use parquet::file::reader::*;
 use parquet::schema::types::Type;
 
 use super::super::errors::*;
+use super::super::logical::project_schema;
 use super::super::types::*;
 use super::common::*;
 
 pub struct ParquetFile {
     reader: SerializedFileReader,
     row_group_index: usize,
-    schema: Rc<Schema>,
-    projection: Option<Vec<usize>>,
+    /// Indices of the columns that are read, in the order that they appear in each batch
+    projection: Vec<usize>,
+    projected_schema: Rc<Schema>,
     batch_size: usize,
     current_row_group: Option<Box<RowGroupReader>>,
-    column_readers: Vec<Option<ColumnReader>>,
+    column_readers: Vec<ColumnReader>,
 }
 
 impl ParquetFile {
@@ -53,10 +55,12 @@ impl ParquetFile {
             DataType::Struct(fields) => {
                 let schema = Schema::new(fields.clone());
                 //println!("Parquet schema: {:?}", schema);
+                let projection =
+                    projection.unwrap_or_else(|| (0..schema.columns().len()).collect());
                 Ok(ParquetFile {
                     reader: reader,
                     row_group_index: 0,
-                    schema: Rc::new(schema),
+                    projected_schema: Rc::new(project_schema(&schema, &projection)),
                     projection,
                     batch_size: 64 * 1024,
                     current_row_group: None,
@@ -78,27 +82,12 @@ impl ParquetFile {
             //println!("Loading row group {} of {}", self.row_group_index, self.reader.num_row_groups());
             let reader = self.reader.get_row_group(self.row_group_index).unwrap();
 
-            self.column_readers = vec![];
-
-            match &self.projection {
-                None => {
-                    for i in 0..reader.num_columns() {
-                        self.column_readers
-                            .push(Some(reader.get_column_reader(i).unwrap()));
-                    }
-                }
-                Some(proj) => {
-                    for i in 0..reader.num_columns() {
-                        if proj.contains(&i) {
-                            self.column_readers
-                                .push(Some(reader.get_column_reader(i).unwrap()));
-                        } else {
-                            //println!("Parquet NOT LOADING COLUMN");
-                            self.column_readers.push(None);
-                        }
-                    }
-                }
-            }
+            // only the columns in the projection are read
+            self.column_readers = self
+                .projection
+                .iter()
+                .map(|i| reader.get_column_reader(*i).unwrap())
+                .collect();
 
             self.current_row_group = Some(reader);
             self.row_group_index += 1;
@@ -109,12 +98,12 @@ impl ParquetFile {
 
     fn load_batch(&mut self) -> Option<Result<Rc<RecordBatch>>> {
         match &self.current_row_group {
-            Some(reader) => {
-                let mut batch: Vec<Value> = Vec::with_capacity(reader.num_columns());
+            Some(_) => {
+                let mut batch: Vec<Value> = Vec::with_capacity(self.column_readers.len());
                 let mut row_count = 0;
                 for i in 0..self.column_readers.len() {
                     let array = match self.column_readers[i] {
-                        Some(ColumnReader::ByteArrayColumnReader(ref mut r)) => {
+                        ColumnReader::ByteArrayColumnReader(ref mut r) => {
                             let mut b: Vec<ByteArray> = Vec::with_capacity(self.batch_size);
                             for _ in 0..self.batch_size {
                                 b.push(ByteArray::default());
@@ -136,7 +125,7 @@ impl ParquetFile {
                                 _ => panic!("Error reading parquet batch (column {})", i),
                             }
                         }
-                        Some(ColumnReader::BoolColumnReader(ref mut r)) => {
+                        ColumnReader::BoolColumnReader(ref mut r) => {
                             let mut builder: Builder<bool> =
                                 Builder::with_capacity(self.batch_size);
                             match r.read_batch(
@@ -153,7 +142,7 @@ impl ParquetFile {
                                 _ => panic!("Error reading parquet batch (column {})", i),
                             }
                         }
-                        Some(ColumnReader::Int32ColumnReader(ref mut r)) => {
+                        ColumnReader::Int32ColumnReader(ref mut r) => {
                             let mut builder: Builder<i32> = Builder::with_capacity(self.batch_size);
                             match r.read_batch(
                                 self.batch_size,
@@ -169,7 +158,7 @@ impl ParquetFile {
                                 _ => panic!("Error reading parquet batch (column {})", i),
                             }
                         }
-                        Some(ColumnReader::Int64ColumnReader(ref mut r)) => {
+                        ColumnReader::Int64ColumnReader(ref mut r) => {
                             let mut builder: Builder<i64> = Builder::with_capacity(self.batch_size);
                             match r.read_batch(
                                 self.batch_size,
@@ -185,7 +174,7 @@ impl ParquetFile {
                                 _ => panic!("Error reading parquet batch (column {})", i),
                             }
                         }
-                        Some(ColumnReader::Int96ColumnReader(ref mut r)) => {
+                        ColumnReader::Int96ColumnReader(ref mut r) => {
                             let mut temp: Vec<Int96> = Vec::with_capacity(self.batch_size);
                             for _ in 0..self.batch_size {
                                 temp.push(Int96::new());
@@ -216,7 +205,7 @@ impl ParquetFile {
                                 _ => panic!("Error reading parquet batch (column {})", i),
                             }
                         }
-                        Some(ColumnReader::FloatColumnReader(ref mut r)) => {
+                        ColumnReader::FloatColumnReader(ref mut r) => {
                             let mut builder: Builder<f32> = Builder::with_capacity(self.batch_size);
                             match r.read_batch(
                                 self.batch_size,
@@ -232,7 +221,7 @@ impl ParquetFile {
                                 _ => panic!("Error reading parquet batch (column {})", i),
                             }
                         }
-                        Some(ColumnReader::DoubleColumnReader(ref mut r)) => {
+                        ColumnReader::DoubleColumnReader(ref mut r) => {
                             let mut builder: Builder<f64> = Builder::with_capacity(self.batch_size);
                             match r.read_batch(
                                 self.batch_size,
@@ -248,10 +237,7 @@ impl ParquetFile {
                                 _ => panic!("Error reading parquet batch (column {})", i),
                             }
                         }
-                        Some(ColumnReader::FixedLenByteArrayColumnReader(_)) => unimplemented!(),
-                        None => {
-                            Array::from(vec![0_i32]) //TODO: really want to return scalar null
-                        }
+                        ColumnReader::FixedLenByteArrayColumnReader(_) => unimplemented!(),
                     };
 
                     batch.push(Value::Column(Rc::new(array)));
@@ -263,7 +249,7 @@ impl ParquetFile {
                     None
                 } else {
                     Some(Ok(Rc::new(DefaultRecordBatch {
-                        schema: self.schema.clone(),
+                        schema: self.projected_schema.clone(),
                         data: batch,
                         row_count,
                     })))
@@ -294,7 +280,23 @@ impl DataSource for ParquetFile {
     }
 
     fn schema(&self) -> &Rc<Schema> {
-        &self.schema
+        &self.projected_schema
+    }
+
+    fn statistics(&self) -> Statistics {
+        // the row count and size are read from the file metadata, but this version of the
+        // parquet crate does not read the statistics of column chunks
+        let metadata = self.reader.metadata();
+        let mut statistics = Statistics::unknown(self.projection.len());
+        statistics.row_count = Some(metadata.file_metadata().num_rows() as usize);
+        statistics.byte_size = Some(
+            metadata
+                .row_groups()
+                .iter()
+                .map(|rg| rg.total_byte_size() as usize)
+                .sum(),
+        );
+        statistics
     }
 }
 
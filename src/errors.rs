@@ -15,6 +15,7 @@
 //! Error types
 
 use super::sqlparser::ParserError;
+use std::fmt;
 use std::io::Error;
 use std::result;
 
@@ -35,6 +36,18 @@ pub enum ExecutionError {
     NotImplemented,
 }
 
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecutionError::IoError(e) => write!(f, "IO error: {}", e),
+            ExecutionError::ParserError(e) => write!(f, "{}", e),
+            ExecutionError::General(s) => write!(f, "{}", s),
+            ExecutionError::InvalidColumn(s) => write!(f, "Invalid column: {}", s),
+            ExecutionError::NotImplemented => write!(f, "Not implemented"),
+        }
+    }
+}
+
 impl From<Error> for ExecutionError {
     fn from(e: Error) -> Self {
         ExecutionError::IoError(e)
@@ -24,6 +24,7 @@ extern crate bytes;
 extern crate clap;
 extern crate csv;
 extern crate datafusion_rustyline;
+extern crate flate2;
 extern crate fnv;
 extern crate json;
 extern crate parquet;
@@ -31,6 +32,9 @@ extern crate parquet;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod analyzer;
+pub mod coercion;
+pub mod config;
 pub mod dataframe;
 pub mod datasources;
 #[macro_use]
@@ -38,9 +42,16 @@ pub mod errors;
 pub mod exec;
 pub mod functions;
 pub mod logical;
+pub mod optimizer;
+pub mod physical;
+pub mod planbuilder;
 pub mod relations;
+pub mod serialize;
 pub mod sqlast;
+pub mod sqldialect;
 pub mod sqlparser;
 pub mod sqlplanner;
 pub mod sqltokenizer;
+pub mod sqlunparser;
 pub mod types;
+pub mod visitor;
@@ -18,6 +18,10 @@ use std::fmt;
 use std::fmt::{Error, Formatter};
 use std::rc::Rc;
 
+use super::analyzer::resolve_column;
+use super::coercion::binary_operand_type;
+use super::datasources::common::{FileOptions, RecordBatch};
+use super::sqlast::ASTNode;
 use super::types::*;
 
 use arrow::datatypes::*;
@@ -26,6 +30,8 @@ use arrow::datatypes::*;
 pub enum FunctionType {
     Scalar,
     Aggregate,
+    /// Function defined by a SQL expression, which is inlined where it is called
+    Macro,
 }
 
 #[derive(Debug, Clone)]
@@ -34,6 +40,8 @@ pub struct FunctionMeta {
     args: Vec<Field>,
     return_type: DataType,
     function_type: FunctionType,
+    /// SQL expression for macro functions, which refers to the arguments by name
+    body: Option<ASTNode>,
 }
 
 impl FunctionMeta {
@@ -48,6 +56,17 @@ impl FunctionMeta {
             args,
             return_type,
             function_type,
+            body: None,
+        }
+    }
+    /// Create the metadata for a function defined by a SQL expression
+    pub fn new_macro(name: String, args: Vec<Field>, return_type: DataType, body: ASTNode) -> Self {
+        FunctionMeta {
+            name,
+            args,
+            return_type,
+            function_type: FunctionType::Macro,
+            body: Some(body),
         }
     }
     pub fn name(&self) -> &String {
@@ -62,6 +81,9 @@ impl FunctionMeta {
     pub fn function_type(&self) -> &FunctionType {
         &self.function_type
     }
+    pub fn body(&self) -> Option<&ASTNode> {
+        self.body.as_ref()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -79,13 +101,23 @@ pub enum Operator {
     Modulus,
     And,
     Or,
+    Like,
+    ILike,
 }
 
 impl Operator {
-    /// Get the result type of applying this operation to its left and right inputs
-    pub fn get_datatype(&self, l: &Expr, _r: &Expr, schema: &Schema) -> DataType {
-        //TODO: implement correctly, just go with left side for now
-        l.get_type(schema).clone()
+    /// Get the result type of applying this operation to its left and right inputs, which is
+    /// an error if the types of the inputs cannot be coerced to a common type
+    pub fn get_datatype(&self, l: &Expr, r: &Expr, schema: &Schema) -> Result<DataType, String> {
+        let operand_type = binary_operand_type(&l.get_type(schema)?, self, &r.get_type(schema)?)?;
+        Ok(match self {
+            Operator::Plus
+            | Operator::Minus
+            | Operator::Multiply
+            | Operator::Divide
+            | Operator::Modulus => operand_type,
+            _ => DataType::Boolean,
+        })
     }
 }
 
@@ -94,6 +126,12 @@ impl Operator {
 pub enum Expr {
     /// index into a value within the row or complex value
     Column(usize),
+    /// reference to a column by name, optionally qualified by the name of the table that it
+    /// belongs to, which the analyzer resolves to a `Column`
+    ColumnRef {
+        relation: Option<String>,
+        name: String,
+    },
     /// literal value
     Literal(ScalarValue),
     /// binary expression e.g. "age > 21"
@@ -104,6 +142,8 @@ pub enum Expr {
     },
     /// unary IS NOT NULL
     IsNotNull(Rc<Expr>),
+    /// unary NOT of a boolean expression
+    Not(Rc<Expr>),
     /// unary IS NULL
     IsNull(Rc<Expr>),
     /// cast a value to a different type
@@ -125,38 +165,107 @@ pub enum Expr {
 }
 
 impl Expr {
-    pub fn get_type(&self, schema: &Schema) -> DataType {
-        match self {
+    /// Get the type of the values of this expression when it is evaluated against rows with
+    /// the specified schema, which is an error if it refers to a column that is not in the
+    /// schema or applies an operator to types that cannot be coerced to a common type
+    pub fn get_type(&self, schema: &Schema) -> Result<DataType, String> {
+        Ok(match self {
             Expr::Column(n) => schema.column(*n).data_type().clone(),
+            Expr::ColumnRef { name, .. } => {
+                let i = resolve_column(schema, None, None, name, false)?;
+                schema.column(i).data_type().clone()
+            }
             Expr::Literal(l) => l.get_datatype(),
             Expr::Cast { data_type, .. } => data_type.clone(),
             Expr::ScalarFunction { return_type, .. } => return_type.clone(),
             Expr::AggregateFunction { return_type, .. } => return_type.clone(),
             Expr::IsNull(_) => DataType::Boolean,
             Expr::IsNotNull(_) => DataType::Boolean,
+            Expr::Not(_) => DataType::Boolean,
             Expr::BinaryExpr {
                 ref left,
                 ref right,
                 ref op,
-            } => {
-                match op {
-                    Operator::Eq | Operator::NotEq => DataType::Boolean,
-                    Operator::Lt | Operator::LtEq => DataType::Boolean,
-                    Operator::Gt | Operator::GtEq => DataType::Boolean,
-                    Operator::And | Operator::Or => DataType::Boolean,
-                    _ => {
-                        let left_type = left.get_type(schema);
-                        let right_type = right.get_type(schema);
-                        get_supertype(&left_type, &right_type).unwrap_or(DataType::Utf8) //TODO ???
-                    }
-                }
+            } => op.get_datatype(left, right, schema)?,
+            Expr::Sort { ref expr, .. } => expr.get_type(schema)?,
+        })
+    }
+
+    /// Get the expressions that are the direct inputs of this expression, in order
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Column(_) | Expr::ColumnRef { .. } | Expr::Literal(_) => vec![],
+            Expr::BinaryExpr {
+                ref left,
+                ref right,
+                ..
+            } => vec![left.as_ref(), right.as_ref()],
+            Expr::IsNotNull(ref expr) | Expr::Not(ref expr) | Expr::IsNull(ref expr) => {
+                vec![expr.as_ref()]
             }
-            Expr::Sort { ref expr, .. } => expr.get_type(schema),
+            Expr::Cast { ref expr, .. } | Expr::Sort { ref expr, .. } => vec![expr.as_ref()],
+            Expr::ScalarFunction { ref args, .. } | Expr::AggregateFunction { ref args, .. } => {
+                args.iter().collect()
+            }
+        }
+    }
+
+    /// Copy this expression with its inputs replaced. There must be one new input for each
+    /// of the expressions returned by `children`, in the same order.
+    pub fn with_new_children(&self, children: Vec<Expr>) -> Expr {
+        assert_eq!(
+            self.children().len(),
+            children.len(),
+            "Wrong number of inputs for expression {:?}",
+            self
+        );
+        let mut children = children.into_iter();
+        macro_rules! next {
+            () => {
+                Rc::new(children.next().unwrap())
+            };
+        }
+        match self {
+            Expr::Column(_) | Expr::ColumnRef { .. } | Expr::Literal(_) => self.clone(),
+            Expr::BinaryExpr { ref op, .. } => Expr::BinaryExpr {
+                left: next!(),
+                op: op.clone(),
+                right: next!(),
+            },
+            Expr::IsNotNull(_) => Expr::IsNotNull(next!()),
+            Expr::Not(_) => Expr::Not(next!()),
+            Expr::IsNull(_) => Expr::IsNull(next!()),
+            Expr::Cast { ref data_type, .. } => Expr::Cast {
+                expr: next!(),
+                data_type: data_type.clone(),
+            },
+            Expr::Sort { asc, .. } => Expr::Sort {
+                expr: next!(),
+                asc: *asc,
+            },
+            Expr::ScalarFunction {
+                ref name,
+                ref return_type,
+                ..
+            } => Expr::ScalarFunction {
+                name: name.clone(),
+                args: children.collect(),
+                return_type: return_type.clone(),
+            },
+            Expr::AggregateFunction {
+                ref name,
+                ref return_type,
+                ..
+            } => Expr::AggregateFunction {
+                name: name.clone(),
+                args: children.collect(),
+                return_type: return_type.clone(),
+            },
         }
     }
 
     pub fn cast_to(&self, cast_to_type: &DataType, schema: &Schema) -> Result<Expr, String> {
-        let this_type = self.get_type(schema);
+        let this_type = self.get_type(schema)?;
         if this_type == *cast_to_type {
             Ok(self.clone())
         } else if can_coerce_from(cast_to_type, &this_type) {
@@ -225,10 +334,16 @@ impl fmt::Debug for Expr {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         match self {
             Expr::Column(i) => write!(f, "#{}", i),
+            Expr::ColumnRef {
+                relation: Some(relation),
+                name,
+            } => write!(f, "#{}.{}", relation, name),
+            Expr::ColumnRef { name, .. } => write!(f, "#{}", name),
             Expr::Literal(v) => write!(f, "{:?}", v),
             Expr::Cast { expr, data_type } => write!(f, "CAST({:?} AS {:?})", expr, data_type),
             Expr::IsNull(expr) => write!(f, "{:?} IS NULL", expr),
             Expr::IsNotNull(expr) => write!(f, "{:?} IS NOT NULL", expr),
+            Expr::Not(expr) => write!(f, "NOT {:?}", expr),
             Expr::BinaryExpr { left, op, right } => write!(f, "{:?} {:?} {:?}", left, op, right),
             Expr::Sort { expr, asc } => if *asc {
                 write!(f, "{:?} ASC", expr)
@@ -292,12 +407,18 @@ pub enum LogicalPlan {
         input: Rc<LogicalPlan>,
         schema: Rc<Schema>,
     },
-    /// A table scan against a table that has been registered on a context
+    /// A table scan against a table that has been registered on a context. Only the columns
+    /// in the projection are read, and `projected_schema` is the schema of these columns. The
+    /// filters are predicates on the columns of `schema` that a `Selection` above the scan
+    /// applies, which the data source can use to skip data that cannot match. Files have the
+    /// same projection and filters.
     TableScan {
         schema_name: String,
         table_name: String,
         schema: Rc<Schema>,
         projection: Option<Vec<usize>>,
+        projected_schema: Rc<Schema>,
+        filters: Vec<Expr>,
     },
     /// Represents a CSV file with a provided schema
     CsvFile {
@@ -305,32 +426,245 @@ pub enum LogicalPlan {
         schema: Rc<Schema>,
         has_header: bool,
         projection: Option<Vec<usize>>,
+        projected_schema: Rc<Schema>,
+        options: FileOptions,
+        filters: Vec<Expr>,
     },
     /// Represents an ndjson file with a provided schema
     NdJsonFile {
         filename: String,
         schema: Rc<Schema>,
         projection: Option<Vec<usize>>,
+        projected_schema: Rc<Schema>,
+        options: FileOptions,
+        filters: Vec<Expr>,
     },
     /// Represents a Parquet file that contains schema information
     ParquetFile {
         filename: String,
         schema: Rc<Schema>,
         projection: Option<Vec<usize>>,
+        projected_schema: Rc<Schema>,
+        options: FileOptions,
+        filters: Vec<Expr>,
+    },
+    /// Represents record batches that are already held in memory
+    InMemory {
+        data: Rc<Vec<Rc<RecordBatch>>>,
+        schema: Rc<Schema>,
+        projection: Option<Vec<usize>>,
     },
     /// An empty relation with an empty schema
     EmptyRelation { schema: Rc<Schema> },
 }
 
 impl LogicalPlan {
+    /// Copy a table scan or file with the filters that its data source receives replaced.
+    /// Other plans are copied unchanged.
+    pub fn with_filters(&self, filters: Vec<Expr>) -> LogicalPlan {
+        let mut plan = self.clone();
+        match plan {
+            LogicalPlan::TableScan {
+                filters: ref mut f, ..
+            }
+            | LogicalPlan::CsvFile {
+                filters: ref mut f, ..
+            }
+            | LogicalPlan::NdJsonFile {
+                filters: ref mut f, ..
+            }
+            | LogicalPlan::ParquetFile {
+                filters: ref mut f, ..
+            } => *f = filters,
+            _ => {}
+        }
+        plan
+    }
+
+    /// Copy a table scan or file so that it only reads the columns in a projection, which are
+    /// returned in the order of the projection. Other plans are copied unchanged.
+    pub fn with_projection(&self, projection: Vec<usize>) -> LogicalPlan {
+        let mut plan = self.clone();
+        match plan {
+            LogicalPlan::TableScan {
+                ref schema,
+                projection: ref mut p,
+                ref mut projected_schema,
+                ..
+            }
+            | LogicalPlan::CsvFile {
+                ref schema,
+                projection: ref mut p,
+                ref mut projected_schema,
+                ..
+            }
+            | LogicalPlan::NdJsonFile {
+                ref schema,
+                projection: ref mut p,
+                ref mut projected_schema,
+                ..
+            }
+            | LogicalPlan::ParquetFile {
+                ref schema,
+                projection: ref mut p,
+                ref mut projected_schema,
+                ..
+            } => {
+                *projected_schema = Rc::new(project_schema(schema, &projection));
+                *p = Some(projection);
+            }
+            _ => {}
+        }
+        plan
+    }
+
+    /// Get the plans that are the inputs of this plan
+    pub fn inputs(&self) -> Vec<&Rc<LogicalPlan>> {
+        match self {
+            LogicalPlan::Limit { ref input, .. }
+            | LogicalPlan::Projection { ref input, .. }
+            | LogicalPlan::Selection { ref input, .. }
+            | LogicalPlan::Aggregate { ref input, .. }
+            | LogicalPlan::Sort { ref input, .. } => vec![input],
+            LogicalPlan::TableScan { .. }
+            | LogicalPlan::CsvFile { .. }
+            | LogicalPlan::NdJsonFile { .. }
+            | LogicalPlan::ParquetFile { .. }
+            | LogicalPlan::InMemory { .. }
+            | LogicalPlan::EmptyRelation { .. } => vec![],
+        }
+    }
+
+    /// Copy this plan with its inputs replaced. There must be one new input for each of the
+    /// plans returned by `inputs`, in the same order. Plans that pass the columns of their
+    /// input through unchanged take the schema of the new input, and other plans keep their
+    /// schema.
+    pub fn with_new_inputs(&self, inputs: &[Rc<LogicalPlan>]) -> LogicalPlan {
+        assert_eq!(
+            self.inputs().len(),
+            inputs.len(),
+            "Wrong number of inputs for plan {:?}",
+            self
+        );
+        let mut plan = self.clone();
+        match plan {
+            LogicalPlan::Projection { ref mut input, .. }
+            | LogicalPlan::Selection { ref mut input, .. }
+            | LogicalPlan::Aggregate { ref mut input, .. } => *input = inputs[0].clone(),
+            LogicalPlan::Sort {
+                ref mut input,
+                ref mut schema,
+                ..
+            }
+            | LogicalPlan::Limit {
+                ref mut input,
+                ref mut schema,
+                ..
+            } => {
+                *input = inputs[0].clone();
+                *schema = input.schema().clone();
+            }
+            _ => {}
+        }
+        plan
+    }
+
+    /// Get the expressions that this plan evaluates. The grouping expressions of an aggregate
+    /// come before its aggregate expressions, and the expressions of a table scan or file are
+    /// the filters that its data source receives.
+    pub fn expressions(&self) -> Vec<&Expr> {
+        match self {
+            LogicalPlan::Projection { ref expr, .. } | LogicalPlan::Sort { ref expr, .. } => {
+                expr.iter().collect()
+            }
+            LogicalPlan::Selection { ref expr, .. } => vec![expr],
+            LogicalPlan::Aggregate {
+                ref group_expr,
+                ref aggr_expr,
+                ..
+            } => group_expr.iter().chain(aggr_expr.iter()).collect(),
+            LogicalPlan::TableScan { ref filters, .. }
+            | LogicalPlan::CsvFile { ref filters, .. }
+            | LogicalPlan::NdJsonFile { ref filters, .. }
+            | LogicalPlan::ParquetFile { ref filters, .. } => filters.iter().collect(),
+            LogicalPlan::Limit { .. }
+            | LogicalPlan::InMemory { .. }
+            | LogicalPlan::EmptyRelation { .. } => vec![],
+        }
+    }
+
+    /// Copy this plan with its expressions replaced. There must be one new expression for
+    /// each of the expressions returned by `expressions`, in the same order. The schema of the
+    /// plan is not changed.
+    pub fn with_new_expressions(&self, expr: Vec<Expr>) -> LogicalPlan {
+        assert_eq!(
+            self.expressions().len(),
+            expr.len(),
+            "Wrong number of expressions for plan {:?}",
+            self
+        );
+        let mut plan = self.clone();
+        match plan {
+            LogicalPlan::Projection {
+                expr: ref mut e, ..
+            }
+            | LogicalPlan::Sort {
+                expr: ref mut e, ..
+            } => *e = expr,
+            LogicalPlan::Selection {
+                expr: ref mut e, ..
+            } => *e = expr.into_iter().next().unwrap(),
+            LogicalPlan::Aggregate {
+                ref mut group_expr,
+                ref mut aggr_expr,
+                ..
+            } => {
+                let mut expr = expr;
+                *aggr_expr = expr.split_off(group_expr.len());
+                *group_expr = expr;
+            }
+            LogicalPlan::TableScan { .. }
+            | LogicalPlan::CsvFile { .. }
+            | LogicalPlan::NdJsonFile { .. }
+            | LogicalPlan::ParquetFile { .. } => return plan.with_filters(expr),
+            _ => {}
+        }
+        plan
+    }
+
+    /// Get the schema of the rows that the expressions of this plan are evaluated against,
+    /// which is the schema of its input, or the schema of the table for the filters of a
+    /// table scan or file
+    pub fn expression_schema(&self) -> &Rc<Schema> {
+        match self {
+            LogicalPlan::TableScan { ref schema, .. }
+            | LogicalPlan::CsvFile { ref schema, .. }
+            | LogicalPlan::NdJsonFile { ref schema, .. }
+            | LogicalPlan::ParquetFile { ref schema, .. } => schema,
+            _ => match self.inputs().first() {
+                Some(input) => input.schema(),
+                None => self.schema(),
+            },
+        }
+    }
+
     /// Get a reference to the logical plan's schema
     pub fn schema(&self) -> &Rc<Schema> {
         match self {
             LogicalPlan::EmptyRelation { schema } => &schema,
-            LogicalPlan::TableScan { schema, .. } => &schema,
-            LogicalPlan::CsvFile { schema, .. } => &schema,
-            LogicalPlan::NdJsonFile { schema, .. } => &schema,
-            LogicalPlan::ParquetFile { schema, .. } => &schema,
+            LogicalPlan::TableScan {
+                projected_schema, ..
+            } => &projected_schema,
+            LogicalPlan::CsvFile {
+                projected_schema, ..
+            } => &projected_schema,
+            LogicalPlan::NdJsonFile {
+                projected_schema, ..
+            } => &projected_schema,
+            LogicalPlan::ParquetFile {
+                projected_schema, ..
+            } => &projected_schema,
+            LogicalPlan::InMemory { schema, .. } => &schema,
             LogicalPlan::Projection { schema, .. } => &schema,
             LogicalPlan::Selection { input, .. } => input.schema(),
             LogicalPlan::Aggregate { schema, .. } => &schema,
@@ -340,6 +674,16 @@ impl LogicalPlan {
     }
 }
 
+/// Create a schema with the columns of another schema that are in a projection
+pub fn project_schema(schema: &Schema, projection: &[usize]) -> Schema {
+    Schema::new(
+        projection
+            .iter()
+            .map(|i| schema.column(*i).clone())
+            .collect(),
+    )
+}
+
 impl LogicalPlan {
     fn fmt_with_indent(&self, f: &mut Formatter, indent: usize) -> Result<(), Error> {
         if indent > 0 {
@@ -349,28 +693,42 @@ impl LogicalPlan {
             }
         }
         match *self {
-            LogicalPlan::EmptyRelation { .. } => write!(f, "EmptyRelation"),
+            LogicalPlan::EmptyRelation { .. } => write!(f, "EmptyRelation")?,
             LogicalPlan::TableScan {
                 ref table_name,
                 ref projection,
+                ref filters,
                 ..
-            } => write!(f, "TableScan: {} projection={:?}", table_name, projection),
+            } => {
+                write!(f, "TableScan: {} projection={:?}", table_name, projection)?;
+                fmt_filters(f, filters)?
+            }
             LogicalPlan::CsvFile {
                 ref filename,
                 ref schema,
+                ref filters,
                 ..
-            } => write!(f, "CsvFile: file={}, schema={:?}", filename, schema),
+            } => {
+                write!(f, "CsvFile: file={}, schema={:?}", filename, schema)?;
+                fmt_filters(f, filters)?
+            }
             LogicalPlan::NdJsonFile {
                 ref filename,
                 ref schema,
-                ..
-            } => write!(f, "NdJsonFile: file={}, schema={:?}", filename, schema),
-            LogicalPlan::ParquetFile { .. } => write!(f, "ParquetFile:"),
-            LogicalPlan::Projection {
-                ref expr,
-                ref input,
+                ref filters,
                 ..
             } => {
+                write!(f, "NdJsonFile: file={}, schema={:?}", filename, schema)?;
+                fmt_filters(f, filters)?
+            }
+            LogicalPlan::ParquetFile { ref filters, .. } => {
+                write!(f, "ParquetFile:")?;
+                fmt_filters(f, filters)?
+            }
+            LogicalPlan::InMemory { ref data, .. } => {
+                write!(f, "InMemory: batches={}", data.len())?
+            }
+            LogicalPlan::Projection { ref expr, .. } => {
                 write!(f, "Projection: ")?;
                 for i in 0..expr.len() {
                     if i > 0 {
@@ -378,34 +736,18 @@ impl LogicalPlan {
                     }
                     write!(f, "{:?}", expr[i])?;
                 }
-                input.fmt_with_indent(f, indent + 1)
-            }
-            LogicalPlan::Selection {
-                ref expr,
-                ref input,
-                ..
-            } => {
-                write!(f, "Selection: {:?}", expr)?;
-                input.fmt_with_indent(f, indent + 1)
             }
+            LogicalPlan::Selection { ref expr, .. } => write!(f, "Selection: {:?}", expr)?,
             LogicalPlan::Aggregate {
-                ref input,
                 ref group_expr,
                 ref aggr_expr,
                 ..
-            } => {
-                write!(
-                    f,
-                    "Aggregate: groupBy=[{:?}], aggr=[{:?}]",
-                    group_expr, aggr_expr
-                )?;
-                input.fmt_with_indent(f, indent + 1)
-            }
-            LogicalPlan::Sort {
-                ref input,
-                ref expr,
-                ..
-            } => {
+            } => write!(
+                f,
+                "Aggregate: groupBy=[{:?}], aggr=[{:?}]",
+                group_expr, aggr_expr
+            )?,
+            LogicalPlan::Sort { ref expr, .. } => {
                 write!(f, "Sort: ")?;
                 for i in 0..expr.len() {
                     if i > 0 {
@@ -413,14 +755,181 @@ impl LogicalPlan {
                     }
                     write!(f, "{:?}", expr[i])?;
                 }
-                input.fmt_with_indent(f, indent + 1)
-            }
-            LogicalPlan::Limit {
-                ref input, limit, ..
-            } => {
-                write!(f, "Limit: {}", limit)?;
-                input.fmt_with_indent(f, indent + 1)
             }
+            LogicalPlan::Limit { limit, .. } => write!(f, "Limit: {}", limit)?,
+        }
+        for input in self.inputs() {
+            input.fmt_with_indent(f, indent + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the filters of a scan, if there are any
+fn fmt_filters(f: &mut Formatter, filters: &[Expr]) -> Result<(), Error> {
+    if filters.is_empty() {
+        Ok(())
+    } else {
+        write!(f, " filters={:?}", filters)
+    }
+}
+
+/// Plans are equal if they have the same structure, and in-memory relations are equal if they
+/// share the same batches
+impl PartialEq for LogicalPlan {
+    fn eq(&self, other: &LogicalPlan) -> bool {
+        match (self, other) {
+            (
+                LogicalPlan::Limit {
+                    limit: l1,
+                    input: i1,
+                    schema: s1,
+                },
+                LogicalPlan::Limit {
+                    limit: l2,
+                    input: i2,
+                    schema: s2,
+                },
+            ) => l1 == l2 && i1 == i2 && s1 == s2,
+            (
+                LogicalPlan::Projection {
+                    expr: e1,
+                    input: i1,
+                    schema: s1,
+                },
+                LogicalPlan::Projection {
+                    expr: e2,
+                    input: i2,
+                    schema: s2,
+                },
+            ) => e1 == e2 && i1 == i2 && s1 == s2,
+            (
+                LogicalPlan::Selection {
+                    expr: e1,
+                    input: i1,
+                },
+                LogicalPlan::Selection {
+                    expr: e2,
+                    input: i2,
+                },
+            ) => e1 == e2 && i1 == i2,
+            (
+                LogicalPlan::Aggregate {
+                    input: i1,
+                    group_expr: g1,
+                    aggr_expr: a1,
+                    schema: s1,
+                },
+                LogicalPlan::Aggregate {
+                    input: i2,
+                    group_expr: g2,
+                    aggr_expr: a2,
+                    schema: s2,
+                },
+            ) => i1 == i2 && g1 == g2 && a1 == a2 && s1 == s2,
+            (
+                LogicalPlan::Sort {
+                    expr: e1,
+                    input: i1,
+                    schema: s1,
+                },
+                LogicalPlan::Sort {
+                    expr: e2,
+                    input: i2,
+                    schema: s2,
+                },
+            ) => e1 == e2 && i1 == i2 && s1 == s2,
+            (
+                LogicalPlan::TableScan {
+                    schema_name: n1,
+                    table_name: t1,
+                    schema: s1,
+                    projection: p1,
+                    filters: r1,
+                    ..
+                },
+                LogicalPlan::TableScan {
+                    schema_name: n2,
+                    table_name: t2,
+                    schema: s2,
+                    projection: p2,
+                    filters: r2,
+                    ..
+                },
+            ) => n1 == n2 && t1 == t2 && s1 == s2 && p1 == p2 && r1 == r2,
+            (
+                LogicalPlan::CsvFile {
+                    filename: f1,
+                    schema: s1,
+                    has_header: h1,
+                    projection: p1,
+                    options: o1,
+                    filters: r1,
+                    ..
+                },
+                LogicalPlan::CsvFile {
+                    filename: f2,
+                    schema: s2,
+                    has_header: h2,
+                    projection: p2,
+                    options: o2,
+                    filters: r2,
+                    ..
+                },
+            ) => f1 == f2 && s1 == s2 && h1 == h2 && p1 == p2 && o1 == o2 && r1 == r2,
+            (
+                LogicalPlan::NdJsonFile {
+                    filename: f1,
+                    schema: s1,
+                    projection: p1,
+                    options: o1,
+                    filters: r1,
+                    ..
+                },
+                LogicalPlan::NdJsonFile {
+                    filename: f2,
+                    schema: s2,
+                    projection: p2,
+                    options: o2,
+                    filters: r2,
+                    ..
+                },
+            )
+            | (
+                LogicalPlan::ParquetFile {
+                    filename: f1,
+                    schema: s1,
+                    projection: p1,
+                    options: o1,
+                    filters: r1,
+                    ..
+                },
+                LogicalPlan::ParquetFile {
+                    filename: f2,
+                    schema: s2,
+                    projection: p2,
+                    options: o2,
+                    filters: r2,
+                    ..
+                },
+            ) => f1 == f2 && s1 == s2 && p1 == p2 && o1 == o2 && r1 == r2,
+            (
+                LogicalPlan::InMemory {
+                    data: d1,
+                    schema: s1,
+                    projection: p1,
+                },
+                LogicalPlan::InMemory {
+                    data: d2,
+                    schema: s2,
+                    projection: p2,
+                },
+            ) => Rc::ptr_eq(d1, d2) && s1 == s2 && p1 == p2,
+            (
+                LogicalPlan::EmptyRelation { schema: s1 },
+                LogicalPlan::EmptyRelation { schema: s2 },
+            ) => s1 == s2,
+            _ => false,
         }
     }
 }
@@ -0,0 +1,1279 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Logical query optimizer
+//!
+//! An `Optimizer` rewrites a logical plan by applying a list of `OptimizerRule`s in order,
+//! repeating the list until a pass makes no change to the plan. Every plan that an
+//! `ExecutionContext` executes is optimized, whether it was created from SQL or with the
+//! DataFrame API, and further rules can be registered on the context.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use arrow::datatypes::{DataType, Field, Schema};
+
+use super::errors::*;
+use super::logical::{Expr, LogicalPlan, Operator};
+use super::sqlplanner::{collect_expr, push_down_projection, replace_columns};
+use super::types::ScalarValue;
+use super::visitor::{
+    rewrite_expr, rewrite_plan, visit_expr, ExprRewriter, ExprVisitor, PlanRewriter,
+};
+
+/// A rule that rewrites a logical plan into an equivalent plan
+pub trait OptimizerRule {
+    /// Name of the rule, used in error messages
+    fn name(&self) -> &str;
+
+    /// Rewrite a plan, returning a plan that produces the same results. Rules are applied
+    /// repeatedly, so a rule should return an equal plan once it has nothing left to do.
+    fn optimize(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>>;
+}
+
+/// Applies a pipeline of rules to logical plans
+#[derive(Clone)]
+pub struct Optimizer {
+    rules: Vec<Rc<OptimizerRule>>,
+    max_passes: usize,
+}
+
+impl Optimizer {
+    /// Create an optimizer with the built-in rules
+    pub fn new() -> Self {
+        Optimizer::with_rules(vec![
+            Rc::new(SimplifyExpressions {}),
+            Rc::new(PredicatePushDown {}),
+            Rc::new(LimitPushDown {}),
+            Rc::new(ProjectionPushDown {}),
+            Rc::new(CommonSubexprElimination {}),
+        ])
+    }
+
+    /// Create an optimizer that applies the specified rules in order
+    pub fn with_rules(rules: Vec<Rc<OptimizerRule>>) -> Self {
+        Optimizer {
+            rules,
+            max_passes: 10,
+        }
+    }
+
+    /// Add a rule to the end of the pipeline
+    pub fn add_rule(&mut self, rule: Rc<OptimizerRule>) {
+        self.rules.push(rule);
+    }
+
+    /// Get the rules in the order that they are applied
+    pub fn rules(&self) -> &Vec<Rc<OptimizerRule>> {
+        &self.rules
+    }
+
+    /// Set the maximum number of times the pipeline is applied to a plan, which stops rules
+    /// that keep changing the plan from running forever
+    pub fn set_max_passes(&mut self, max_passes: usize) {
+        self.max_passes = max_passes;
+    }
+
+    /// Apply the rules to a plan until it stops changing or the maximum number of passes
+    /// has been made
+    pub fn optimize(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        let mut plan = plan.clone();
+        for _ in 0..self.max_passes {
+            let mut new_plan = plan.clone();
+            for rule in &self.rules {
+                new_plan = rule.optimize(&new_plan).map_err(|e| {
+                    ExecutionError::General(format!(
+                        "Optimizer rule '{}' failed: {}",
+                        rule.name(),
+                        e
+                    ))
+                })?;
+            }
+            if new_plan == plan {
+                break;
+            }
+            plan = new_plan;
+        }
+        Ok(plan)
+    }
+}
+
+/// Evaluate constant expressions once when planning rather than for every batch, and remove
+/// boolean expressions and casts that do not change the result
+pub struct SimplifyExpressions {}
+
+impl OptimizerRule for SimplifyExpressions {
+    fn name(&self) -> &str {
+        "simplify_expressions"
+    }
+
+    fn optimize(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        rewrite_plan(plan, &mut PlanSimplifier {})
+    }
+}
+
+/// Simplifies the expressions of each plan, and removes filters that keep every row
+struct PlanSimplifier {}
+
+impl PlanRewriter for PlanSimplifier {
+    fn rewrite(&mut self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        let expr = plan.expressions();
+        if expr.is_empty() {
+            return Ok(plan.clone());
+        }
+        let schema = plan.expression_schema();
+        let expr = expr
+            .into_iter()
+            .map(|e| simplify(e, schema))
+            .collect::<Result<Vec<Expr>>>()?;
+        if let LogicalPlan::Selection { ref input, .. } = plan.as_ref() {
+            // the filter keeps every row
+            if expr[0] == Expr::Literal(ScalarValue::Boolean(true)) {
+                return Ok(input.clone());
+            }
+        }
+        Ok(Rc::new(plan.with_new_expressions(expr)))
+    }
+}
+
+/// Simplify an expression over an input with the specified schema
+pub fn simplify(expr: &Expr, schema: &Schema) -> Result<Expr> {
+    rewrite_expr(expr, &mut Simplifier { schema })
+}
+
+/// Simplifies expressions whose inputs have already been simplified
+struct Simplifier<'a> {
+    schema: &'a Schema,
+}
+
+impl<'a> ExprRewriter for Simplifier<'a> {
+    fn rewrite(&mut self, expr: Expr) -> Result<Expr> {
+        Ok(match expr {
+            Expr::Cast { expr, data_type } => {
+                if let Expr::Literal(ref v) = *expr {
+                    if let Some(v) = v.cast_to(&data_type) {
+                        return Ok(Expr::Literal(v));
+                    }
+                }
+                // the cast is kept if the type of its input cannot be determined
+                if expr.get_type(self.schema).as_ref() == Ok(&data_type) {
+                    return Ok(expr.as_ref().clone());
+                }
+                Expr::Cast { expr, data_type }
+            }
+            Expr::BinaryExpr { left, op, right } => simplify_binary(
+                left.as_ref().clone(),
+                &op,
+                right.as_ref().clone(),
+                self.schema,
+            ),
+            Expr::Not(expr) => match expr.as_ref() {
+                Expr::Not(ref expr) => expr.as_ref().clone(),
+                Expr::Literal(ScalarValue::Boolean(b)) => Expr::Literal(ScalarValue::Boolean(!b)),
+                _ => Expr::Not(expr.clone()),
+            },
+            other => other,
+        })
+    }
+}
+
+/// Simplify a binary expression whose operands have already been simplified
+fn simplify_binary(left: Expr, op: &Operator, right: Expr, schema: &Schema) -> Expr {
+    if let (Expr::Literal(ref l), Expr::Literal(ref r)) = (&left, &right) {
+        if let Some(v) = evaluate_binary(l, op, r) {
+            return Expr::Literal(v);
+        }
+    }
+    match (op, &left, &right) {
+        (Operator::And, Expr::Literal(ScalarValue::Boolean(false)), _)
+        | (Operator::And, _, Expr::Literal(ScalarValue::Boolean(false))) => {
+            return Expr::Literal(ScalarValue::Boolean(false))
+        }
+        (Operator::And, Expr::Literal(ScalarValue::Boolean(true)), _)
+        | (Operator::Or, Expr::Literal(ScalarValue::Boolean(false)), _) => return right,
+        (Operator::And, _, Expr::Literal(ScalarValue::Boolean(true)))
+        | (Operator::Or, _, Expr::Literal(ScalarValue::Boolean(false))) => return left,
+        (Operator::Or, Expr::Literal(ScalarValue::Boolean(true)), _)
+        | (Operator::Or, _, Expr::Literal(ScalarValue::Boolean(true))) => {
+            return Expr::Literal(ScalarValue::Boolean(true))
+        }
+        _ => {}
+    }
+    if is_comparison(op) {
+        // compare a column with a literal of the column's type rather than casting every value
+        // of the column to the type of the literal
+        if let Some((column, literal)) = narrow_comparison(&left, &right, schema) {
+            return Expr::BinaryExpr {
+                left: Rc::new(column),
+                op: op.clone(),
+                right: Rc::new(literal),
+            };
+        }
+        if let Some((column, literal)) = narrow_comparison(&right, &left, schema) {
+            return Expr::BinaryExpr {
+                left: Rc::new(literal),
+                op: op.clone(),
+                right: Rc::new(column),
+            };
+        }
+    }
+    Expr::BinaryExpr {
+        left: Rc::new(left),
+        op: op.clone(),
+        right: Rc::new(right),
+    }
+}
+
+fn is_comparison(op: &Operator) -> bool {
+    match op {
+        Operator::Eq
+        | Operator::NotEq
+        | Operator::Lt
+        | Operator::LtEq
+        | Operator::Gt
+        | Operator::GtEq => true,
+        _ => false,
+    }
+}
+
+/// Match a cast of an integer column to a type at least as wide with the same signedness,
+/// compared with a literal that fits in the column's type, returning the column and the literal
+/// converted to the column's type. Other casts can change the values of the column, for example
+/// by wrapping, so removing them would change the result of the comparison.
+fn narrow_comparison(cast: &Expr, literal: &Expr, schema: &Schema) -> Option<(Expr, Expr)> {
+    match (cast, literal) {
+        (
+            Expr::Cast {
+                ref expr,
+                ref data_type,
+            },
+            Expr::Literal(ref v),
+        ) => match expr.as_ref() {
+            Expr::Column(i) => {
+                let column_type = schema.column(*i).data_type();
+                let (column_signed, column_bits) = integer_type(column_type)?;
+                let (cast_signed, cast_bits) = integer_type(data_type)?;
+                integer_type(&v.get_datatype())?;
+                if column_signed != cast_signed || cast_bits < column_bits {
+                    return None;
+                }
+                let narrowed = v.cast_to(column_type)?;
+                if narrowed.cast_to(&v.get_datatype())? == *v {
+                    Some((Expr::Column(*i), Expr::Literal(narrowed)))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Get whether an integer type is signed and its width in bits, or `None` for other types
+fn integer_type(data_type: &DataType) -> Option<(bool, usize)> {
+    match data_type {
+        DataType::Int8 => Some((true, 8)),
+        DataType::Int16 => Some((true, 16)),
+        DataType::Int32 => Some((true, 32)),
+        DataType::Int64 => Some((true, 64)),
+        DataType::UInt8 => Some((false, 8)),
+        DataType::UInt16 => Some((false, 16)),
+        DataType::UInt32 => Some((false, 32)),
+        DataType::UInt64 => Some((false, 64)),
+        _ => None,
+    }
+}
+
+/// Evaluate a binary operation on two literals, returning `None` if it cannot be evaluated
+/// when planning, in which case it is left to be evaluated when the query runs
+fn evaluate_binary(left: &ScalarValue, op: &Operator, right: &ScalarValue) -> Option<ScalarValue> {
+    let compare = |f: fn(Ordering) -> bool| {
+        left.compare(right)
+            .map(|ordering| ScalarValue::Boolean(f(ordering)))
+    };
+    match op {
+        Operator::Plus => left.checked_add(right),
+        Operator::Minus => left.checked_sub(right),
+        Operator::Multiply => left.checked_mul(right),
+        Operator::Divide => left.checked_div(right),
+        Operator::Modulus => left.checked_rem(right),
+        Operator::Eq => compare(|o| o == Ordering::Equal),
+        Operator::NotEq => compare(|o| o != Ordering::Equal),
+        Operator::Lt => compare(|o| o == Ordering::Less),
+        Operator::LtEq => compare(|o| o != Ordering::Greater),
+        Operator::Gt => compare(|o| o == Ordering::Greater),
+        Operator::GtEq => compare(|o| o != Ordering::Less),
+        Operator::And => match (left, right) {
+            (ScalarValue::Boolean(l), ScalarValue::Boolean(r)) => {
+                Some(ScalarValue::Boolean(*l && *r))
+            }
+            _ => None,
+        },
+        Operator::Or => match (left, right) {
+            (ScalarValue::Boolean(l), ScalarValue::Boolean(r)) => {
+                Some(ScalarValue::Boolean(*l || *r))
+            }
+            _ => None,
+        },
+        Operator::Like | Operator::ILike => None,
+    }
+}
+
+/// Move limits below projections, so that a limit is applied directly to the rows that are
+/// read and the scan stops as soon as enough rows have been read, and combine nested limits
+pub struct LimitPushDown {}
+
+impl OptimizerRule for LimitPushDown {
+    fn name(&self) -> &str {
+        "limit_push_down"
+    }
+
+    fn optimize(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        rewrite_plan(plan, &mut LimitPusher {})
+    }
+}
+
+/// Moves a limit below the projections directly below it and combines it with a limit below
+/// them. The plans below the limit have already been rewritten.
+struct LimitPusher {}
+
+impl PlanRewriter for LimitPusher {
+    fn rewrite(&mut self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        match plan.as_ref() {
+            LogicalPlan::Limit {
+                limit,
+                ref input,
+                ref schema,
+            } => match input.as_ref() {
+                LogicalPlan::Projection {
+                    ref expr,
+                    input: ref projection_input,
+                    schema: ref projection_schema,
+                } => Ok(Rc::new(LogicalPlan::Projection {
+                    expr: expr.clone(),
+                    input: self.rewrite(&Rc::new(LogicalPlan::Limit {
+                        limit: *limit,
+                        input: projection_input.clone(),
+                        schema: projection_input.schema().clone(),
+                    }))?,
+                    schema: projection_schema.clone(),
+                })),
+                LogicalPlan::Limit {
+                    limit: inner_limit,
+                    input: ref inner_input,
+                    ..
+                } => self.rewrite(&Rc::new(LogicalPlan::Limit {
+                    limit: *limit.min(inner_limit),
+                    input: inner_input.clone(),
+                    schema: schema.clone(),
+                })),
+                _ => Ok(plan.clone()),
+            },
+            _ => Ok(plan.clone()),
+        }
+    }
+}
+
+/// Read only the columns of each table or file that the plan uses
+pub struct ProjectionPushDown {}
+
+impl OptimizerRule for ProjectionPushDown {
+    fn name(&self) -> &str {
+        "projection_push_down"
+    }
+
+    fn optimize(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        // every column of the result is needed
+        let projection: HashSet<usize> = (0..plan.schema().columns().len()).collect();
+        Ok(push_down_projection(plan, &projection))
+    }
+}
+
+/// Move filters as close to the data as possible, so that fewer rows pass through the rest of
+/// the plan, and give the filters on a table or file to its data source. The filters are still
+/// applied above the scan since data sources do not have to remove the rows that fail them.
+pub struct PredicatePushDown {}
+
+impl OptimizerRule for PredicatePushDown {
+    fn name(&self) -> &str {
+        "predicate_push_down"
+    }
+
+    fn optimize(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        Ok(push_down_predicates(plan, vec![]))
+    }
+}
+
+/// Split a predicate into the expressions that are combined with AND
+pub fn split_conjunction(expr: &Expr, accum: &mut Vec<Expr>) {
+    match expr {
+        Expr::BinaryExpr {
+            ref left,
+            op: Operator::And,
+            ref right,
+        } => {
+            split_conjunction(left, accum);
+            split_conjunction(right, accum);
+        }
+        _ => accum.push(expr.clone()),
+    }
+}
+
+/// Combine predicates with AND, returning `None` if there are none
+pub fn conjunction(predicates: Vec<Expr>) -> Option<Expr> {
+    predicates.into_iter().fold(None, |acc, p| match acc {
+        Some(left) => Some(Expr::BinaryExpr {
+            left: Rc::new(left),
+            op: Operator::And,
+            right: Rc::new(p),
+        }),
+        None => Some(p),
+    })
+}
+
+/// Apply predicates to a plan, pushing them below the plan where that does not change results
+fn push_down_predicates(plan: &Rc<LogicalPlan>, predicates: Vec<Expr>) -> Rc<LogicalPlan> {
+    match plan.as_ref() {
+        LogicalPlan::Selection {
+            ref expr,
+            ref input,
+        } => {
+            let mut predicates = predicates;
+            split_conjunction(expr, &mut predicates);
+            push_down_predicates(input, predicates)
+        }
+        LogicalPlan::Projection {
+            ref expr,
+            ref input,
+            ref schema,
+        } => {
+            // predicates on columns that the projection computes stay above it, so that the
+            // expressions are not evaluated twice
+            let (below, above): (Vec<Expr>, Vec<Expr>) = predicates.into_iter().partition(|p| {
+                let mut columns = HashSet::new();
+                collect_expr(p, &mut columns);
+                columns.iter().all(|i| !is_common_candidate(&expr[*i]))
+            });
+            // rewrite the predicates in terms of the input to the projection
+            let below = below.iter().map(|p| replace_columns(p, expr)).collect();
+            let projection = Rc::new(LogicalPlan::Projection {
+                expr: expr.clone(),
+                input: push_down_predicates(input, below),
+                schema: schema.clone(),
+            });
+            selection(projection, above)
+        }
+        LogicalPlan::Sort {
+            ref expr,
+            ref input,
+            ref schema,
+        } => Rc::new(LogicalPlan::Sort {
+            expr: expr.clone(),
+            input: push_down_predicates(input, predicates),
+            schema: schema.clone(),
+        }),
+        LogicalPlan::Aggregate {
+            ref input,
+            ref group_expr,
+            ref aggr_expr,
+            ref schema,
+        } => {
+            // only predicates on the grouping columns can be applied before aggregating
+            let (below, above): (Vec<Expr>, Vec<Expr>) = predicates.into_iter().partition(|p| {
+                let mut columns = HashSet::new();
+                collect_expr(p, &mut columns);
+                columns.iter().all(|i| *i < group_expr.len())
+            });
+            let below = below
+                .iter()
+                .map(|p| replace_columns(p, group_expr))
+                .collect();
+            let aggregate = Rc::new(LogicalPlan::Aggregate {
+                input: push_down_predicates(input, below),
+                group_expr: group_expr.clone(),
+                aggr_expr: aggr_expr.clone(),
+                schema: schema.clone(),
+            });
+            selection(aggregate, above)
+        }
+        LogicalPlan::Limit {
+            limit,
+            ref input,
+            ref schema,
+        } => {
+            // filtering before a limit would change which rows are returned
+            let limit = Rc::new(LogicalPlan::Limit {
+                limit: *limit,
+                input: push_down_predicates(input, vec![]),
+                schema: schema.clone(),
+            });
+            selection(limit, predicates)
+        }
+        LogicalPlan::TableScan {
+            ref filters,
+            ref projection,
+            ..
+        }
+        | LogicalPlan::CsvFile {
+            ref filters,
+            ref projection,
+            ..
+        }
+        | LogicalPlan::NdJsonFile {
+            ref filters,
+            ref projection,
+            ..
+        }
+        | LogicalPlan::ParquetFile {
+            ref filters,
+            ref projection,
+            ..
+        } => {
+            // scan filters refer to the columns of the table rather than the projected columns
+            let columns: Option<Vec<Expr>> = projection
+                .as_ref()
+                .map(|p| p.iter().map(|i| Expr::Column(*i)).collect());
+            let mut scan_filters = filters.clone();
+            for p in &predicates {
+                let p = match columns {
+                    Some(ref c) => replace_columns(p, c),
+                    None => p.clone(),
+                };
+                if !scan_filters.contains(&p) {
+                    scan_filters.push(p);
+                }
+            }
+            selection(Rc::new(plan.with_filters(scan_filters)), predicates)
+        }
+        LogicalPlan::InMemory { .. } | LogicalPlan::EmptyRelation { .. } => {
+            selection(plan.clone(), predicates)
+        }
+    }
+}
+
+/// Evaluate expressions that a projection and the filter below it use more than once in a
+/// separate projection, so that each one is evaluated once per batch. Expressions that the
+/// filter uses are evaluated below it, and expressions that only the projection uses are
+/// evaluated above it, so that they are not evaluated for rows that the filter removes. Scalar
+/// functions are assumed to return the same result for the same arguments.
+pub struct CommonSubexprElimination {}
+
+impl OptimizerRule for CommonSubexprElimination {
+    fn name(&self) -> &str {
+        "common_subexpr_elimination"
+    }
+
+    fn optimize(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        rewrite_plan(plan, &mut CommonSubexprHoister {})
+    }
+}
+
+/// Evaluates the common subexpressions of each projection, and the filter directly below it,
+/// in separate projections. The plans below the projection have already been rewritten.
+struct CommonSubexprHoister {}
+
+impl PlanRewriter for CommonSubexprHoister {
+    fn rewrite(&mut self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        let (expr, input, schema) = match plan.as_ref() {
+            LogicalPlan::Projection {
+                ref expr,
+                ref input,
+                ref schema,
+            } => (expr, input, schema),
+            _ => return Ok(plan.clone()),
+        };
+        let (input, expr) = match input.as_ref() {
+            // a filter directly below the projection can share expressions with it
+            LogicalPlan::Selection {
+                expr: ref predicate,
+                input: ref selection_input,
+            } => {
+                let mut counts = vec![];
+                for e in expr.iter().chain(Some(predicate)) {
+                    count_subexprs(e, &mut counts)?;
+                }
+                // only the common subexpressions of the predicate are evaluated below the
+                // filter, since they are evaluated for every row by the filter anyway
+                let mut below = vec![];
+                replace_common(predicate, &counts, 0, &mut below)?;
+                let below: Vec<(Expr, usize)> = below.into_iter().map(|e| (e, 2)).collect();
+
+                let mut all_expr = expr.clone();
+                all_expr.push(predicate.clone());
+                let (lower, mut rewritten) =
+                    hoist_subexprs(&all_expr, &below, selection_input.schema())?;
+                let selection_input = match lower {
+                    Some(lower) => Rc::new(project(lower, selection_input)?),
+                    None => selection_input.clone(),
+                };
+                let predicate = rewritten.pop().unwrap();
+                let selection = Rc::new(LogicalPlan::Selection {
+                    expr: predicate,
+                    input: selection_input,
+                });
+                hoist_projection(&rewritten, &selection)?
+            }
+            _ => hoist_projection(expr, input)?,
+        };
+        Ok(Rc::new(LogicalPlan::Projection {
+            expr,
+            input,
+            schema: schema.clone(),
+        }))
+    }
+}
+
+/// Evaluate the subexpressions that occur more than once in a list of expressions in a
+/// projection of the input, returning the new input and the list rewritten to refer to it
+fn hoist_projection(
+    expr: &[Expr],
+    input: &Rc<LogicalPlan>,
+) -> Result<(Rc<LogicalPlan>, Vec<Expr>)> {
+    let mut counts = vec![];
+    for e in expr {
+        count_subexprs(e, &mut counts)?;
+    }
+    match hoist_subexprs(expr, &counts, input.schema())? {
+        (Some(lower), rewritten) => Ok((Rc::new(project(lower, input)?), rewritten)),
+        (None, rewritten) => Ok((input.clone(), rewritten)),
+    }
+}
+
+/// Replace the subexpressions whose count is more than one in a list of expressions. If there
+/// are any, return the expressions of a projection that evaluates them along with the input
+/// columns that are still needed, and the list rewritten to refer to the projection.
+fn hoist_subexprs(
+    expr: &[Expr],
+    counts: &[(Expr, usize)],
+    schema: &Schema,
+) -> Result<(Option<Vec<Expr>>, Vec<Expr>)> {
+    let mut common = vec![];
+    let n = schema.columns().len();
+    let rewritten = expr
+        .iter()
+        .map(|e| replace_common(e, counts, n, &mut common))
+        .collect::<Result<Vec<Expr>>>()?;
+    if common.is_empty() {
+        return Ok((None, rewritten));
+    }
+
+    // the projection only keeps the input columns that are still used
+    let mut used = HashSet::new();
+    for e in &rewritten {
+        collect_expr(e, &mut used);
+    }
+    let mut columns: Vec<usize> = used.into_iter().filter(|i| *i < n).collect();
+    columns.sort();
+    let mut mapping = vec![Expr::Literal(ScalarValue::Null); n + common.len()];
+    for (pos, i) in columns.iter().enumerate() {
+        mapping[*i] = Expr::Column(pos);
+    }
+    for i in 0..common.len() {
+        mapping[n + i] = Expr::Column(columns.len() + i);
+    }
+
+    let mut lower: Vec<Expr> = columns.iter().map(|i| Expr::Column(*i)).collect();
+    lower.extend(common);
+    let rewritten = rewritten
+        .iter()
+        .map(|e| replace_columns(e, &mapping))
+        .collect();
+    Ok((Some(lower), rewritten))
+}
+
+/// Determine whether an expression does enough work to be worth evaluating only once
+fn is_common_candidate(expr: &Expr) -> bool {
+    match expr {
+        Expr::Column(_) | Expr::ColumnRef { .. } | Expr::Literal(_) => false,
+        Expr::Sort { .. } | Expr::AggregateFunction { .. } => false,
+        Expr::Cast { ref expr, .. } => match expr.as_ref() {
+            Expr::Column(_) | Expr::Literal(_) => false,
+            _ => true,
+        },
+        _ => true,
+    }
+}
+
+/// Count the occurrences of each candidate subexpression
+fn count_subexprs(expr: &Expr, counts: &mut Vec<(Expr, usize)>) -> Result<()> {
+    visit_expr(expr, &mut SubexprCounter { counts })
+}
+
+/// Counts the occurrences of candidate subexpressions, other than the arguments of aggregate
+/// functions
+struct SubexprCounter<'a> {
+    counts: &'a mut Vec<(Expr, usize)>,
+}
+
+impl<'a> ExprVisitor for SubexprCounter<'a> {
+    fn pre_visit(&mut self, expr: &Expr) -> Result<bool> {
+        if let Expr::AggregateFunction { .. } = expr {
+            return Ok(false);
+        }
+        if is_common_candidate(expr) {
+            match self.counts.iter_mut().find(|(e, _)| e == expr) {
+                Some(entry) => entry.1 += 1,
+                None => self.counts.push((expr.clone(), 1)),
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Replace the outermost subexpressions that occur more than once with references to the
+/// columns that follow the `n` input columns, adding them to `common`
+fn replace_common(
+    expr: &Expr,
+    counts: &[(Expr, usize)],
+    n: usize,
+    common: &mut Vec<Expr>,
+) -> Result<Expr> {
+    rewrite_expr(expr, &mut CommonReplacer { counts, n, common })
+}
+
+/// Replaces common subexpressions before the expressions inside them are visited, so that
+/// only the outermost ones are replaced
+struct CommonReplacer<'a> {
+    counts: &'a [(Expr, usize)],
+    n: usize,
+    common: &'a mut Vec<Expr>,
+}
+
+impl<'a> ExprRewriter for CommonReplacer<'a> {
+    fn pre_rewrite(&mut self, expr: &Expr) -> Result<Option<Expr>> {
+        if let Expr::AggregateFunction { .. } = expr {
+            return Ok(Some(expr.clone()));
+        }
+        if !self.counts.iter().any(|(e, count)| *count > 1 && e == expr) {
+            return Ok(None);
+        }
+        let i = match self.common.iter().position(|e| e == expr) {
+            Some(i) => i,
+            None => {
+                self.common.push(expr.clone());
+                self.common.len() - 1
+            }
+        };
+        Ok(Some(Expr::Column(self.n + i)))
+    }
+
+    fn rewrite(&mut self, expr: Expr) -> Result<Expr> {
+        Ok(expr)
+    }
+}
+
+/// Create a projection of expressions that are evaluated against the output of a plan
+fn project(expr: Vec<Expr>, input: &Rc<LogicalPlan>) -> Result<LogicalPlan> {
+    let input_schema = input.schema();
+    let fields = expr
+        .iter()
+        .enumerate()
+        .map(|(i, e)| match e {
+            Expr::Column(c) => Ok(input_schema.column(*c).clone()),
+            _ => Ok(Field::new(
+                &format!("expr{}", i),
+                e.get_type(input_schema)?,
+                true,
+            )),
+        })
+        .collect::<Result<Vec<Field>>>()?;
+    Ok(LogicalPlan::Projection {
+        expr,
+        input: input.clone(),
+        schema: Rc::new(Schema::new(fields)),
+    })
+}
+
+/// Filter the output of a plan with predicates, if there are any
+fn selection(input: Rc<LogicalPlan>, predicates: Vec<Expr>) -> Rc<LogicalPlan> {
+    match conjunction(predicates) {
+        Some(expr) => Rc::new(LogicalPlan::Selection { expr, input }),
+        None => input,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::types::ScalarValue;
+    use super::*;
+    use arrow::datatypes::*;
+    use std::cell::Cell;
+
+    /// Removes one level of nested limits per pass
+    struct MergeLimits {
+        calls: Cell<usize>,
+    }
+
+    impl OptimizerRule for MergeLimits {
+        fn name(&self) -> &str {
+            "merge_limits"
+        }
+
+        fn optimize(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+            self.calls.set(self.calls.get() + 1);
+            match plan.as_ref() {
+                LogicalPlan::Limit {
+                    limit,
+                    ref input,
+                    ref schema,
+                } => match input.as_ref() {
+                    LogicalPlan::Limit {
+                        limit: inner_limit,
+                        input: ref inner_input,
+                        ..
+                    } => Ok(Rc::new(LogicalPlan::Limit {
+                        limit: *limit.min(inner_limit),
+                        input: inner_input.clone(),
+                        schema: schema.clone(),
+                    })),
+                    _ => Ok(plan.clone()),
+                },
+                _ => Ok(plan.clone()),
+            }
+        }
+    }
+
+    struct FailingRule {}
+
+    impl OptimizerRule for FailingRule {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn optimize(&self, _plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+            Err(ExecutionError::General("oops".to_string()))
+        }
+    }
+
+    fn nested_limits(limits: &[usize]) -> Rc<LogicalPlan> {
+        let schema = Rc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let mut plan = Rc::new(LogicalPlan::EmptyRelation {
+            schema: schema.clone(),
+        });
+        for limit in limits {
+            plan = Rc::new(LogicalPlan::Limit {
+                limit: *limit,
+                input: plan,
+                schema: schema.clone(),
+            });
+        }
+        plan
+    }
+
+    #[test]
+    fn test_optimize_to_fixpoint() {
+        let rule = Rc::new(MergeLimits {
+            calls: Cell::new(0),
+        });
+        let optimizer = Optimizer::with_rules(vec![rule.clone()]);
+        let plan = optimizer.optimize(&nested_limits(&[5, 3, 10])).unwrap();
+        assert_eq!(nested_limits(&[3]), plan);
+        // two passes merge the limits and a third finds nothing to change
+        assert_eq!(3, rule.calls.get());
+
+        // the number of passes is limited
+        let mut optimizer = Optimizer::with_rules(vec![Rc::new(MergeLimits {
+            calls: Cell::new(0),
+        })]);
+        optimizer.set_max_passes(1);
+        let plan = optimizer.optimize(&nested_limits(&[5, 3, 10])).unwrap();
+        assert_eq!(nested_limits(&[5, 3]), plan);
+    }
+
+    #[test]
+    fn test_projection_push_down() {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Int32, false),
+            Field::new("c", DataType::Int32, false),
+        ]));
+        let scan = Rc::new(LogicalPlan::TableScan {
+            schema_name: "default".to_string(),
+            table_name: "t".to_string(),
+            schema: schema.clone(),
+            projection: None,
+            projected_schema: schema.clone(),
+            filters: vec![],
+        });
+        let plan = Rc::new(LogicalPlan::Aggregate {
+            input: scan,
+            group_expr: vec![Expr::Column(2)],
+            aggr_expr: vec![],
+            schema: Rc::new(Schema::new(vec![Field::new("c", DataType::Int32, false)])),
+        });
+        let plan = Optimizer::new().optimize(&plan).unwrap();
+        assert_eq!(
+            "Aggregate: groupBy=[[#0]], aggr=[[]]\n  TableScan: t projection=Some([2])",
+            format!("{:?}", plan)
+        );
+        assert_eq!(1, plan.schema().columns().len());
+    }
+
+    #[test]
+    fn test_projection_push_down_remaps_columns() {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Int64, false),
+            Field::new("c", DataType::Int64, false),
+            Field::new("d", DataType::Int64, false),
+        ]));
+        let scan = Rc::new(LogicalPlan::TableScan {
+            schema_name: "default".to_string(),
+            table_name: "t".to_string(),
+            schema: schema.clone(),
+            projection: None,
+            projected_schema: schema.clone(),
+            filters: vec![],
+        });
+        // SELECT d, b FROM t WHERE c < 5 ORDER BY b LIMIT 10
+        let selection = Rc::new(LogicalPlan::Selection {
+            expr: lt(2, 5),
+            input: scan,
+        });
+        let sort = Rc::new(LogicalPlan::Sort {
+            expr: vec![Expr::Sort {
+                expr: Rc::new(Expr::Column(1)),
+                asc: true,
+            }],
+            input: selection,
+            schema: schema.clone(),
+        });
+        let limit = Rc::new(LogicalPlan::Limit {
+            limit: 10,
+            input: sort,
+            schema: schema.clone(),
+        });
+        let plan = Rc::new(LogicalPlan::Projection {
+            expr: vec![Expr::Column(3), Expr::Column(1)],
+            input: limit,
+            schema: Rc::new(Schema::new(vec![
+                Field::new("d", DataType::Int64, false),
+                Field::new("b", DataType::Int64, false),
+            ])),
+        });
+        let plan = push_down_projection(&plan, &[0, 1].iter().cloned().collect());
+        assert_eq!(
+            "Projection: #2, #0\
+             \n  Limit: 10\
+             \n    Sort: #0 ASC\
+             \n      Selection: #1 Lt Int64(5)\
+             \n        TableScan: t projection=Some([1, 2, 3])",
+            format!("{:?}", plan)
+        );
+        let names: Vec<&str> = plan
+            .schema()
+            .columns()
+            .iter()
+            .map(|c| c.name().as_str())
+            .collect();
+        assert_eq!(vec!["d", "b"], names);
+        match plan.as_ref() {
+            LogicalPlan::Projection { ref input, .. } => {
+                assert_eq!(3, input.schema().columns().len())
+            }
+            _ => panic!(),
+        }
+
+        // only the columns in the projection are returned
+        let plan = push_down_projection(&plan, &[1].iter().cloned().collect());
+        assert_eq!(
+            "Projection: #0\
+             \n  Limit: 10\
+             \n    Sort: #0 ASC\
+             \n      Selection: #1 Lt Int64(5)\
+             \n        TableScan: t projection=Some([1, 2])",
+            format!("{:?}", plan)
+        );
+    }
+
+    fn lt(column: usize, value: i64) -> Expr {
+        Expr::BinaryExpr {
+            left: Rc::new(Expr::Column(column)),
+            op: Operator::Lt,
+            right: Rc::new(Expr::Literal(ScalarValue::Int64(value))),
+        }
+    }
+
+    #[test]
+    fn test_predicate_push_down() {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Int64, false),
+        ]));
+        let scan = Rc::new(LogicalPlan::TableScan {
+            schema_name: "default".to_string(),
+            table_name: "t".to_string(),
+            schema: schema.clone(),
+            projection: None,
+            projected_schema: schema.clone(),
+            filters: vec![],
+        });
+        // SELECT b, a FROM (SELECT a, b FROM t GROUP BY a, b) WHERE b < 1 AND a < 2
+        let aggregate = Rc::new(LogicalPlan::Aggregate {
+            input: scan,
+            group_expr: vec![Expr::Column(0), Expr::Column(1)],
+            aggr_expr: vec![],
+            schema: schema.clone(),
+        });
+        let projection = Rc::new(LogicalPlan::Projection {
+            expr: vec![Expr::Column(1), Expr::Column(0)],
+            input: aggregate,
+            schema: schema.clone(),
+        });
+        let plan = Rc::new(LogicalPlan::Selection {
+            expr: conjunction(vec![lt(0, 1), lt(1, 2)]).unwrap(),
+            input: projection,
+        });
+        let optimizer = Optimizer::with_rules(vec![Rc::new(PredicatePushDown {})]);
+        let plan = optimizer.optimize(&plan).unwrap();
+        assert_eq!(
+            "Projection: #1, #0\n  \
+             Aggregate: groupBy=[[#0, #1]], aggr=[[]]\n    \
+             Selection: #1 Lt Int64(1) And #0 Lt Int64(2)\n      \
+             TableScan: t projection=None filters=[#1 Lt Int64(1), #0 Lt Int64(2)]",
+            format!("{:?}", plan)
+        );
+
+        // predicates are not moved below a limit
+        let plan = Rc::new(LogicalPlan::Selection {
+            expr: lt(0, 1),
+            input: nested_limits(&[5]),
+        });
+        assert_eq!(plan, optimizer.optimize(&plan).unwrap());
+    }
+
+    fn binary(left: &Expr, op: Operator, right: &Expr) -> Expr {
+        Expr::BinaryExpr {
+            left: Rc::new(left.clone()),
+            op,
+            right: Rc::new(right.clone()),
+        }
+    }
+
+    #[test]
+    fn test_simplify() {
+        let schema = Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Boolean, false),
+        ]);
+        let int64 = |v| Expr::Literal(ScalarValue::Int64(v));
+        let boolean = |v| Expr::Literal(ScalarValue::Boolean(v));
+        let cast_a = Expr::Cast {
+            expr: Rc::new(Expr::Column(0)),
+            data_type: DataType::Int64,
+        };
+        let b = Expr::Column(1);
+
+        // CAST(a AS Int64) > 1 + 2
+        let expr = cast_a.gt(&binary(&int64(1), Operator::Plus, &int64(2)));
+        assert_eq!(
+            "#0 Gt Int32(3)",
+            format!("{:?}", simplify(&expr, &schema).unwrap())
+        );
+        // the literal does not fit in the column's type
+        let expr = cast_a.lt(&int64(1 << 40));
+        assert_eq!(
+            "CAST(#0 AS Int64) Lt Int64(1099511627776)",
+            format!("{:?}", simplify(&expr, &schema).unwrap())
+        );
+        // casts that can change the column's values are kept, for example 257 is 1 as an Int8
+        let int64_schema = Schema::new(vec![Field::new("c", DataType::Int64, false)]);
+        let cast_c = |data_type| Expr::Cast {
+            expr: Rc::new(Expr::Column(0)),
+            data_type,
+        };
+        for data_type in vec![DataType::Int8, DataType::UInt64] {
+            let expr = cast_c(data_type).eq(&int64(1));
+            assert_eq!(expr, simplify(&expr, &int64_schema).unwrap());
+        }
+        // CAST(5 AS Float64)
+        let expr = Expr::Cast {
+            expr: Rc::new(int64(5)),
+            data_type: DataType::Float64,
+        };
+        assert_eq!(
+            "Float64(5.0)",
+            format!("{:?}", simplify(&expr, &schema).unwrap())
+        );
+        // casts of literals that are out of the range of the type are not folded, for example
+        // CAST(300 AS UInt8), CAST(-1 AS UInt32) and CAST(1e10 AS Int32)
+        for (value, data_type) in vec![
+            (ScalarValue::Int64(300), DataType::UInt8),
+            (ScalarValue::Int64(-1), DataType::UInt32),
+            (ScalarValue::Float64(1e10), DataType::Int32),
+            (ScalarValue::Float64(1e300), DataType::Float32),
+        ] {
+            let expr = Expr::Cast {
+                expr: Rc::new(Expr::Literal(value)),
+                data_type,
+            };
+            assert_eq!(expr, simplify(&expr, &schema).unwrap());
+        }
+        // CAST(-1.5 AS Int8) truncates the value
+        let expr = Expr::Cast {
+            expr: Rc::new(Expr::Literal(ScalarValue::Float64(-1.5))),
+            data_type: DataType::Int8,
+        };
+        assert_eq!(
+            Expr::Literal(ScalarValue::Int8(-1)),
+            simplify(&expr, &schema).unwrap()
+        );
+        // division by zero is left to fail when the query runs
+        let expr = binary(&int64(1), Operator::Divide, &int64(0));
+        assert_eq!(expr, simplify(&expr, &schema).unwrap());
+
+        // boolean identities
+        let not_not_b = Expr::Not(Rc::new(Expr::Not(Rc::new(b.clone()))));
+        assert_eq!(b, simplify(&not_not_b, &schema).unwrap());
+        assert_eq!(
+            b,
+            simplify(&binary(&boolean(true), Operator::And, &b), &schema).unwrap()
+        );
+        assert_eq!(
+            boolean(false),
+            simplify(&binary(&b, Operator::And, &boolean(false)), &schema).unwrap()
+        );
+        assert_eq!(
+            b,
+            simplify(&binary(&boolean(false), Operator::Or, &b), &schema).unwrap()
+        );
+        assert_eq!(
+            boolean(true),
+            simplify(&binary(&b, Operator::Or, &int64(1).lt(&int64(2))), &schema).unwrap()
+        );
+
+        // a filter that keeps every row is removed
+        let plan = Rc::new(LogicalPlan::Selection {
+            expr: binary(&boolean(true), Operator::And, &int64(1).lt(&int64(2))),
+            input: nested_limits(&[5]),
+        });
+        let optimizer = Optimizer::with_rules(vec![Rc::new(SimplifyExpressions {})]);
+        assert_eq!(nested_limits(&[5]), optimizer.optimize(&plan).unwrap());
+    }
+
+    #[test]
+    fn test_limit_push_down() {
+        let input = nested_limits(&[3]);
+        let projection = Rc::new(LogicalPlan::Projection {
+            expr: vec![Expr::Column(0)],
+            input: input.clone(),
+            schema: input.schema().clone(),
+        });
+        let plan = Rc::new(LogicalPlan::Limit {
+            limit: 5,
+            input: projection,
+            schema: input.schema().clone(),
+        });
+        let optimizer = Optimizer::with_rules(vec![Rc::new(LimitPushDown {})]);
+        assert_eq!(
+            "Projection: #0\n  Limit: 3\n    EmptyRelation",
+            format!("{:?}", optimizer.optimize(&plan).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_common_subexpr_elimination() {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Int64, false),
+            Field::new("c", DataType::Int64, false),
+        ]));
+        let scan = Rc::new(LogicalPlan::TableScan {
+            schema_name: "default".to_string(),
+            table_name: "t".to_string(),
+            schema: schema.clone(),
+            projection: None,
+            projected_schema: schema.clone(),
+            filters: vec![],
+        });
+        let binary = |left: Expr, op: Operator, right: Expr| Expr::BinaryExpr {
+            left: Rc::new(left),
+            op,
+            right: Rc::new(right),
+        };
+        let sum = binary(Expr::Column(1), Operator::Plus, Expr::Column(2));
+        // SELECT a, (b + c) * 2 FROM t WHERE b + c > 1
+        let plan = Rc::new(LogicalPlan::Projection {
+            expr: vec![
+                Expr::Column(0),
+                binary(
+                    sum.clone(),
+                    Operator::Multiply,
+                    Expr::Literal(ScalarValue::Int64(2)),
+                ),
+            ],
+            input: Rc::new(LogicalPlan::Selection {
+                expr: binary(sum, Operator::Gt, Expr::Literal(ScalarValue::Int64(1))),
+                input: scan.clone(),
+            }),
+            schema: Rc::new(Schema::new(vec![
+                Field::new("a", DataType::Int64, false),
+                Field::new("d", DataType::Int64, false),
+            ])),
+        });
+        let optimizer = Optimizer::with_rules(vec![Rc::new(CommonSubexprElimination {})]);
+        assert_eq!(
+            "Projection: #0, #1 Multiply Int64(2)\
+             \n  Selection: #1 Gt Int64(1)\
+             \n    Projection: #0, #1 Plus #2\
+             \n      TableScan: t projection=None",
+            format!("{:?}", optimizer.optimize(&plan).unwrap())
+        );
+
+        // SELECT 10 / b, 10 / b FROM t WHERE b <> 0, where the division is only evaluated
+        // for the rows that pass the filter
+        let quotient = binary(
+            Expr::Literal(ScalarValue::Int64(10)),
+            Operator::Divide,
+            Expr::Column(1),
+        );
+        let plan = Rc::new(LogicalPlan::Projection {
+            expr: vec![quotient.clone(), quotient],
+            input: Rc::new(LogicalPlan::Selection {
+                expr: binary(
+                    Expr::Column(1),
+                    Operator::NotEq,
+                    Expr::Literal(ScalarValue::Int64(0)),
+                ),
+                input: scan,
+            }),
+            schema: Rc::new(Schema::new(vec![
+                Field::new("x", DataType::Int64, false),
+                Field::new("y", DataType::Int64, false),
+            ])),
+        });
+        assert_eq!(
+            "Projection: #0, #0\
+             \n  Projection: Int64(10) Divide #1\
+             \n    Selection: #1 NotEq Int64(0)\
+             \n      TableScan: t projection=None",
+            format!("{:?}", optimizer.optimize(&plan).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_rule_error() {
+        let mut optimizer = Optimizer::new();
+        optimizer.add_rule(Rc::new(FailingRule {}));
+        assert_eq!(6, optimizer.rules().len());
+        match optimizer.optimize(&nested_limits(&[1])) {
+            Err(ExecutionError::General(msg)) => {
+                assert_eq!("Optimizer rule 'failing' failed: oops", msg)
+            }
+            _ => panic!("expected an error"),
+        }
+    }
+}
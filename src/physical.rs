@@ -0,0 +1,947 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Physical planning
+//!
+//! A physical plan is a tree of `ExecutionPlan` nodes that describes how a logical plan will
+//! be executed: expressions are compiled, the files to read are known and an algorithm has
+//! been chosen for each operator. A `PhysicalPlanner` creates the physical plan for an
+//! optimized logical plan. The relations that produce the batches are only created when the
+//! plan is executed, so a physical plan can be inspected before it runs and executed more
+//! than once.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs::File;
+use std::rc::Rc;
+
+use arrow::datatypes::Schema;
+
+use super::datasources::common::*;
+use super::datasources::csv::CsvFile;
+use super::datasources::empty::EmptyRelation;
+use super::datasources::memory::MemoryDataSource;
+use super::datasources::ndjson::NdJsonFile;
+use super::datasources::parquet::ParquetFile;
+use super::errors::*;
+use super::exec::*;
+use super::logical::*;
+use super::optimizer::conjunction;
+use super::relations::aggregate::AggregateRelation;
+use super::relations::filter::FilterRelation;
+use super::relations::limit::LimitRelation;
+use super::relations::metrics::*;
+use super::relations::projection::ProjectRelation;
+use super::relations::sort::TopKRelation;
+use super::sqlplanner::{collect_expr, push_down_projection, replace_columns};
+use super::types::ScalarValue;
+
+/// How the output of an execution plan is divided into partitions
+#[derive(Debug, Clone, PartialEq)]
+pub enum Partitioning {
+    /// The output is a single stream of batches
+    Single,
+    /// The output is divided into this many partitions in an unspecified way
+    Unknown(usize),
+}
+
+/// A node in a physical plan
+pub trait ExecutionPlan {
+    /// Describe this node, without its children, as shown by EXPLAIN
+    fn name(&self) -> String;
+
+    /// Get the schema of the output
+    fn schema(&self) -> &Rc<Schema>;
+
+    /// Get the partitioning of the output
+    fn output_partitioning(&self) -> Partitioning;
+
+    /// Get the sort expressions that the output is sorted by, which refer to the columns of
+    /// the output, or None if the output is not known to be sorted
+    fn output_ordering(&self) -> Option<Vec<Expr>>;
+
+    /// Get the plans that produce the inputs of this plan
+    fn children(&self) -> Vec<Rc<ExecutionPlan>>;
+
+    /// Create the relation that produces the output, given a relation for each child
+    fn execute(&self, inputs: Vec<Box<SimpleRelation>>) -> Result<Box<SimpleRelation>>;
+}
+
+/// Creates physical plans from logical plans
+pub trait PhysicalPlanner {
+    /// Create the physical plan for a logical plan. The inputs of the logical plan should be
+    /// planned with `ExecutionContext::create_physical_plan`, so that the planner of the
+    /// context is used for every node in the plan.
+    fn create_physical_plan(
+        &self,
+        plan: &LogicalPlan,
+        ctx: &ExecutionContext,
+    ) -> Result<Rc<ExecutionPlan>>;
+}
+
+/// Create the relations that execute a physical plan. When `metrics` is provided, each
+/// relation is wrapped in a `MetricsRelation` and a node describing it is added to `metrics`.
+pub fn execute_plan(
+    plan: &Rc<ExecutionPlan>,
+    metrics: &mut Option<Vec<MetricsNode>>,
+) -> Result<Box<SimpleRelation>> {
+    // metrics for the inputs to this relation
+    let mut child_metrics: Option<Vec<MetricsNode>> = match metrics {
+        Some(_) => Some(vec![]),
+        None => None,
+    };
+    let inputs = plan
+        .children()
+        .iter()
+        .map(|c| execute_plan(c, &mut child_metrics))
+        .collect::<Result<Vec<_>>>()?;
+    let rel = plan.execute(inputs)?;
+    match metrics {
+        Some(ref mut nodes) => {
+            let m = Rc::new(RefCell::new(RelationMetrics::new()));
+            nodes.push(MetricsNode {
+                name: plan.name(),
+                metrics: m.clone(),
+                children: child_metrics.unwrap_or(vec![]),
+            });
+            Ok(Box::new(MetricsRelation::new(rel, m)))
+        }
+        None => Ok(rel),
+    }
+}
+
+/// Write a physical plan using the same indentation as the logical plan
+pub fn format_plan(plan: &Rc<ExecutionPlan>, s: &mut String, indent: usize) {
+    if indent > 0 {
+        s.push_str("\n");
+        for _ in 0..indent {
+            s.push_str("  ");
+        }
+    }
+    s.push_str(&plan.name());
+    plan.children()
+        .iter()
+        .for_each(|c| format_plan(c, s, indent + 1));
+}
+
+/// Plans each logical plan with the relations that are built in
+pub struct DefaultPhysicalPlanner {}
+
+impl PhysicalPlanner for DefaultPhysicalPlanner {
+    fn create_physical_plan(
+        &self,
+        plan: &LogicalPlan,
+        ctx: &ExecutionContext,
+    ) -> Result<Rc<ExecutionPlan>> {
+        let batch_size = ctx.session_config().batch_size;
+
+        match *plan {
+            LogicalPlan::EmptyRelation { ref schema } => Ok(Rc::new(DataSourceExec::new(
+                "DataSourceRelation: empty".to_string(),
+                schema.clone(),
+                || Ok(Rc::new(RefCell::new(EmptyRelation::new())) as Rc<RefCell<DataSource>>),
+            ))),
+
+            LogicalPlan::TableScan {
+                ref table_name,
+                ref projection,
+                ref filters,
+                ..
+            } => {
+                // the registered table is planned in place of the scan
+                let df = ctx.registered_table(table_name)?;
+                // filters can only be passed on when the table is read directly from files,
+                // since any other plan may change the columns
+                let table_plan = match df.plan().as_ref() {
+                    LogicalPlan::CsvFile { .. }
+                    | LogicalPlan::NdJsonFile { .. }
+                    | LogicalPlan::ParquetFile { .. } => {
+                        Rc::new(df.plan().with_filters(filters.clone()))
+                    }
+                    _ => df.plan().clone(),
+                };
+                match projection {
+                    Some(p) => {
+                        let h: HashSet<usize> = p.iter().cloned().collect();
+                        ctx.create_physical_plan(&push_down_projection(&table_plan, &h))
+                    }
+                    None => ctx.create_physical_plan(&table_plan),
+                }
+            }
+
+            LogicalPlan::CsvFile {
+                ref filename,
+                ref schema,
+                has_header,
+                ref projection,
+                ref options,
+                ..
+            } => {
+                let file_schema = options.file_schema(schema);
+                let file_projection = file_projection(projection, &file_schema);
+                let delimiter = options.delimiter;
+                let reader_options = options.clone();
+                plan_file_scan(
+                    ctx,
+                    &format!("DataSourceRelation: csv={}", filename),
+                    plan,
+                    move |file| {
+                        let mut csv = CsvFile::from_reader(
+                            reader_options.open_reader(file),
+                            file_schema.clone(),
+                            has_header,
+                            delimiter,
+                            file_projection.clone(),
+                        )?;
+                        if let Some(batch_size) = batch_size {
+                            csv.set_batch_size(batch_size);
+                        }
+                        Ok(Rc::new(RefCell::new(csv)))
+                    },
+                )
+            }
+
+            LogicalPlan::NdJsonFile {
+                ref filename,
+                ref schema,
+                ref projection,
+                ref options,
+                ..
+            } => {
+                let file_schema = options.file_schema(schema);
+                let file_projection = file_projection(projection, &file_schema);
+                let reader_options = options.clone();
+                plan_file_scan(
+                    ctx,
+                    &format!("DataSourceRelation: ndjson={}", filename),
+                    plan,
+                    move |file| {
+                        let mut json = NdJsonFile::from_reader(
+                            reader_options.open_reader(file),
+                            file_schema.clone(),
+                            file_projection.clone(),
+                        )?;
+                        if let Some(batch_size) = batch_size {
+                            json.set_batch_size(batch_size);
+                        }
+                        Ok(Rc::new(RefCell::new(json)))
+                    },
+                )
+            }
+
+            LogicalPlan::ParquetFile {
+                ref filename,
+                ref schema,
+                ref projection,
+                ref options,
+                ..
+            } => {
+                let file_projection = file_projection(projection, &options.file_schema(schema));
+                plan_file_scan(
+                    ctx,
+                    &format!("DataSourceRelation: parquet={}", filename),
+                    plan,
+                    move |file| {
+                        let mut parquet = ParquetFile::open(file, file_projection.clone())?;
+                        if let Some(batch_size) = batch_size {
+                            parquet.set_batch_size(batch_size);
+                        }
+                        Ok(Rc::new(RefCell::new(parquet)))
+                    },
+                )
+            }
+
+            LogicalPlan::InMemory {
+                ref data,
+                ref schema,
+                ..
+            } => {
+                let (data, memory_schema) = (data.clone(), schema.clone());
+                Ok(Rc::new(DataSourceExec::new(
+                    format!("DataSourceRelation: memory batches={}", data.len()),
+                    schema.clone(),
+                    move || {
+                        Ok(Rc::new(RefCell::new(MemoryDataSource::new(
+                            memory_schema.clone(),
+                            data.clone(),
+                        ))) as Rc<RefCell<DataSource>>)
+                    },
+                )))
+            }
+
+            LogicalPlan::Selection {
+                ref expr,
+                ref input,
+            } => {
+                let compiled_expr = compile_scalar_expr(ctx, expr, input.schema())?.get_func();
+                Ok(Rc::new(FilterExec {
+                    input: ctx.create_physical_plan(input)?,
+                    expr: expr.clone(),
+                    compiled_expr,
+                }))
+            }
+
+            LogicalPlan::Projection {
+                ref expr,
+                ref input,
+                ref schema,
+            } => {
+                let compiled_expr = expr
+                    .iter()
+                    .map(|e| compile_scalar_expr(ctx, e, input.schema()))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Rc::new(ProjectionExec {
+                    input: ctx.create_physical_plan(input)?,
+                    expr: expr.clone(),
+                    compiled_expr,
+                    schema: schema.clone(),
+                }))
+            }
+
+            LogicalPlan::Aggregate {
+                ref input,
+                ref group_expr,
+                ref aggr_expr,
+                ref schema,
+            } => {
+                let compiled_group_expr = group_expr
+                    .iter()
+                    .map(|e| compile_scalar_expr(ctx, e, input.schema()))
+                    .collect::<Result<Vec<_>>>()?;
+                let compiled_aggr_expr = aggr_expr
+                    .iter()
+                    .map(|e| compile_expr(ctx, e, input.schema()))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Rc::new(AggregateExec {
+                    input: ctx.create_physical_plan(input)?,
+                    group_expr: group_expr.clone(),
+                    aggr_expr: aggr_expr.clone(),
+                    compiled_group_expr,
+                    compiled_aggr_expr,
+                    schema: schema.clone(),
+                }))
+            }
+
+            LogicalPlan::Sort { .. } => Err(ExecutionError::General(
+                "Sorting without a limit is not supported".to_string(),
+            )),
+
+            LogicalPlan::Limit {
+                limit,
+                ref input,
+                ref schema,
+            } => match input.as_ref() {
+                // a limit of a sort only needs to keep the first rows in sort order
+                LogicalPlan::Sort {
+                    ref expr,
+                    input: ref sort_input,
+                    ..
+                } => {
+                    let mut sort_expr = Vec::with_capacity(expr.len());
+                    let mut sort_asc = Vec::with_capacity(expr.len());
+                    for e in expr {
+                        match e {
+                            Expr::Sort { ref expr, asc } => {
+                                sort_expr.push(
+                                    compile_scalar_expr(ctx, expr, sort_input.schema())?.get_func(),
+                                );
+                                sort_asc.push(*asc);
+                            }
+                            other => {
+                                return Err(ExecutionError::General(format!(
+                                    "Expected a sort expression but found {:?}",
+                                    other
+                                )))
+                            }
+                        }
+                    }
+                    Ok(Rc::new(TopKExec {
+                        input: ctx.create_physical_plan(sort_input)?,
+                        expr: expr.clone(),
+                        sort_expr,
+                        sort_asc,
+                        k: limit,
+                        schema: schema.clone(),
+                    }))
+                }
+                _ => Ok(Rc::new(LimitExec {
+                    input: ctx.create_physical_plan(input)?,
+                    limit,
+                    schema: schema.clone(),
+                })),
+            },
+        }
+    }
+}
+
+/// Plan a scan of the files of a CSV, ndjson or Parquet location, where `open` opens a single
+/// file and applies the projection to its columns
+fn plan_file_scan<F>(
+    ctx: &ExecutionContext,
+    name: &str,
+    plan: &LogicalPlan,
+    open: F,
+) -> Result<Rc<ExecutionPlan>>
+where
+    F: Fn(File) -> Result<Rc<RefCell<DataSource>>> + 'static,
+{
+    let (filename, schema, projection, projected_schema, options, filters) = match plan {
+        LogicalPlan::CsvFile {
+            ref filename,
+            ref schema,
+            ref projection,
+            ref projected_schema,
+            ref options,
+            ref filters,
+            ..
+        }
+        | LogicalPlan::NdJsonFile {
+            ref filename,
+            ref schema,
+            ref projection,
+            ref projected_schema,
+            ref options,
+            ref filters,
+        }
+        | LogicalPlan::ParquetFile {
+            ref filename,
+            ref schema,
+            ref projection,
+            ref projected_schema,
+            ref options,
+            ref filters,
+        } => (
+            filename.clone(),
+            schema.clone(),
+            projection.clone(),
+            projected_schema.clone(),
+            options.clone(),
+            filters.clone(),
+        ),
+        _ => {
+            return Err(ExecutionError::General(format!(
+                "Expected a file scan but found {:?}",
+                plan
+            )))
+        }
+    };
+    let partition_filter = compile_partition_filter(ctx, &filters, &schema, &options)?;
+    let source_filters = project_filters(&filters, &schema, &projection);
+    Ok(Rc::new(DataSourceExec::new(
+        name.to_string(),
+        projected_schema,
+        move || {
+            let ds = open_location(
+                &filename,
+                &schema,
+                &projection,
+                &options,
+                &partition_filter,
+                &open,
+            )?;
+            ds.borrow_mut().push_down_filters(&source_filters);
+            Ok(ds)
+        },
+    )))
+}
+
+/// Renumber the filters of a scan from the columns of its table to the columns of the
+/// projection that its data source reads, leaving out filters on columns that are not read
+fn project_filters(
+    filters: &[Expr],
+    schema: &Schema,
+    projection: &Option<Vec<usize>>,
+) -> Vec<Expr> {
+    let projection = match projection {
+        Some(p) => p,
+        None => return filters.to_vec(),
+    };
+    let columns: Vec<Expr> = (0..schema.columns().len())
+        .map(|i| match projection.iter().position(|p| *p == i) {
+            Some(j) => Expr::Column(j),
+            None => Expr::Literal(ScalarValue::Null),
+        })
+        .collect();
+    filters
+        .iter()
+        .filter(|f| {
+            let mut referenced = HashSet::new();
+            collect_expr(f, &mut referenced);
+            referenced.iter().all(|i| projection.contains(i))
+        })
+        .map(|f| replace_columns(f, &columns))
+        .collect()
+}
+
+/// Compile the filters of a file scan that only reference partition columns, which are used
+/// to skip the files of partitions that cannot match
+fn compile_partition_filter(
+    ctx: &ExecutionContext,
+    filters: &[Expr],
+    schema: &Schema,
+    options: &FileOptions,
+) -> Result<Option<CompiledExpr>> {
+    let file_column_count = schema.columns().len() - options.partition_count;
+    let partition_filters: Vec<Expr> = filters
+        .iter()
+        .filter(|f| {
+            let mut columns = HashSet::new();
+            collect_expr(f, &mut columns);
+            columns.iter().all(|i| *i >= file_column_count)
+        })
+        .cloned()
+        .collect();
+    match conjunction(partition_filters) {
+        Some(expr) if options.partition_count > 0 => {
+            Ok(Some(compile_scalar_expr(ctx, &expr, schema)?.get_func()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Reads a data source, which is opened each time the plan is executed
+pub struct DataSourceExec {
+    name: String,
+    schema: Rc<Schema>,
+    open: Rc<Fn() -> Result<Rc<RefCell<DataSource>>>>,
+}
+
+impl DataSourceExec {
+    pub fn new<F>(name: String, schema: Rc<Schema>, open: F) -> Self
+    where
+        F: Fn() -> Result<Rc<RefCell<DataSource>>> + 'static,
+    {
+        DataSourceExec {
+            name,
+            schema,
+            open: Rc::new(open),
+        }
+    }
+}
+
+impl ExecutionPlan for DataSourceExec {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::Single
+    }
+
+    fn output_ordering(&self) -> Option<Vec<Expr>> {
+        None
+    }
+
+    fn children(&self) -> Vec<Rc<ExecutionPlan>> {
+        vec![]
+    }
+
+    fn execute(&self, _inputs: Vec<Box<SimpleRelation>>) -> Result<Box<SimpleRelation>> {
+        Ok(Box::new(DataSourceRelation {
+            schema: self.schema.as_ref().clone(),
+            ds: (self.open)()?,
+        }))
+    }
+}
+
+struct DataSourceRelation {
+    schema: Schema,
+    ds: Rc<RefCell<DataSource>>,
+}
+
+impl SimpleRelation for DataSourceRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        Box::new(DataSourceIterator::new(self.ds.clone()))
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        &self.schema
+    }
+}
+
+/// Keeps the rows for which a boolean expression is true
+pub struct FilterExec {
+    input: Rc<ExecutionPlan>,
+    expr: Expr,
+    compiled_expr: CompiledExpr,
+}
+
+impl ExecutionPlan for FilterExec {
+    fn name(&self) -> String {
+        format!("FilterRelation: {:?}", self.expr)
+    }
+
+    fn schema(&self) -> &Rc<Schema> {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn output_ordering(&self) -> Option<Vec<Expr>> {
+        self.input.output_ordering()
+    }
+
+    fn children(&self) -> Vec<Rc<ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn execute(&self, mut inputs: Vec<Box<SimpleRelation>>) -> Result<Box<SimpleRelation>> {
+        Ok(Box::new(FilterRelation::new(
+            inputs.remove(0),
+            self.compiled_expr.clone(),
+        )))
+    }
+}
+
+/// Evaluates a list of expressions for each row
+pub struct ProjectionExec {
+    input: Rc<ExecutionPlan>,
+    expr: Vec<Expr>,
+    compiled_expr: Vec<RuntimeExpr>,
+    schema: Rc<Schema>,
+}
+
+impl ExecutionPlan for ProjectionExec {
+    fn name(&self) -> String {
+        format!("ProjectRelation: {:?}", self.expr)
+    }
+
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn output_ordering(&self) -> Option<Vec<Expr>> {
+        // the output stays sorted by the leading sort columns that are projected
+        let mut ordering = vec![];
+        for e in self.input.output_ordering().unwrap_or(vec![]) {
+            let projected = match e {
+                Expr::Sort { ref expr, asc } => self
+                    .expr
+                    .iter()
+                    .position(|p| p == expr.as_ref())
+                    .map(|i| Expr::Sort {
+                        expr: Rc::new(Expr::Column(i)),
+                        asc,
+                    }),
+                _ => None,
+            };
+            match projected {
+                Some(sort) => ordering.push(sort),
+                None => break,
+            }
+        }
+        if ordering.is_empty() {
+            None
+        } else {
+            Some(ordering)
+        }
+    }
+
+    fn children(&self) -> Vec<Rc<ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn execute(&self, mut inputs: Vec<Box<SimpleRelation>>) -> Result<Box<SimpleRelation>> {
+        Ok(Box::new(ProjectRelation::new(
+            inputs.remove(0),
+            self.compiled_expr.clone(),
+            self.schema.clone(),
+        )))
+    }
+}
+
+/// Evaluates aggregate functions for each group of rows, using a hash table of the groups
+pub struct AggregateExec {
+    input: Rc<ExecutionPlan>,
+    group_expr: Vec<Expr>,
+    aggr_expr: Vec<Expr>,
+    compiled_group_expr: Vec<RuntimeExpr>,
+    compiled_aggr_expr: Vec<RuntimeExpr>,
+    schema: Rc<Schema>,
+}
+
+impl ExecutionPlan for AggregateExec {
+    fn name(&self) -> String {
+        format!(
+            "AggregateRelation: groupBy={:?}, aggr={:?}",
+            self.group_expr, self.aggr_expr
+        )
+    }
+
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::Single
+    }
+
+    fn output_ordering(&self) -> Option<Vec<Expr>> {
+        None
+    }
+
+    fn children(&self) -> Vec<Rc<ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn execute(&self, mut inputs: Vec<Box<SimpleRelation>>) -> Result<Box<SimpleRelation>> {
+        Ok(Box::new(AggregateRelation::new(
+            self.schema.clone(),
+            inputs.remove(0),
+            self.compiled_group_expr.clone(),
+            self.compiled_aggr_expr.clone(),
+        )))
+    }
+}
+
+/// Returns the first `k` rows of the input in sort order
+pub struct TopKExec {
+    input: Rc<ExecutionPlan>,
+    expr: Vec<Expr>,
+    sort_expr: Vec<CompiledExpr>,
+    sort_asc: Vec<bool>,
+    k: usize,
+    schema: Rc<Schema>,
+}
+
+impl ExecutionPlan for TopKExec {
+    fn name(&self) -> String {
+        format!("TopKRelation: k={} {:?}", self.k, self.expr)
+    }
+
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::Single
+    }
+
+    fn output_ordering(&self) -> Option<Vec<Expr>> {
+        Some(self.expr.clone())
+    }
+
+    fn children(&self) -> Vec<Rc<ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn execute(&self, mut inputs: Vec<Box<SimpleRelation>>) -> Result<Box<SimpleRelation>> {
+        Ok(Box::new(TopKRelation::new(
+            self.schema.clone(),
+            inputs.remove(0),
+            self.sort_expr.clone(),
+            self.sort_asc.clone(),
+            self.k,
+        )))
+    }
+}
+
+/// Returns at most `limit` rows of the input
+pub struct LimitExec {
+    input: Rc<ExecutionPlan>,
+    limit: usize,
+    schema: Rc<Schema>,
+}
+
+impl ExecutionPlan for LimitExec {
+    fn name(&self) -> String {
+        format!("LimitRelation: limit={}", self.limit)
+    }
+
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::Single
+    }
+
+    fn output_ordering(&self) -> Option<Vec<Expr>> {
+        self.input.output_ordering()
+    }
+
+    fn children(&self) -> Vec<Rc<ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn execute(&self, mut inputs: Vec<Box<SimpleRelation>>) -> Result<Box<SimpleRelation>> {
+        Ok(Box::new(LimitRelation::new(
+            self.schema.clone(),
+            inputs.remove(0),
+            self.limit,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::planbuilder::LogicalPlanBuilder;
+    use super::*;
+    use arrow::datatypes::{DataType, Field};
+
+    fn create_context() -> ExecutionContext {
+        let mut ctx = ExecutionContext::local();
+        let people = ctx
+            .load_csv(
+                "./test/data/people.csv",
+                &Schema::new(vec![
+                    Field::new("id", DataType::Int32, false),
+                    Field::new("name", DataType::Utf8, false),
+                ]),
+                true,
+                None,
+            )
+            .unwrap();
+        ctx.register("people", people);
+        ctx
+    }
+
+    fn plan_text(plan: &Rc<ExecutionPlan>) -> String {
+        let mut s = String::new();
+        format_plan(plan, &mut s, 0);
+        s
+    }
+
+    fn collect_rows(plan: &Rc<ExecutionPlan>) -> Vec<String> {
+        let mut rel = execute_plan(plan, &mut None).unwrap();
+        let mut rows = vec![];
+        for batch in rel.scan() {
+            let batch = batch.unwrap();
+            for i in 0..batch.num_rows() {
+                let row: Vec<String> = batch.row_slice(i).iter().map(|v| v.to_string()).collect();
+                rows.push(row.join(","));
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn test_physical_plan() {
+        let ctx = create_context();
+        let schema = ctx.registered_table("people").unwrap().schema().clone();
+        let logical_plan = LogicalPlanBuilder::scan("default", "people", &schema, None)
+            .unwrap()
+            .sort(vec![Expr::Sort {
+                expr: Rc::new(Expr::Column(0)),
+                asc: false,
+            }])
+            .unwrap()
+            .limit(2)
+            .unwrap()
+            .project(vec![Expr::Column(1), Expr::Column(0)])
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let plan = ctx.create_physical_plan(&logical_plan).unwrap();
+        assert_eq!(
+            "ProjectRelation: [#1, #0]\
+             \n  TopKRelation: k=2 [#0 DESC]\
+             \n    DataSourceRelation: csv=./test/data/people.csv",
+            plan_text(&plan)
+        );
+        assert_eq!("name", plan.schema().column(0).name());
+        assert_eq!(Partitioning::Single, plan.output_partitioning());
+        assert_eq!(1, plan.children().len());
+
+        // the output stays sorted by the projected sort column
+        assert_eq!("Some([#1 DESC])", format!("{:?}", plan.output_ordering()));
+        let scan = plan.children()[0].children()[0].clone();
+        assert!(scan.output_ordering().is_none());
+        assert_eq!(0, scan.children().len());
+
+        // a physical plan can be executed more than once
+        let expected = vec!["Juliet,10".to_string(), "Irene,9".to_string()];
+        assert_eq!(expected, collect_rows(&plan));
+        assert_eq!(expected, collect_rows(&plan));
+    }
+
+    #[test]
+    fn test_sort_without_limit() {
+        let ctx = create_context();
+        let schema = ctx.registered_table("people").unwrap().schema().clone();
+        let logical_plan = LogicalPlanBuilder::scan("default", "people", &schema, None)
+            .unwrap()
+            .sort(vec![Expr::Sort {
+                expr: Rc::new(Expr::Column(0)),
+                asc: true,
+            }])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(ctx.create_physical_plan(&logical_plan).is_err());
+    }
+
+    /// Plans selections as their input, so that no rows are filtered
+    struct NoFilterPlanner {}
+
+    impl PhysicalPlanner for NoFilterPlanner {
+        fn create_physical_plan(
+            &self,
+            plan: &LogicalPlan,
+            ctx: &ExecutionContext,
+        ) -> Result<Rc<ExecutionPlan>> {
+            match plan {
+                LogicalPlan::Selection { ref input, .. } => ctx.create_physical_plan(input),
+                _ => DefaultPhysicalPlanner {}.create_physical_plan(plan, ctx),
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_planner() {
+        let mut ctx = create_context();
+        ctx.set_physical_planner(Rc::new(NoFilterPlanner {}));
+        let df = ctx.sql("SELECT id FROM people WHERE id < 3").unwrap();
+        let plan = ctx.create_execution_plan(df.plan()).unwrap();
+        assert!(!plan_text(&plan).contains("FilterRelation"));
+        assert_eq!(10, collect_rows(&plan).len());
+    }
+
+    #[test]
+    fn test_project_filters() {
+        let schema = Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Int32, false),
+            Field::new("c", DataType::Int32, false),
+        ]);
+        let one = Expr::Literal(ScalarValue::Int32(1));
+        let filters = vec![
+            Expr::Column(2).lt(&one),
+            Expr::Column(0).lt(&one),
+            Expr::Column(2).eq(&Expr::Column(1)),
+        ];
+
+        // the data source reads columns c and b, in that order, and column a is not read
+        let projected = project_filters(&filters, &schema, &Some(vec![2, 1]));
+        assert_eq!(
+            vec![
+                Expr::Column(0).lt(&one),
+                Expr::Column(0).eq(&Expr::Column(1))
+            ],
+            projected
+        );
+        assert_eq!(filters, project_filters(&filters, &schema, &None));
+    }
+}
@@ -0,0 +1,394 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Logical plan builder
+//!
+//! `LogicalPlanBuilder` creates logical plans one relation at a time, computing the schema
+//! of each relation from its input. Expressions may refer to the columns of the input by
+//! index or by name. Names are resolved, column indices are checked against the schema of
+//! the input, and the casts that the expressions need are inserted, so a plan that is built
+//! successfully can be executed. The SQL planner and the DataFrame API both build their plans
+//! this way.
+
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use arrow::datatypes::Schema;
+
+use super::analyzer::resolve_exprs;
+use super::coercion::{coerce_exprs, coerce_predicate};
+use super::datasources::common::FileOptions;
+use super::errors::*;
+use super::logical::*;
+use super::sqlplanner::{collect_expr, contains_aggregate, exprlist_to_fields};
+
+/// Builds a logical plan on top of an input plan. There is no `join` method because the
+/// logical plan has no join node yet.
+#[derive(Clone)]
+pub struct LogicalPlanBuilder {
+    plan: Rc<LogicalPlan>,
+    /// Whether column names in expressions must match the names of columns exactly
+    case_sensitive: bool,
+}
+
+impl LogicalPlanBuilder {
+    /// Start building from an existing plan
+    pub fn new(plan: Rc<LogicalPlan>) -> Self {
+        LogicalPlanBuilder {
+            plan,
+            case_sensitive: false,
+        }
+    }
+
+    /// Match the column names in expressions case-sensitively, as the SQL planner matches
+    /// quoted identifiers
+    pub fn with_case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Start building from a relation that has no columns and produces no rows
+    pub fn empty() -> Self {
+        LogicalPlanBuilder::new(Rc::new(LogicalPlan::EmptyRelation {
+            schema: Rc::new(Schema::empty()),
+        }))
+    }
+
+    /// Scan a table that is registered with a context, optionally reading only the columns
+    /// in a projection
+    pub fn scan(
+        schema_name: &str,
+        table_name: &str,
+        schema: &Rc<Schema>,
+        projection: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        LogicalPlanBuilder::from_source(
+            LogicalPlan::TableScan {
+                schema_name: schema_name.to_string(),
+                table_name: table_name.to_string(),
+                schema: schema.clone(),
+                projection: None,
+                projected_schema: schema.clone(),
+                filters: vec![],
+            },
+            projection,
+        )
+    }
+
+    /// Scan a CSV file or directory of files
+    pub fn scan_csv(
+        filename: &str,
+        schema: &Rc<Schema>,
+        has_header: bool,
+        options: FileOptions,
+        projection: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        LogicalPlanBuilder::from_source(
+            LogicalPlan::CsvFile {
+                filename: filename.to_string(),
+                schema: schema.clone(),
+                has_header,
+                projection: None,
+                projected_schema: schema.clone(),
+                options,
+                filters: vec![],
+            },
+            projection,
+        )
+    }
+
+    /// Scan an ndjson file or directory of files
+    pub fn scan_ndjson(
+        filename: &str,
+        schema: &Rc<Schema>,
+        options: FileOptions,
+        projection: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        LogicalPlanBuilder::from_source(
+            LogicalPlan::NdJsonFile {
+                filename: filename.to_string(),
+                schema: schema.clone(),
+                projection: None,
+                projected_schema: schema.clone(),
+                options,
+                filters: vec![],
+            },
+            projection,
+        )
+    }
+
+    /// Scan a Parquet file or directory of files, which have the specified schema
+    pub fn scan_parquet(
+        filename: &str,
+        schema: &Rc<Schema>,
+        options: FileOptions,
+        projection: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        LogicalPlanBuilder::from_source(
+            LogicalPlan::ParquetFile {
+                filename: filename.to_string(),
+                schema: schema.clone(),
+                projection: None,
+                projected_schema: schema.clone(),
+                options,
+                filters: vec![],
+            },
+            projection,
+        )
+    }
+
+    fn from_source(plan: LogicalPlan, projection: Option<Vec<usize>>) -> Result<Self> {
+        let plan = match projection {
+            Some(projection) => {
+                let columns = plan.schema().columns().len();
+                if let Some(i) = projection.iter().find(|i| **i >= columns) {
+                    return Err(ExecutionError::InvalidColumn(format!(
+                        "Projection refers to column {} but the schema only has {} columns",
+                        i, columns
+                    )));
+                }
+                plan.with_projection(projection)
+            }
+            None => plan,
+        };
+        Ok(LogicalPlanBuilder::new(Rc::new(plan)))
+    }
+
+    /// Keep the rows for which a boolean expression is true
+    pub fn filter(&self, expr: Expr) -> Result<Self> {
+        let expr = self.prepare(&[expr])?.remove(0);
+        Ok(self.with_plan(LogicalPlan::Selection {
+            expr: coerce_predicate(&expr, self.schema())?,
+            input: self.plan.clone(),
+        }))
+    }
+
+    /// Evaluate a list of expressions for each row
+    pub fn project(&self, expr: Vec<Expr>) -> Result<Self> {
+        let expr = self.prepare(&expr)?;
+        for e in &expr {
+            match e {
+                Expr::Sort { .. } => {
+                    return Err(ExecutionError::General(format!(
+                        "Sort expression {:?} cannot be projected",
+                        e
+                    )))
+                }
+                _ if contains_aggregate(e) => {
+                    return Err(ExecutionError::General(format!(
+                        "Aggregate expression {:?} cannot be projected",
+                        e
+                    )))
+                }
+                _ => {}
+            }
+        }
+        let schema = Rc::new(Schema::new(exprlist_to_fields(&expr, self.schema())?));
+        Ok(self.with_plan(LogicalPlan::Projection {
+            expr,
+            input: self.plan.clone(),
+            schema,
+        }))
+    }
+
+    /// Evaluate aggregate functions for each group of rows that have the same values of the
+    /// grouping expressions. The result has a column for each grouping expression followed by
+    /// a column for each aggregate expression.
+    pub fn aggregate(&self, group_expr: Vec<Expr>, aggr_expr: Vec<Expr>) -> Result<Self> {
+        let group_expr = self.prepare(&group_expr)?;
+        let aggr_expr = self.prepare(&aggr_expr)?;
+        if let Some(e) = group_expr.iter().find(|e| contains_aggregate(e)) {
+            return Err(ExecutionError::General(format!(
+                "Grouping expression {:?} cannot contain aggregate functions",
+                e
+            )));
+        }
+        for e in &aggr_expr {
+            match e {
+                Expr::AggregateFunction { ref args, .. }
+                    if !args.iter().any(contains_aggregate) => {}
+                _ => {
+                    return Err(ExecutionError::General(format!(
+                        "Expression {:?} is not an aggregate function",
+                        e
+                    )))
+                }
+            }
+        }
+        let mut all_expr = group_expr.clone();
+        all_expr.extend(aggr_expr.iter().cloned());
+        let schema = Rc::new(Schema::new(exprlist_to_fields(&all_expr, self.schema())?));
+        Ok(self.with_plan(LogicalPlan::Aggregate {
+            input: self.plan.clone(),
+            group_expr,
+            aggr_expr,
+            schema,
+        }))
+    }
+
+    /// Sort the rows by a list of sort expressions
+    pub fn sort(&self, expr: Vec<Expr>) -> Result<Self> {
+        let expr = self.prepare(&expr)?;
+        if let Some(e) = expr.iter().find(|e| match e {
+            Expr::Sort { .. } => false,
+            _ => true,
+        }) {
+            return Err(ExecutionError::General(format!(
+                "Expression {:?} is not a sort expression",
+                e
+            )));
+        }
+        Ok(self.with_plan(LogicalPlan::Sort {
+            expr,
+            input: self.plan.clone(),
+            schema: self.schema().clone(),
+        }))
+    }
+
+    /// Keep at most `n` rows
+    pub fn limit(&self, n: usize) -> Result<Self> {
+        Ok(self.with_plan(LogicalPlan::Limit {
+            limit: n,
+            input: self.plan.clone(),
+            schema: self.schema().clone(),
+        }))
+    }
+
+    /// Get the plan that has been built
+    pub fn build(&self) -> Result<Rc<LogicalPlan>> {
+        Ok(self.plan.clone())
+    }
+
+    /// Get the schema of the plan that has been built
+    pub fn schema(&self) -> &Rc<Schema> {
+        self.plan.schema()
+    }
+
+    fn with_plan(&self, plan: LogicalPlan) -> Self {
+        LogicalPlanBuilder::new(Rc::new(plan)).with_case_sensitive(self.case_sensitive)
+    }
+
+    /// Resolve the column references in expressions that are evaluated against the plan,
+    /// check that the columns exist, and insert the casts that the expressions need
+    fn prepare(&self, expr: &[Expr]) -> Result<Vec<Expr>> {
+        let expr = resolve_exprs(expr, &self.plan, self.case_sensitive)?;
+        let mut columns = HashSet::new();
+        expr.iter().for_each(|e| collect_expr(e, &mut columns));
+        let len = self.schema().columns().len();
+        if let Some(i) = columns.iter().find(|i| **i >= len) {
+            return Err(ExecutionError::InvalidColumn(format!(
+                "Column {} does not exist in schema {}",
+                i,
+                self.schema().to_string()
+            )));
+        }
+        coerce_exprs(&expr, self.schema())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::{DataType, Field};
+    use types::ScalarValue;
+
+    fn employees() -> LogicalPlanBuilder {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("state", DataType::Utf8, false),
+            Field::new("salary", DataType::Float64, false),
+        ]));
+        LogicalPlanBuilder::scan("default", "employees", &schema, None).unwrap()
+    }
+
+    fn col(name: &str) -> Expr {
+        Expr::ColumnRef {
+            relation: None,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_build_plan() {
+        let plan = employees()
+            .filter(Expr::BinaryExpr {
+                left: Rc::new(col("id")),
+                op: Operator::Lt,
+                right: Rc::new(Expr::Literal(ScalarValue::Int64(10))),
+            })
+            .unwrap()
+            .project(vec![col("state"), col("salary")])
+            .unwrap()
+            .sort(vec![Expr::Sort {
+                expr: Rc::new(Expr::Column(1)),
+                asc: false,
+            }])
+            .unwrap()
+            .limit(3)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            "Limit: 3\
+             \n  Sort: #1 DESC\
+             \n    Projection: #1, #2\
+             \n      Selection: CAST(#0 AS Int64) Lt Int64(10)\
+             \n        TableScan: employees projection=None",
+            format!("{:?}", plan)
+        );
+        assert_eq!("salary", plan.schema().column(1).name());
+
+        let plan = employees()
+            .project(vec![Expr::IsNull(Rc::new(col("state")))])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(&DataType::Boolean, plan.schema().column(0).data_type());
+    }
+
+    #[test]
+    fn test_build_aggregate() {
+        let sum = Expr::AggregateFunction {
+            name: "SUM".to_string(),
+            args: vec![col("salary")],
+            return_type: DataType::Float64,
+        };
+        let plan = employees()
+            .aggregate(vec![col("state")], vec![sum.clone()])
+            .unwrap()
+            .build()
+            .unwrap();
+        let schema = plan.schema();
+        assert_eq!(2, schema.columns().len());
+        assert_eq!("state", schema.column(0).name());
+        assert_eq!(&DataType::Float64, schema.column(1).data_type());
+
+        // aggregates are only allowed in the aggregate expressions
+        assert!(employees().project(vec![sum.clone()]).is_err());
+        assert!(employees().aggregate(vec![sum.clone()], vec![]).is_err());
+        assert!(employees().aggregate(vec![], vec![col("id")]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_plans() {
+        assert!(employees().project(vec![col("missing")]).is_err());
+        assert!(employees().project(vec![Expr::Column(3)]).is_err());
+        assert!(employees().filter(col("salary")).is_err());
+        assert!(employees().sort(vec![col("id")]).is_err());
+        let schema = employees().schema().clone();
+        assert!(LogicalPlanBuilder::scan("default", "t", &schema, Some(vec![0, 3])).is_err());
+        let scan = LogicalPlanBuilder::scan("default", "t", &schema, Some(vec![2, 0])).unwrap();
+        assert_eq!("salary", scan.schema().column(0).name());
+    }
+}
@@ -19,6 +19,8 @@ use std::rc::Rc;
 use super::super::datasources::common::*;
 use super::super::errors::*;
 use super::super::exec::*;
+use super::super::types::*;
+use super::filter::filter;
 use arrow::datatypes::Schema;
 
 pub struct LimitRelation {
@@ -40,30 +42,61 @@ impl LimitRelation {
 
 impl SimpleRelation for LimitRelation {
     fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
-        let mut count: usize = 0;
-        let limit = self.limit;
-
-        Box::new(self.input.scan().map(move |batch| match batch {
-            Ok(ref b) => {
-                if count + b.num_rows() < limit {
-                    count += b.num_rows();
-                    Ok(b.clone())
-                } else {
-                    let n = b.num_rows().min(limit - count);
-                    count += n;
-                    let new_batch: Rc<RecordBatch> = Rc::new(DefaultRecordBatch {
-                        schema: b.schema().clone(),
-                        data: b.columns().clone(),
-                        row_count: n,
-                    });
-                    Ok(new_batch)
-                }
-            }
-            Err(e) => Err(e),
-        }))
+        Box::new(LimitIterator {
+            input: self.input.scan(),
+            remaining: self.limit,
+        })
     }
 
     fn schema<'a>(&'a self) -> &'a Schema {
         self.schema.as_ref()
     }
 }
+
+/// Returns batches from the input until the limit is reached, without reading any further
+/// batches from the input
+struct LimitIterator<'a> {
+    input: Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>,
+    /// Number of rows that can still be returned
+    remaining: usize,
+}
+
+impl<'a> Iterator for LimitIterator<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        match self.input.next() {
+            Some(Ok(ref b)) if b.num_rows() <= self.remaining => {
+                self.remaining -= b.num_rows();
+                Some(Ok(b.clone()))
+            }
+            Some(Ok(ref b)) => {
+                let n = self.remaining;
+                self.remaining = 0;
+                Some(Ok(truncate(b.as_ref(), n)))
+            }
+            other => other,
+        }
+    }
+}
+
+/// Copy the first `n` rows of a batch
+fn truncate(batch: &RecordBatch, n: usize) -> Rc<RecordBatch> {
+    let mask = Array::from((0..batch.num_rows()).map(|i| i < n).collect::<Vec<bool>>());
+    let data = batch
+        .columns()
+        .iter()
+        .map(|c| match c {
+            Value::Column(_) => Value::Column(Rc::new(filter(c, &mask))),
+            Value::Scalar(_) => c.clone(),
+        })
+        .collect();
+    Rc::new(DefaultRecordBatch {
+        schema: batch.schema().clone(),
+        data,
+        row_count: n,
+    })
+}
@@ -0,0 +1,135 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runtime metrics for relations (used by EXPLAIN ANALYZE)
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+
+use arrow::datatypes::Schema;
+
+/// Metrics collected while scanning a relation
+#[derive(Debug, Clone)]
+pub struct RelationMetrics {
+    /// Number of rows produced by the relation
+    pub rows_out: usize,
+    /// Number of batches produced by the relation
+    pub batches: usize,
+    /// Time spent producing batches, including time spent in the relation's inputs
+    pub elapsed: Duration,
+}
+
+impl RelationMetrics {
+    pub fn new() -> Self {
+        RelationMetrics {
+            rows_out: 0,
+            batches: 0,
+            elapsed: Duration::new(0, 0),
+        }
+    }
+
+    /// Elapsed time in milliseconds
+    pub fn elapsed_millis(&self) -> f64 {
+        self.elapsed.as_secs() as f64 * 1000.0 + self.elapsed.subsec_nanos() as f64 / 1000000.0
+    }
+}
+
+/// Describes one relation in an execution plan along with the metrics for that relation and
+/// the metrics for its inputs
+pub struct MetricsNode {
+    pub name: String,
+    pub metrics: Rc<RefCell<RelationMetrics>>,
+    pub children: Vec<MetricsNode>,
+}
+
+impl MetricsNode {
+    /// Number of rows received from the inputs to this relation
+    pub fn rows_in(&self) -> usize {
+        self.children
+            .iter()
+            .map(|c| c.metrics.borrow().rows_out)
+            .sum()
+    }
+
+    /// Write the tree of relations using the same indentation as the logical plan
+    pub fn fmt_with_indent(&self, s: &mut String, indent: usize) {
+        if indent > 0 {
+            s.push_str("\n");
+            for _ in 0..indent {
+                s.push_str("  ");
+            }
+        }
+        s.push_str(&self.name);
+        self.children
+            .iter()
+            .for_each(|c| c.fmt_with_indent(s, indent + 1));
+    }
+}
+
+/// Relation that wraps another relation and records how many rows and batches it produces and
+/// how long it takes to produce them
+pub struct MetricsRelation {
+    input: Box<SimpleRelation>,
+    metrics: Rc<RefCell<RelationMetrics>>,
+}
+
+impl MetricsRelation {
+    pub fn new(input: Box<SimpleRelation>, metrics: Rc<RefCell<RelationMetrics>>) -> Self {
+        MetricsRelation { input, metrics }
+    }
+}
+
+impl SimpleRelation for MetricsRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        // some relations (such as aggregates) do all of their work before returning an iterator
+        let start = Instant::now();
+        let input = self.input.scan();
+        self.metrics.borrow_mut().elapsed += start.elapsed();
+
+        Box::new(MetricsIterator {
+            input,
+            metrics: self.metrics.clone(),
+        })
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.input.schema()
+    }
+}
+
+struct MetricsIterator<'a> {
+    input: Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>,
+    metrics: Rc<RefCell<RelationMetrics>>,
+}
+
+impl<'a> Iterator for MetricsIterator<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        let next = self.input.next();
+        let mut metrics = self.metrics.borrow_mut();
+        metrics.elapsed += start.elapsed();
+        if let Some(Ok(ref batch)) = next {
+            metrics.rows_out += batch.num_rows();
+            metrics.batches += 1;
+        }
+        next
+    }
+}
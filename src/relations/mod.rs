@@ -15,4 +15,6 @@
 pub mod aggregate;
 pub mod filter;
 pub mod limit;
+pub mod metrics;
 pub mod projection;
+pub mod sort;
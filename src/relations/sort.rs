@@ -14,10 +14,196 @@
 
 //! Relational Sort
 
-//pub struct SortRelation {
-//    schema: Schema,
-//    input: Box<SimpleRelation>,
-//    sort_expr: Vec<CompiledExpr>,
-//    sort_asc: Vec<bool>
-//}
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::rc::Rc;
 
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::types::*;
+
+use arrow::datatypes::*;
+
+/// The number of input batches that are kept for the rows in the heap before those rows are
+/// copied into a single batch
+const MAX_RETAINED_BATCHES: usize = 16;
+
+/// Returns the first `k` rows of the input in sort order. Only the best `k` rows seen so far
+/// are kept, in a heap, rather than sorting the whole input.
+pub struct TopKRelation {
+    schema: Rc<Schema>,
+    input: Box<SimpleRelation>,
+    sort_expr: Vec<CompiledExpr>,
+    sort_asc: Rc<Vec<bool>>,
+    k: usize,
+}
+
+impl TopKRelation {
+    pub fn new(
+        schema: Rc<Schema>,
+        input: Box<SimpleRelation>,
+        sort_expr: Vec<CompiledExpr>,
+        sort_asc: Vec<bool>,
+        k: usize,
+    ) -> Self {
+        TopKRelation {
+            schema,
+            input,
+            sort_expr,
+            sort_asc: Rc::new(sort_asc),
+            k,
+        }
+    }
+
+    fn top_k(&mut self) -> Result<Option<Rc<RecordBatch>>> {
+        let mut heap: BinaryHeap<TopKRow> = BinaryHeap::with_capacity(self.k + 1);
+        // the batches that rows in the heap were read from
+        let mut batches: Vec<Rc<RecordBatch>> = vec![];
+        let mut seq = 0;
+        let schema = self.schema.clone();
+        if self.k > 0 {
+            for batch in self.input.scan() {
+                let batch = batch?;
+                let keys: Vec<Value> = self
+                    .sort_expr
+                    .iter()
+                    .map(|e| (*e)(batch.as_ref()))
+                    .collect::<Result<_>>()?;
+                let mut retained = false;
+                for i in 0..batch.num_rows() {
+                    let row = TopKRow {
+                        key: keys.iter().map(|k| get_column_value(k, i)).collect(),
+                        asc: self.sort_asc.clone(),
+                        seq,
+                        batch: batches.len(),
+                        row: i,
+                    };
+                    seq += 1;
+                    // replace the worst row if this row is better
+                    if heap.len() < self.k {
+                        heap.push(row);
+                        retained = true;
+                    } else if row < *heap.peek().unwrap() {
+                        heap.pop();
+                        heap.push(row);
+                        retained = true;
+                    }
+                }
+                if retained {
+                    batches.push(batch);
+                }
+                if batches.len() > MAX_RETAINED_BATCHES {
+                    // copy the rows in the heap into one batch so that the others can be freed
+                    let mut rows = heap.into_vec();
+                    let batch = take_batch(&schema, &batches, &rows)?;
+                    for (i, row) in rows.iter_mut().enumerate() {
+                        row.batch = 0;
+                        row.row = i;
+                    }
+                    heap = BinaryHeap::from(rows);
+                    batches = vec![batch];
+                }
+            }
+        }
+        if heap.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(take_batch(
+            &schema,
+            &batches,
+            &heap.into_sorted_vec(),
+        )?))
+    }
+}
+
+impl SimpleRelation for TopKRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        match self.top_k() {
+            Ok(Some(batch)) => Box::new(vec![Ok(batch)].into_iter()),
+            Ok(None) => Box::new(vec![].into_iter()),
+            Err(e) => Box::new(vec![Err(e)].into_iter()),
+        }
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+/// Create a batch from the values of the rows, which are read from `batches`
+fn take_batch(
+    schema: &Rc<Schema>,
+    batches: &[Rc<RecordBatch>],
+    rows: &[TopKRow],
+) -> Result<Rc<RecordBatch>> {
+    let rows: Vec<(usize, usize)> = rows.iter().map(|r| (r.batch, r.row)).collect();
+    let data = schema
+        .columns()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let columns: Vec<Value> = batches.iter().map(|b| b.column(i).clone()).collect();
+            Ok(Value::Column(Rc::new(take_rows(
+                &columns,
+                &rows,
+                field.data_type(),
+            )?)))
+        })
+        .collect::<Result<_>>()?;
+    Ok(Rc::new(DefaultRecordBatch {
+        schema: schema.clone(),
+        data,
+        row_count: rows.len(),
+    }))
+}
+
+/// A row in the heap, which is ordered by its sort key and then by its position in the input
+/// so that rows with equal keys are returned in input order. The values of the row are read
+/// from the retained batches when the heap is complete.
+struct TopKRow {
+    key: Vec<ScalarValue>,
+    asc: Rc<Vec<bool>>,
+    seq: usize,
+    batch: usize,
+    row: usize,
+}
+
+/// Compare two values of a sort key, with nulls sorted after other values in both ascending
+/// and descending order
+fn compare_sort_values(a: &ScalarValue, b: &ScalarValue, asc: bool) -> Ordering {
+    match (a, b) {
+        (ScalarValue::Null, ScalarValue::Null) => Ordering::Equal,
+        (ScalarValue::Null, _) => Ordering::Greater,
+        (_, ScalarValue::Null) => Ordering::Less,
+        _ if asc => a.compare(b).unwrap_or(Ordering::Equal),
+        _ => b.compare(a).unwrap_or(Ordering::Equal),
+    }
+}
+
+impl Ord for TopKRow {
+    fn cmp(&self, other: &TopKRow) -> Ordering {
+        for i in 0..self.key.len() {
+            let ordering = compare_sort_values(&self.key[i], &other.key[i], self.asc[i]);
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        self.seq.cmp(&other.seq)
+    }
+}
+
+impl PartialOrd for TopKRow {
+    fn partial_cmp(&self, other: &TopKRow) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TopKRow {
+    fn eq(&self, other: &TopKRow) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TopKRow {}
@@ -0,0 +1,1214 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Serialization of plans, expressions, values and schemas
+//!
+//! Values are converted to a JSON document, which is written either as indented JSON text
+//! that is easy to read and diff, or as a compact binary encoding of the same document for
+//! sending plans to workers. Both forms start with the version of the format, and documents
+//! written by a newer version are rejected.
+//!
+//! Enum variants are written as an object with a single key naming the variant, for example
+//! `{"Column": 0}` or `{"Literal": {"Int32": 42}}`, and data types are written by name.
+//! Record batches held in memory are written column by column, with values of the types in
+//! the schema of the batch.
+
+use std::io::{Cursor, Read};
+use std::rc::Rc;
+
+use arrow::datatypes::{DataType, Field, Schema};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use json;
+use json::number::Number;
+use json::JsonValue;
+
+use super::datasources::common::*;
+use super::errors::*;
+use super::exec::PhysicalPlan;
+use super::logical::*;
+use super::types::*;
+
+/// Version of the serialization format, which is increased when the format changes
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Bytes at the start of the binary form
+const MAGIC: &[u8; 4] = b"DFSR";
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_NUMBER: u8 = 3;
+const TAG_STRING: u8 = 4;
+const TAG_ARRAY: u8 = 5;
+const TAG_OBJECT: u8 = 6;
+
+/// A value that can be converted to and from a JSON document
+pub trait Serializable: Sized {
+    /// Convert to a JSON value
+    fn to_value(&self) -> JsonValue;
+
+    /// Convert from a JSON value that was created by `to_value`
+    fn from_value(value: &JsonValue) -> Result<Self>;
+
+    /// Serialize to versioned, indented JSON text
+    fn to_json(&self) -> String {
+        let mut doc = JsonValue::new_object();
+        doc["version"] = FORMAT_VERSION.into();
+        doc["value"] = self.to_value();
+        json::stringify_pretty(doc, 2)
+    }
+
+    /// Deserialize from JSON text that was created by `to_json`
+    fn from_json(s: &str) -> Result<Self> {
+        let doc =
+            json::parse(s).map_err(|e| ExecutionError::General(format!("Invalid JSON: {}", e)))?;
+        match doc["version"].as_u32() {
+            Some(version) => check_version(version)?,
+            None => {
+                return Err(ExecutionError::General(
+                    "Missing format version".to_string(),
+                ))
+            }
+        }
+        Self::from_value(&doc["value"])
+    }
+
+    /// Serialize to the versioned binary form
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        buf.write_u32::<LittleEndian>(FORMAT_VERSION).unwrap();
+        write_value(&mut buf, &self.to_value());
+        buf
+    }
+
+    /// Deserialize from bytes that were created by `to_bytes`
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut r = Cursor::new(bytes);
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ExecutionError::General(
+                "Not a serialized DataFusion value".to_string(),
+            ));
+        }
+        check_version(r.read_u32::<LittleEndian>()?)?;
+        let value = read_value(&mut r)?;
+        if r.position() as usize != bytes.len() {
+            return Err(ExecutionError::General(
+                "Unexpected bytes after serialized value".to_string(),
+            ));
+        }
+        Self::from_value(&value)
+    }
+}
+
+fn check_version(version: u32) -> Result<()> {
+    if version > FORMAT_VERSION {
+        Err(ExecutionError::General(format!(
+            "Unsupported serialization format version {} (the latest supported version is {})",
+            version, FORMAT_VERSION
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    buf.write_u32::<LittleEndian>(s.len() as u32).unwrap();
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &JsonValue) {
+    match value {
+        JsonValue::Null => buf.push(TAG_NULL),
+        JsonValue::Boolean(false) => buf.push(TAG_FALSE),
+        JsonValue::Boolean(true) => buf.push(TAG_TRUE),
+        JsonValue::Number(n) => {
+            let (positive, mantissa, exponent) = n.as_parts();
+            buf.push(TAG_NUMBER);
+            buf.push(positive as u8);
+            buf.write_u64::<LittleEndian>(mantissa).unwrap();
+            buf.write_i16::<LittleEndian>(exponent).unwrap();
+        }
+        JsonValue::Short(_) | JsonValue::String(_) => {
+            buf.push(TAG_STRING);
+            write_string(buf, value.as_str().unwrap());
+        }
+        JsonValue::Array(values) => {
+            buf.push(TAG_ARRAY);
+            buf.write_u32::<LittleEndian>(values.len() as u32).unwrap();
+            for v in values {
+                write_value(buf, v);
+            }
+        }
+        JsonValue::Object(_) => {
+            buf.push(TAG_OBJECT);
+            buf.write_u32::<LittleEndian>(value.len() as u32).unwrap();
+            for (k, v) in value.entries() {
+                write_string(buf, k);
+                write_value(buf, v);
+            }
+        }
+    }
+}
+
+fn read_string(r: &mut Cursor<&[u8]>) -> Result<String> {
+    let len = r.read_u32::<LittleEndian>()? as usize;
+    let mut bytes = vec![0; len];
+    r.read_exact(&mut bytes)?;
+    String::from_utf8(bytes)
+        .map_err(|_| ExecutionError::General("Invalid UTF-8 in serialized string".to_string()))
+}
+
+fn read_value(r: &mut Cursor<&[u8]>) -> Result<JsonValue> {
+    match r.read_u8()? {
+        TAG_NULL => Ok(JsonValue::Null),
+        TAG_FALSE => Ok(JsonValue::Boolean(false)),
+        TAG_TRUE => Ok(JsonValue::Boolean(true)),
+        TAG_NUMBER => {
+            let positive = r.read_u8()? != 0;
+            let mantissa = r.read_u64::<LittleEndian>()?;
+            let exponent = r.read_i16::<LittleEndian>()?;
+            Ok(JsonValue::Number(Number::from_parts(
+                positive, mantissa, exponent,
+            )))
+        }
+        TAG_STRING => Ok(read_string(r)?.into()),
+        TAG_ARRAY => {
+            let len = r.read_u32::<LittleEndian>()?;
+            let values = (0..len).map(|_| read_value(r)).collect::<Result<_>>()?;
+            Ok(JsonValue::Array(values))
+        }
+        TAG_OBJECT => {
+            let len = r.read_u32::<LittleEndian>()?;
+            let mut value = JsonValue::new_object();
+            for _ in 0..len {
+                let key = read_string(r)?;
+                value[key.as_str()] = read_value(r)?;
+            }
+            Ok(value)
+        }
+        tag => Err(ExecutionError::General(format!(
+            "Invalid tag {} in serialized value",
+            tag
+        ))),
+    }
+}
+
+/// Create an object for an enum variant
+fn variant(name: &str, value: JsonValue) -> JsonValue {
+    let mut v = JsonValue::new_object();
+    v[name] = value;
+    v
+}
+
+/// Get the name and value of an enum variant
+fn get_variant(value: &JsonValue) -> Result<(&str, &JsonValue)> {
+    match value.entries().next() {
+        Some(entry) if value.len() == 1 => Ok(entry),
+        _ => Err(ExecutionError::General(format!(
+            "Expected an object with a single variant but found {}",
+            value.dump()
+        ))),
+    }
+}
+
+fn unknown_variant<T>(kind: &str, name: &str) -> Result<T> {
+    Err(ExecutionError::General(format!(
+        "Unknown {} variant '{}'",
+        kind, name
+    )))
+}
+
+fn get_field<'a>(value: &'a JsonValue, name: &str) -> Result<&'a JsonValue> {
+    match value[name] {
+        JsonValue::Null => Err(ExecutionError::General(format!(
+            "Missing field '{}' in {}",
+            name,
+            value.dump()
+        ))),
+        ref v => Ok(v),
+    }
+}
+
+fn invalid_field<T>(name: &str, value: &JsonValue) -> Result<T> {
+    Err(ExecutionError::General(format!(
+        "Invalid value {} for field '{}'",
+        value.dump(),
+        name
+    )))
+}
+
+fn get_string(value: &JsonValue, name: &str) -> Result<String> {
+    let v = get_field(value, name)?;
+    match v.as_str() {
+        Some(s) => Ok(s.to_string()),
+        None => invalid_field(name, v),
+    }
+}
+
+fn get_bool(value: &JsonValue, name: &str) -> Result<bool> {
+    let v = get_field(value, name)?;
+    match v.as_bool() {
+        Some(b) => Ok(b),
+        None => invalid_field(name, v),
+    }
+}
+
+fn get_usize(value: &JsonValue, name: &str) -> Result<usize> {
+    let v = get_field(value, name)?;
+    match v.as_usize() {
+        Some(n) => Ok(n),
+        None => invalid_field(name, v),
+    }
+}
+
+fn get_u8(value: &JsonValue, name: &str) -> Result<u8> {
+    let v = get_field(value, name)?;
+    match v.as_u8() {
+        Some(n) => Ok(n),
+        None => invalid_field(name, v),
+    }
+}
+
+fn get_list<T, F>(value: &JsonValue, name: &str, f: F) -> Result<Vec<T>>
+where
+    F: Fn(&JsonValue) -> Result<T>,
+{
+    list_from_value(get_field(value, name)?, name, f)
+}
+
+fn list_from_value<T, F>(value: &JsonValue, name: &str, f: F) -> Result<Vec<T>>
+where
+    F: Fn(&JsonValue) -> Result<T>,
+{
+    if !value.is_array() {
+        return invalid_field(name, value);
+    }
+    value.members().map(f).collect()
+}
+
+fn list_value<T: Serializable>(values: &[T]) -> JsonValue {
+    JsonValue::Array(values.iter().map(|v| v.to_value()).collect())
+}
+
+fn projection_value(projection: &Option<Vec<usize>>) -> JsonValue {
+    match projection {
+        Some(ref p) => JsonValue::Array(p.iter().map(|i| (*i).into()).collect()),
+        None => JsonValue::Null,
+    }
+}
+
+fn get_projection(value: &JsonValue) -> Result<Option<Vec<usize>>> {
+    if value["projection"].is_null() {
+        Ok(None)
+    } else {
+        let indices = get_list(value, "projection", |v| match v.as_usize() {
+            Some(i) => Ok(i),
+            None => invalid_field("projection", v),
+        })?;
+        Ok(Some(indices))
+    }
+}
+
+fn get_schema(value: &JsonValue, name: &str) -> Result<Rc<Schema>> {
+    Ok(Rc::new(Schema::from_value(get_field(value, name)?)?))
+}
+
+fn get_plan(value: &JsonValue, name: &str) -> Result<Rc<LogicalPlan>> {
+    Ok(Rc::new(LogicalPlan::from_value(get_field(value, name)?)?))
+}
+
+fn get_expr(value: &JsonValue, name: &str) -> Result<Rc<Expr>> {
+    Ok(Rc::new(Expr::from_value(get_field(value, name)?)?))
+}
+
+fn get_exprs(value: &JsonValue, name: &str) -> Result<Vec<Expr>> {
+    get_list(value, name, Expr::from_value)
+}
+
+/// Convert a value to JSON without its type, which is known from a schema. Floating point
+/// values that are not finite are written as strings because JSON numbers cannot hold them.
+fn untyped_value(v: &ScalarValue) -> JsonValue {
+    match v {
+        ScalarValue::Null => JsonValue::Null,
+        ScalarValue::Boolean(v) => (*v).into(),
+        ScalarValue::Int8(v) => i64_value(*v as i64),
+        ScalarValue::Int16(v) => i64_value(*v as i64),
+        ScalarValue::Int32(v) => i64_value(*v as i64),
+        ScalarValue::Int64(v) => i64_value(*v),
+        ScalarValue::UInt8(v) => (*v).into(),
+        ScalarValue::UInt16(v) => (*v).into(),
+        ScalarValue::UInt32(v) => (*v).into(),
+        ScalarValue::UInt64(v) => (*v).into(),
+        ScalarValue::Float32(v) if v.is_finite() => (*v).into(),
+        ScalarValue::Float32(v) => v.to_string().into(),
+        ScalarValue::Float64(v) if v.is_finite() => (*v).into(),
+        ScalarValue::Float64(v) => v.to_string().into(),
+        ScalarValue::Utf8(ref v) => v.as_str().into(),
+        ScalarValue::Struct(ref v) => JsonValue::Array(v.iter().map(untyped_value).collect()),
+    }
+}
+
+/// Convert a signed integer to JSON, which the `json` crate cannot do for the minimum value
+/// of each signed type
+fn i64_value(v: i64) -> JsonValue {
+    let magnitude = if v < 0 {
+        (v as u64).wrapping_neg()
+    } else {
+        v as u64
+    };
+    JsonValue::Number(Number::from_parts(v >= 0, magnitude, 0))
+}
+
+fn i64_from_value(value: &JsonValue) -> Option<i64> {
+    match value {
+        JsonValue::Number(n) => match n.as_parts() {
+            (true, mantissa, 0) if mantissa <= i64::max_value() as u64 => Some(mantissa as i64),
+            (false, mantissa, 0) if mantissa <= 1 << 63 => Some((mantissa as i64).wrapping_neg()),
+            (_, _, 0) => None,
+            _ => value.as_i64(),
+        },
+        _ => None,
+    }
+}
+
+/// Convert JSON created by `untyped_value` back to a value of the specified type
+fn typed_value(value: &JsonValue, data_type: &DataType) -> Result<ScalarValue> {
+    macro_rules! number {
+        ($ACCESSOR:ident, $SV:ident) => {
+            value.$ACCESSOR().map(ScalarValue::$SV)
+        };
+    }
+    macro_rules! signed {
+        ($TY:ident, $SV:ident) => {
+            i64_from_value(value)
+                .filter(|v| *v >= $TY::min_value() as i64 && *v <= $TY::max_value() as i64)
+                .map(|v| ScalarValue::$SV(v as $TY))
+        };
+    }
+    // floats are parsed from their text so that they round trip exactly
+    macro_rules! float {
+        ($TY:ty, $SV:ident) => {
+            if value.is_number() || value.is_string() {
+                let text = value
+                    .as_str()
+                    .map(|s| s.to_string())
+                    .unwrap_or(value.dump());
+                text.parse::<$TY>().ok().map(ScalarValue::$SV)
+            } else {
+                None
+            }
+        };
+    }
+    if value.is_null() {
+        return Ok(ScalarValue::Null);
+    }
+    let scalar = match data_type {
+        DataType::Boolean => value.as_bool().map(ScalarValue::Boolean),
+        DataType::Int8 => signed!(i8, Int8),
+        DataType::Int16 => signed!(i16, Int16),
+        DataType::Int32 => signed!(i32, Int32),
+        DataType::Int64 => i64_from_value(value).map(ScalarValue::Int64),
+        DataType::UInt8 => number!(as_u8, UInt8),
+        DataType::UInt16 => number!(as_u16, UInt16),
+        DataType::UInt32 => number!(as_u32, UInt32),
+        DataType::UInt64 => number!(as_u64, UInt64),
+        DataType::Float32 => float!(f32, Float32),
+        DataType::Float64 => float!(f64, Float64),
+        DataType::Utf8 => value
+            .as_str()
+            .map(|s| ScalarValue::Utf8(Rc::new(s.to_string()))),
+        DataType::Struct(ref fields) if value.is_array() && value.len() == fields.len() => {
+            let values = value
+                .members()
+                .zip(fields.iter())
+                .map(|(v, f)| typed_value(v, f.data_type()))
+                .collect::<Result<_>>()?;
+            Some(ScalarValue::Struct(values))
+        }
+        _ => None,
+    };
+    match scalar {
+        Some(v) => Ok(v),
+        None => Err(ExecutionError::General(format!(
+            "Cannot read {} as a value of type {:?}",
+            value.dump(),
+            data_type
+        ))),
+    }
+}
+
+impl Serializable for DataType {
+    fn to_value(&self) -> JsonValue {
+        match self {
+            DataType::Struct(fields) => variant("Struct", list_value(fields)),
+            other => format!("{:?}", other).into(),
+        }
+    }
+
+    fn from_value(value: &JsonValue) -> Result<Self> {
+        if let Some(name) = value.as_str() {
+            return match name {
+                "Boolean" => Ok(DataType::Boolean),
+                "Int8" => Ok(DataType::Int8),
+                "Int16" => Ok(DataType::Int16),
+                "Int32" => Ok(DataType::Int32),
+                "Int64" => Ok(DataType::Int64),
+                "UInt8" => Ok(DataType::UInt8),
+                "UInt16" => Ok(DataType::UInt16),
+                "UInt32" => Ok(DataType::UInt32),
+                "UInt64" => Ok(DataType::UInt64),
+                "Float16" => Ok(DataType::Float16),
+                "Float32" => Ok(DataType::Float32),
+                "Float64" => Ok(DataType::Float64),
+                "Utf8" => Ok(DataType::Utf8),
+                _ => unknown_variant("data type", name),
+            };
+        }
+        match get_variant(value)? {
+            ("Struct", fields) => Ok(DataType::Struct(list_from_value(
+                fields,
+                "Struct",
+                Field::from_value,
+            )?)),
+            (name, _) => unknown_variant("data type", name),
+        }
+    }
+}
+
+impl Serializable for Field {
+    fn to_value(&self) -> JsonValue {
+        let mut v = JsonValue::new_object();
+        v["name"] = self.name().as_str().into();
+        v["data_type"] = self.data_type().to_value();
+        v["nullable"] = self.is_nullable().into();
+        v
+    }
+
+    fn from_value(value: &JsonValue) -> Result<Self> {
+        Ok(Field::new(
+            &get_string(value, "name")?,
+            DataType::from_value(get_field(value, "data_type")?)?,
+            get_bool(value, "nullable")?,
+        ))
+    }
+}
+
+impl Serializable for Schema {
+    fn to_value(&self) -> JsonValue {
+        let mut v = JsonValue::new_object();
+        v["fields"] = list_value(self.columns());
+        v
+    }
+
+    fn from_value(value: &JsonValue) -> Result<Self> {
+        Ok(Schema::new(get_list(value, "fields", Field::from_value)?))
+    }
+}
+
+impl Serializable for ScalarValue {
+    fn to_value(&self) -> JsonValue {
+        match self {
+            ScalarValue::Null => "Null".into(),
+            ScalarValue::Struct(ref values) => variant("Struct", list_value(values)),
+            other => variant(&format!("{:?}", other.get_datatype()), untyped_value(other)),
+        }
+    }
+
+    fn from_value(value: &JsonValue) -> Result<Self> {
+        match value.as_str() {
+            Some("Null") => return Ok(ScalarValue::Null),
+            Some(name) => return unknown_variant("scalar value", name),
+            None => {}
+        }
+        match get_variant(value)? {
+            ("Struct", values) => Ok(ScalarValue::Struct(list_from_value(
+                values,
+                "Struct",
+                ScalarValue::from_value,
+            )?)),
+            (name, v) => {
+                let data_type = DataType::from_value(&name.into())?;
+                match typed_value(v, &data_type)? {
+                    ScalarValue::Null => invalid_field(name, v),
+                    scalar => Ok(scalar),
+                }
+            }
+        }
+    }
+}
+
+impl Serializable for Operator {
+    fn to_value(&self) -> JsonValue {
+        format!("{:?}", self).into()
+    }
+
+    fn from_value(value: &JsonValue) -> Result<Self> {
+        match value.as_str() {
+            Some("Eq") => Ok(Operator::Eq),
+            Some("NotEq") => Ok(Operator::NotEq),
+            Some("Lt") => Ok(Operator::Lt),
+            Some("LtEq") => Ok(Operator::LtEq),
+            Some("Gt") => Ok(Operator::Gt),
+            Some("GtEq") => Ok(Operator::GtEq),
+            Some("Plus") => Ok(Operator::Plus),
+            Some("Minus") => Ok(Operator::Minus),
+            Some("Multiply") => Ok(Operator::Multiply),
+            Some("Divide") => Ok(Operator::Divide),
+            Some("Modulus") => Ok(Operator::Modulus),
+            Some("And") => Ok(Operator::And),
+            Some("Or") => Ok(Operator::Or),
+            Some("Like") => Ok(Operator::Like),
+            Some("ILike") => Ok(Operator::ILike),
+            _ => unknown_variant("operator", &value.dump()),
+        }
+    }
+}
+
+fn function_value(name: &str, args: &[Expr], return_type: &DataType) -> JsonValue {
+    let mut v = JsonValue::new_object();
+    v["name"] = name.into();
+    v["args"] = list_value(args);
+    v["return_type"] = return_type.to_value();
+    v
+}
+
+impl Serializable for Expr {
+    fn to_value(&self) -> JsonValue {
+        match self {
+            Expr::Column(i) => variant("Column", (*i).into()),
+            Expr::ColumnRef { relation, name } => {
+                let mut v = JsonValue::new_object();
+                v["relation"] = relation.as_ref().map(|r| r.as_str()).into();
+                v["name"] = name.as_str().into();
+                variant("ColumnRef", v)
+            }
+            Expr::Literal(value) => variant("Literal", value.to_value()),
+            Expr::BinaryExpr { left, op, right } => {
+                let mut v = JsonValue::new_object();
+                v["left"] = left.to_value();
+                v["op"] = op.to_value();
+                v["right"] = right.to_value();
+                variant("BinaryExpr", v)
+            }
+            Expr::IsNotNull(e) => variant("IsNotNull", e.to_value()),
+            Expr::Not(e) => variant("Not", e.to_value()),
+            Expr::IsNull(e) => variant("IsNull", e.to_value()),
+            Expr::Cast { expr, data_type } => {
+                let mut v = JsonValue::new_object();
+                v["expr"] = expr.to_value();
+                v["data_type"] = data_type.to_value();
+                variant("Cast", v)
+            }
+            Expr::Sort { expr, asc } => {
+                let mut v = JsonValue::new_object();
+                v["expr"] = expr.to_value();
+                v["asc"] = (*asc).into();
+                variant("Sort", v)
+            }
+            Expr::ScalarFunction {
+                name,
+                args,
+                return_type,
+            } => variant("ScalarFunction", function_value(name, args, return_type)),
+            Expr::AggregateFunction {
+                name,
+                args,
+                return_type,
+            } => variant("AggregateFunction", function_value(name, args, return_type)),
+        }
+    }
+
+    fn from_value(value: &JsonValue) -> Result<Self> {
+        let (name, v) = get_variant(value)?;
+        match name {
+            "Column" => match v.as_usize() {
+                Some(i) => Ok(Expr::Column(i)),
+                None => invalid_field(name, v),
+            },
+            "ColumnRef" => Ok(Expr::ColumnRef {
+                relation: if v["relation"].is_null() {
+                    None
+                } else {
+                    Some(get_string(v, "relation")?)
+                },
+                name: get_string(v, "name")?,
+            }),
+            "Literal" => Ok(Expr::Literal(ScalarValue::from_value(v)?)),
+            "BinaryExpr" => Ok(Expr::BinaryExpr {
+                left: get_expr(v, "left")?,
+                op: Operator::from_value(get_field(v, "op")?)?,
+                right: get_expr(v, "right")?,
+            }),
+            "IsNotNull" => Ok(Expr::IsNotNull(Rc::new(Expr::from_value(v)?))),
+            "Not" => Ok(Expr::Not(Rc::new(Expr::from_value(v)?))),
+            "IsNull" => Ok(Expr::IsNull(Rc::new(Expr::from_value(v)?))),
+            "Cast" => Ok(Expr::Cast {
+                expr: get_expr(v, "expr")?,
+                data_type: DataType::from_value(get_field(v, "data_type")?)?,
+            }),
+            "Sort" => Ok(Expr::Sort {
+                expr: get_expr(v, "expr")?,
+                asc: get_bool(v, "asc")?,
+            }),
+            "ScalarFunction" => Ok(Expr::ScalarFunction {
+                name: get_string(v, "name")?,
+                args: get_exprs(v, "args")?,
+                return_type: DataType::from_value(get_field(v, "return_type")?)?,
+            }),
+            "AggregateFunction" => Ok(Expr::AggregateFunction {
+                name: get_string(v, "name")?,
+                args: get_exprs(v, "args")?,
+                return_type: DataType::from_value(get_field(v, "return_type")?)?,
+            }),
+            _ => unknown_variant("expression", name),
+        }
+    }
+}
+
+impl Serializable for FileOptions {
+    fn to_value(&self) -> JsonValue {
+        let mut v = JsonValue::new_object();
+        v["delimiter"] = self.delimiter.into();
+        v["compression"] = format!("{:?}", self.compression).into();
+        v["partition_count"] = self.partition_count.into();
+        v
+    }
+
+    fn from_value(value: &JsonValue) -> Result<Self> {
+        let compression = match get_string(value, "compression")?.as_ref() {
+            "Uncompressed" => FileCompression::Uncompressed,
+            "Gzip" => FileCompression::Gzip,
+            other => return unknown_variant("compression", other),
+        };
+        Ok(FileOptions {
+            delimiter: get_u8(value, "delimiter")?,
+            compression,
+            partition_count: get_usize(value, "partition_count")?,
+        })
+    }
+}
+
+/// Write the columns of a record batch as lists of values
+fn batch_value(batch: &RecordBatch) -> JsonValue {
+    let mut v = JsonValue::new_object();
+    v["schema"] = batch.schema().to_value();
+    v["columns"] = JsonValue::Array(
+        batch
+            .columns()
+            .iter()
+            .map(|c| {
+                JsonValue::Array(
+                    (0..batch.num_rows())
+                        .map(|i| untyped_value(&get_column_value(c, i)))
+                        .collect(),
+                )
+            })
+            .collect(),
+    );
+    v
+}
+
+fn batch_from_value(value: &JsonValue) -> Result<Rc<RecordBatch>> {
+    let schema = get_schema(value, "schema")?;
+    let columns = get_field(value, "columns")?;
+    if !columns.is_array() || columns.len() != schema.columns().len() {
+        return invalid_field("columns", columns);
+    }
+    let row_count = columns.members().next().map(|c| c.len()).unwrap_or(0);
+    let data = columns
+        .members()
+        .zip(schema.columns().iter())
+        .map(|(values, field)| {
+            if !values.is_array() || values.len() != row_count {
+                return invalid_field("columns", columns);
+            }
+            let values = values
+                .members()
+                .map(|v| typed_value(v, field.data_type()))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Value::Column(Rc::new(scalars_to_array(
+                &values,
+                field.data_type(),
+            )?)))
+        })
+        .collect::<Result<_>>()?;
+    Ok(Rc::new(DefaultRecordBatch {
+        schema,
+        data,
+        row_count,
+    }))
+}
+
+impl Serializable for LogicalPlan {
+    fn to_value(&self) -> JsonValue {
+        let mut v = JsonValue::new_object();
+        let name = match self {
+            LogicalPlan::Limit {
+                limit,
+                input,
+                schema,
+            } => {
+                v["limit"] = (*limit).into();
+                v["input"] = input.to_value();
+                v["schema"] = schema.to_value();
+                "Limit"
+            }
+            LogicalPlan::Projection {
+                expr,
+                input,
+                schema,
+            } => {
+                v["expr"] = list_value(expr);
+                v["input"] = input.to_value();
+                v["schema"] = schema.to_value();
+                "Projection"
+            }
+            LogicalPlan::Selection { expr, input } => {
+                v["expr"] = expr.to_value();
+                v["input"] = input.to_value();
+                "Selection"
+            }
+            LogicalPlan::Aggregate {
+                input,
+                group_expr,
+                aggr_expr,
+                schema,
+            } => {
+                v["input"] = input.to_value();
+                v["group_expr"] = list_value(group_expr);
+                v["aggr_expr"] = list_value(aggr_expr);
+                v["schema"] = schema.to_value();
+                "Aggregate"
+            }
+            LogicalPlan::Sort {
+                expr,
+                input,
+                schema,
+            } => {
+                v["expr"] = list_value(expr);
+                v["input"] = input.to_value();
+                v["schema"] = schema.to_value();
+                "Sort"
+            }
+            LogicalPlan::TableScan {
+                schema_name,
+                table_name,
+                schema,
+                projection,
+                projected_schema,
+                filters,
+            } => {
+                v["schema_name"] = schema_name.as_str().into();
+                v["table_name"] = table_name.as_str().into();
+                v["schema"] = schema.to_value();
+                v["projection"] = projection_value(projection);
+                v["projected_schema"] = projected_schema.to_value();
+                v["filters"] = list_value(filters);
+                "TableScan"
+            }
+            LogicalPlan::CsvFile {
+                filename,
+                schema,
+                has_header,
+                projection,
+                projected_schema,
+                options,
+                filters,
+            } => {
+                v["filename"] = filename.as_str().into();
+                v["schema"] = schema.to_value();
+                v["has_header"] = (*has_header).into();
+                v["projection"] = projection_value(projection);
+                v["projected_schema"] = projected_schema.to_value();
+                v["options"] = options.to_value();
+                v["filters"] = list_value(filters);
+                "CsvFile"
+            }
+            LogicalPlan::NdJsonFile {
+                filename,
+                schema,
+                projection,
+                projected_schema,
+                options,
+                filters,
+            }
+            | LogicalPlan::ParquetFile {
+                filename,
+                schema,
+                projection,
+                projected_schema,
+                options,
+                filters,
+            } => {
+                v["filename"] = filename.as_str().into();
+                v["schema"] = schema.to_value();
+                v["projection"] = projection_value(projection);
+                v["projected_schema"] = projected_schema.to_value();
+                v["options"] = options.to_value();
+                v["filters"] = list_value(filters);
+                match self {
+                    LogicalPlan::NdJsonFile { .. } => "NdJsonFile",
+                    _ => "ParquetFile",
+                }
+            }
+            LogicalPlan::InMemory {
+                data,
+                schema,
+                projection,
+            } => {
+                v["data"] =
+                    JsonValue::Array(data.iter().map(|b| batch_value(b.as_ref())).collect());
+                v["schema"] = schema.to_value();
+                v["projection"] = projection_value(projection);
+                "InMemory"
+            }
+            LogicalPlan::EmptyRelation { schema } => {
+                v["schema"] = schema.to_value();
+                "EmptyRelation"
+            }
+        };
+        variant(name, v)
+    }
+
+    fn from_value(value: &JsonValue) -> Result<Self> {
+        let (name, v) = get_variant(value)?;
+        match name {
+            "Limit" => Ok(LogicalPlan::Limit {
+                limit: get_usize(v, "limit")?,
+                input: get_plan(v, "input")?,
+                schema: get_schema(v, "schema")?,
+            }),
+            "Projection" => Ok(LogicalPlan::Projection {
+                expr: get_exprs(v, "expr")?,
+                input: get_plan(v, "input")?,
+                schema: get_schema(v, "schema")?,
+            }),
+            "Selection" => Ok(LogicalPlan::Selection {
+                expr: Expr::from_value(get_field(v, "expr")?)?,
+                input: get_plan(v, "input")?,
+            }),
+            "Aggregate" => Ok(LogicalPlan::Aggregate {
+                input: get_plan(v, "input")?,
+                group_expr: get_exprs(v, "group_expr")?,
+                aggr_expr: get_exprs(v, "aggr_expr")?,
+                schema: get_schema(v, "schema")?,
+            }),
+            "Sort" => Ok(LogicalPlan::Sort {
+                expr: get_exprs(v, "expr")?,
+                input: get_plan(v, "input")?,
+                schema: get_schema(v, "schema")?,
+            }),
+            "TableScan" => Ok(LogicalPlan::TableScan {
+                schema_name: get_string(v, "schema_name")?,
+                table_name: get_string(v, "table_name")?,
+                schema: get_schema(v, "schema")?,
+                projection: get_projection(v)?,
+                projected_schema: get_schema(v, "projected_schema")?,
+                filters: get_exprs(v, "filters")?,
+            }),
+            "CsvFile" => Ok(LogicalPlan::CsvFile {
+                filename: get_string(v, "filename")?,
+                schema: get_schema(v, "schema")?,
+                has_header: get_bool(v, "has_header")?,
+                projection: get_projection(v)?,
+                projected_schema: get_schema(v, "projected_schema")?,
+                options: FileOptions::from_value(get_field(v, "options")?)?,
+                filters: get_exprs(v, "filters")?,
+            }),
+            "NdJsonFile" => Ok(LogicalPlan::NdJsonFile {
+                filename: get_string(v, "filename")?,
+                schema: get_schema(v, "schema")?,
+                projection: get_projection(v)?,
+                projected_schema: get_schema(v, "projected_schema")?,
+                options: FileOptions::from_value(get_field(v, "options")?)?,
+                filters: get_exprs(v, "filters")?,
+            }),
+            "ParquetFile" => Ok(LogicalPlan::ParquetFile {
+                filename: get_string(v, "filename")?,
+                schema: get_schema(v, "schema")?,
+                projection: get_projection(v)?,
+                projected_schema: get_schema(v, "projected_schema")?,
+                options: FileOptions::from_value(get_field(v, "options")?)?,
+                filters: get_exprs(v, "filters")?,
+            }),
+            "InMemory" => Ok(LogicalPlan::InMemory {
+                data: Rc::new(get_list(v, "data", batch_from_value)?),
+                schema: get_schema(v, "schema")?,
+                projection: get_projection(v)?,
+            }),
+            "EmptyRelation" => Ok(LogicalPlan::EmptyRelation {
+                schema: get_schema(v, "schema")?,
+            }),
+            _ => unknown_variant("logical plan", name),
+        }
+    }
+}
+
+impl Serializable for PhysicalPlan {
+    fn to_value(&self) -> JsonValue {
+        let mut v = JsonValue::new_object();
+        let name = match self {
+            PhysicalPlan::Interactive { plan } => {
+                v["plan"] = plan.to_value();
+                "Interactive"
+            }
+            PhysicalPlan::Write {
+                plan,
+                filename,
+                kind,
+                has_header,
+                delimiter,
+            } => {
+                v["plan"] = plan.to_value();
+                v["filename"] = filename.as_str().into();
+                v["kind"] = kind.as_str().into();
+                v["has_header"] = (*has_header).into();
+                v["delimiter"] = (*delimiter).into();
+                "Write"
+            }
+            PhysicalPlan::Show { plan, count } => {
+                v["plan"] = plan.to_value();
+                v["count"] = (*count).into();
+                "Show"
+            }
+        };
+        variant(name, v)
+    }
+
+    fn from_value(value: &JsonValue) -> Result<Self> {
+        let (name, v) = get_variant(value)?;
+        match name {
+            "Interactive" => Ok(PhysicalPlan::Interactive {
+                plan: get_plan(v, "plan")?,
+            }),
+            "Write" => Ok(PhysicalPlan::Write {
+                plan: get_plan(v, "plan")?,
+                filename: get_string(v, "filename")?,
+                kind: get_string(v, "kind")?,
+                has_header: get_bool(v, "has_header")?,
+                delimiter: get_u8(v, "delimiter")?,
+            }),
+            "Show" => Ok(PhysicalPlan::Show {
+                plan: get_plan(v, "plan")?,
+                count: get_usize(v, "count")?,
+            }),
+            _ => unknown_variant("physical plan", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::bitmap::Bitmap;
+
+    fn round_trip<T: Serializable>(value: &T) -> (T, T) {
+        (
+            T::from_json(&value.to_json()).unwrap(),
+            T::from_bytes(&value.to_bytes()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_data_type_round_trip() {
+        let data_types = vec![
+            DataType::Boolean,
+            DataType::Int8,
+            DataType::UInt64,
+            DataType::Float64,
+            DataType::Utf8,
+            DataType::Struct(vec![
+                Field::new("lat", DataType::Float64, false),
+                Field::new("tags", DataType::Struct(vec![]), true),
+            ]),
+        ];
+        for data_type in &data_types {
+            let (from_json, from_bytes) = round_trip(data_type);
+            assert_eq!(*data_type, from_json);
+            assert_eq!(*data_type, from_bytes);
+        }
+        assert!(DataType::from_value(&"Decimal".into()).is_err());
+    }
+
+    #[test]
+    fn test_scalar_round_trip() {
+        let values = vec![
+            ScalarValue::Null,
+            ScalarValue::Boolean(true),
+            ScalarValue::Int8(-128),
+            ScalarValue::Int32(i32::min_value()),
+            ScalarValue::Int64(i64::min_value()),
+            ScalarValue::UInt64(u64::max_value()),
+            ScalarValue::Float32(1.1),
+            ScalarValue::Float64(0.1),
+            ScalarValue::Float64(::std::f64::consts::PI),
+            ScalarValue::Float64(-1.5e-300),
+            ScalarValue::Float64(::std::f64::INFINITY),
+            ScalarValue::Utf8(Rc::new("naïve \"quoted\"".to_string())),
+            ScalarValue::Struct(vec![ScalarValue::Int32(1), ScalarValue::Null]),
+        ];
+        for value in &values {
+            let (from_json, from_bytes) = round_trip(value);
+            assert_eq!(*value, from_json);
+            assert_eq!(*value, from_bytes);
+        }
+    }
+
+    #[test]
+    fn test_expr_round_trip() {
+        let expr = Expr::BinaryExpr {
+            left: Rc::new(Expr::Cast {
+                expr: Rc::new(Expr::Column(0)),
+                data_type: DataType::Int64,
+            }),
+            op: Operator::Lt,
+            right: Rc::new(Expr::ScalarFunction {
+                name: "sqrt".to_string(),
+                args: vec![
+                    Expr::ColumnRef {
+                        relation: Some("t".to_string()),
+                        name: "a".to_string(),
+                    },
+                    Expr::IsNull(Rc::new(Expr::Literal(ScalarValue::Utf8(Rc::new(
+                        "x".to_string(),
+                    ))))),
+                ],
+                return_type: DataType::Float64,
+            }),
+        };
+        let (from_json, from_bytes) = round_trip(&expr);
+        assert_eq!(expr, from_json);
+        assert_eq!(expr, from_bytes);
+        // the binary form is smaller than the JSON text
+        assert!(expr.to_bytes().len() < expr.to_json().len());
+    }
+
+    #[test]
+    fn test_json_format() {
+        let expr = Expr::Not(Rc::new(Expr::Column(1)));
+        assert_eq!(
+            "{\n  \"version\": 1,\n  \"value\": {\n    \"Not\": {\n      \"Column\": 1\n    }\n  }\n}",
+            expr.to_json()
+        );
+    }
+
+    #[test]
+    fn test_plan_round_trip() {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let filter = Expr::BinaryExpr {
+            left: Rc::new(Expr::Column(0)),
+            op: Operator::Lt,
+            right: Rc::new(Expr::Literal(ScalarValue::Int32(5))),
+        };
+        let plan = LogicalPlan::Limit {
+            limit: 10,
+            input: Rc::new(LogicalPlan::Selection {
+                expr: filter.clone(),
+                input: Rc::new(LogicalPlan::CsvFile {
+                    filename: "people.csv".to_string(),
+                    schema: schema.clone(),
+                    has_header: true,
+                    projection: Some(vec![0]),
+                    projected_schema: Rc::new(Schema::new(vec![schema.column(0).clone()])),
+                    options: FileOptions {
+                        delimiter: b'|',
+                        compression: FileCompression::Gzip,
+                        partition_count: 1,
+                    },
+                    filters: vec![filter],
+                }),
+            }),
+            schema: schema.clone(),
+        };
+        let (from_json, from_bytes) = round_trip(&plan);
+        assert_eq!(plan, from_json);
+        assert_eq!(plan, from_bytes);
+
+        let physical_plan = PhysicalPlan::Show {
+            plan: Rc::new(plan),
+            count: 5,
+        };
+        let (from_json, _) = round_trip(&physical_plan);
+        assert_eq!(physical_plan.to_json(), from_json.to_json());
+    }
+
+    #[test]
+    fn test_in_memory_round_trip() {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let mut bitmap = Bitmap::new(2);
+        bitmap.clear(1);
+        let names = Array::with_nulls(
+            2,
+            ArrayData::Utf8(::arrow::array::ListArray::from(::arrow::list::List::from(
+                vec!["a".to_string(), "".to_string()],
+            ))),
+            1,
+            bitmap,
+        );
+        let batch: Rc<RecordBatch> = Rc::new(DefaultRecordBatch {
+            schema: schema.clone(),
+            data: vec![
+                Value::Column(Rc::new(Array::from(vec![1, 2]))),
+                Value::Column(Rc::new(names)),
+            ],
+            row_count: 2,
+        });
+        let plan = LogicalPlan::InMemory {
+            data: Rc::new(vec![batch]),
+            schema,
+            projection: None,
+        };
+        let (from_json, from_bytes) = round_trip(&plan);
+        assert_eq!(plan.to_json(), from_json.to_json());
+        match from_bytes {
+            LogicalPlan::InMemory { ref data, .. } => {
+                assert_eq!(1, data.len());
+                assert_eq!(2, data[0].num_rows());
+                assert_eq!(
+                    ScalarValue::Int32(2),
+                    get_column_value(data[0].column(0), 1)
+                );
+                assert_eq!(
+                    ScalarValue::Utf8(Rc::new("a".to_string())),
+                    get_column_value(data[0].column(1), 0)
+                );
+                assert_eq!(ScalarValue::Null, get_column_value(data[0].column(1), 1));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let expr = Expr::Column(0);
+        let json = expr.to_json().replace("\"version\": 1", "\"version\": 2");
+        assert!(Expr::from_json(&json).is_err());
+        assert!(Expr::from_json("{\"value\": {\"Column\": 0}}").is_err());
+        assert!(Expr::from_json("{\"version\": 1, \"value\": {\"Foo\": 0}}").is_err());
+
+        let mut bytes = expr.to_bytes();
+        assert!(Expr::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        bytes.push(0);
+        assert!(Expr::from_bytes(&bytes).is_err());
+        assert!(Expr::from_bytes(b"JUNK\x01\0\0\0\0").is_err());
+    }
+}
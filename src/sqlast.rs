@@ -13,6 +13,16 @@
 // limitations under the License.
 
 //! SQL Abstract Syntax Tree (AST) types
+//!
+//! The `Display` implementations produce canonical SQL that can be parsed again with
+//! `Parser::parse_sql`. Parentheses are only written for `SQLNested` nodes and where they are
+//! needed to preserve the structure of the tree, so reparsing the SQL for a tree that was
+//! produced by the parser results in the same tree. The SQL is written in the ANSI dialect,
+//! except for `ILIKE`, which requires the PostgreSQL dialect.
+
+use std::fmt;
+
+use super::sqltokenizer::{is_keyword, Location};
 
 /// Supported file types for `CREATE EXTERNAL TABLE`
 #[derive(Debug, Clone, PartialEq)]
@@ -25,9 +35,11 @@ pub enum FileType {
 /// SQL Abstract Syntax Tree (AST)
 #[derive(Debug, Clone, PartialEq)]
 pub enum ASTNode {
-    SQLIdentifier(String),
+    SQLIdentifier(SQLIdent),
+    /// Identifier enclosed in double quotes, which is matched case-sensitively
+    SQLQuotedIdentifier(SQLIdent),
     SQLWildcard,
-    SQLCompoundIdentifier(Vec<String>),
+    SQLCompoundIdentifier(Vec<SQLIdent>),
     SQLIsNull(Box<ASTNode>),
     SQLIsNotNull(Box<ASTNode>),
     SQLBinaryExpr {
@@ -75,6 +87,105 @@ pub enum ASTNode {
         header_row: bool,
         /// Path to file or directory contianing files
         location: String,
+        /// Do nothing if the table already exists
+        if_not_exists: bool,
+        /// Columns whose values are taken from the names of subdirectories of the location
+        partition_columns: Vec<SQLColumnDef>,
+        /// Options for reading the files, as names and values
+        options: Vec<(String, String)>,
+    },
+    SQLCreateView {
+        /// View name
+        name: String,
+        /// The SELECT statement that defines the view
+        query: Box<ASTNode>,
+        /// Replace the view if it already exists
+        or_replace: bool,
+        /// Do nothing if the view already exists
+        if_not_exists: bool,
+    },
+    SQLCreateFunction {
+        /// Function name
+        name: String,
+        /// Argument names and types
+        args: Vec<(String, SQLType)>,
+        /// Type of the value that the function returns
+        return_type: SQLType,
+        /// SQL expression that computes the result from the arguments
+        body: String,
+        /// Whether the function was declared TEMPORARY
+        temporary: bool,
+    },
+    SQLCreateTableAs {
+        /// Table name
+        name: String,
+        /// File type, or None for tables that are held in memory
+        file_type: Option<FileType>,
+        /// For CSV files, indicate whether the files have a header row or not
+        header_row: bool,
+        /// Directory that will contain the files, or None for tables that are held in memory
+        location: Option<String>,
+        /// The SELECT statement that provides the rows
+        query: Box<ASTNode>,
+        /// Do nothing if the table already exists
+        if_not_exists: bool,
+    },
+    SQLInsert {
+        /// Table name
+        table_name: String,
+        /// The SELECT statement that provides the rows
+        query: Box<ASTNode>,
+    },
+    SQLCopyTo {
+        /// The SELECT statement that provides the rows, or the identifier of a table
+        source: Box<ASTNode>,
+        /// Path of the file that is written
+        location: String,
+        /// Options from the WITH clause, with upper case names
+        options: Vec<(String, String)>,
+    },
+    SQLCopyFrom {
+        /// Table name
+        table_name: String,
+        /// Path to file or directory containing files
+        location: String,
+        /// Options from the WITH clause, with upper case names
+        options: Vec<(String, String)>,
+    },
+    SQLDropTable {
+        /// Table or view name
+        name: String,
+        /// Do nothing if the table does not exist
+        if_exists: bool,
+        /// Whether the statement is DROP VIEW, which only drops views, rather than DROP TABLE,
+        /// which only drops tables
+        view: bool,
+    },
+    /// Change a session setting
+    SQLSetVariable {
+        /// Setting name, in lower case
+        name: String,
+        value: String,
+    },
+    /// Show the value of a session setting, or all settings if the name is None
+    SQLShowVariable {
+        name: Option<String>,
+    },
+    /// List the registered tables and views
+    SQLShowTables,
+    /// List the columns of a table or view
+    SQLShowColumns {
+        table_name: String,
+    },
+    SQLExplain {
+        /// Whether to execute the statement and report runtime metrics
+        analyze: bool,
+        /// The statement being explained
+        statement: Box<ASTNode>,
+    },
+    /// Compute the statistics of a table by reading all of its rows
+    SQLAnalyzeTable {
+        table_name: String,
     },
 }
 
@@ -86,6 +197,36 @@ pub struct SQLColumnDef {
     pub allow_null: bool,
 }
 
+/// An identifier, along with the location of its token when it was parsed from SQL text.
+/// Locations are not compared, so trees that are parsed from different text are equal when
+/// they have the same identifiers.
+#[derive(Debug, Clone)]
+pub struct SQLIdent {
+    pub value: String,
+    pub location: Option<Location>,
+}
+
+impl SQLIdent {
+    /// Create an identifier that was not parsed from SQL text
+    pub fn new(value: &str) -> Self {
+        SQLIdent {
+            value: value.to_string(),
+            location: None,
+        }
+    }
+
+    /// Create an identifier for a token at a known location
+    pub fn with_location(value: String, location: Option<Location>) -> Self {
+        SQLIdent { value, location }
+    }
+}
+
+impl PartialEq for SQLIdent {
+    fn eq(&self, other: &SQLIdent) -> bool {
+        self.value == other.value
+    }
+}
+
 /// SQL datatypes for literals in SQL statements
 #[derive(Debug, Clone, PartialEq)]
 pub enum SQLType {
@@ -119,4 +260,564 @@ pub enum SQLOperator {
     NotEq,
     And,
     Or,
+    Like,
+    ILike,
+    Not,
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileType::CSV => write!(f, "CSV"),
+            FileType::NdJson => write!(f, "NDJSON"),
+            FileType::Parquet => write!(f, "PARQUET"),
+        }
+    }
+}
+
+impl fmt::Display for SQLType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SQLType::Boolean => write!(f, "BOOLEAN"),
+            SQLType::UInt8 => write!(f, "UINT8"),
+            SQLType::UInt16 => write!(f, "UINT16"),
+            SQLType::UInt32 => write!(f, "UINT32"),
+            SQLType::UInt64 => write!(f, "UINT64"),
+            SQLType::Int8 => write!(f, "INT8"),
+            SQLType::Int16 => write!(f, "INT16"),
+            SQLType::Int32 => write!(f, "INT32"),
+            SQLType::Int64 => write!(f, "INT64"),
+            SQLType::Float32 => write!(f, "FLOAT32"),
+            SQLType::Double64 => write!(f, "FLOAT64"),
+            SQLType::Utf8(n) => write!(f, "VARCHAR({})", n),
+        }
+    }
+}
+
+impl SQLOperator {
+    /// Precedence of the operator, matching the precedence used by the parser
+    pub fn precedence(&self) -> u8 {
+        match self {
+            SQLOperator::Or => 5,
+            SQLOperator::And => 10,
+            SQLOperator::Not => 12,
+            SQLOperator::Eq
+            | SQLOperator::NotEq
+            | SQLOperator::Lt
+            | SQLOperator::LtEq
+            | SQLOperator::Gt
+            | SQLOperator::GtEq
+            | SQLOperator::Like
+            | SQLOperator::ILike => 20,
+            SQLOperator::Plus | SQLOperator::Minus => 30,
+            SQLOperator::Multiply | SQLOperator::Divide | SQLOperator::Modulus => 40,
+        }
+    }
+}
+
+impl fmt::Display for SQLOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            SQLOperator::Plus => "+",
+            SQLOperator::Minus => "-",
+            SQLOperator::Multiply => "*",
+            SQLOperator::Divide => "/",
+            SQLOperator::Modulus => "%",
+            SQLOperator::Gt => ">",
+            SQLOperator::Lt => "<",
+            SQLOperator::GtEq => ">=",
+            SQLOperator::LtEq => "<=",
+            SQLOperator::Eq => "=",
+            SQLOperator::NotEq => "!=",
+            SQLOperator::And => "AND",
+            SQLOperator::Or => "OR",
+            SQLOperator::Like => "LIKE",
+            SQLOperator::ILike => "ILIKE",
+            SQLOperator::Not => "NOT",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Precedence of the `IS [NOT] NULL` postfix operators
+const IS_NULL_PRECEDENCE: u8 = 15;
+
+/// Determine whether an identifier can be written without quotes
+pub fn is_plain_identifier(id: &str) -> bool {
+    let mut chars = id.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '@' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@') && !is_keyword(id)
+        }
+        _ => false,
+    }
+}
+
+/// Write an identifier, quoting it if it would not otherwise be parsed as an identifier
+fn fmt_identifier(f: &mut fmt::Formatter, id: &str) -> fmt::Result {
+    if is_plain_identifier(id) {
+        write!(f, "{}", id)
+    } else {
+        write!(f, "\"{}\"", id.replace("\"", "\"\""))
+    }
+}
+
+/// Write the `STORED AS` and `LOCATION` clauses of a CREATE TABLE statement
+fn fmt_storage(
+    f: &mut fmt::Formatter,
+    file_type: &FileType,
+    header_row: bool,
+    location: &str,
+) -> fmt::Result {
+    fmt_stored_as(f, file_type, header_row)?;
+    fmt_location(f, location)
+}
+
+fn fmt_stored_as(f: &mut fmt::Formatter, file_type: &FileType, header_row: bool) -> fmt::Result {
+    write!(f, " STORED AS {}", file_type)?;
+    if *file_type == FileType::CSV {
+        if header_row {
+            write!(f, " WITH HEADER ROW")?;
+        } else {
+            write!(f, " WITHOUT HEADER ROW")?;
+        }
+    }
+    Ok(())
+}
+
+fn fmt_location(f: &mut fmt::Formatter, location: &str) -> fmt::Result {
+    write!(f, " LOCATION '{}'", location.replace("'", "''"))
+}
+
+/// Write a list of column definitions in parentheses
+fn fmt_column_defs(f: &mut fmt::Formatter, columns: &[SQLColumnDef]) -> fmt::Result {
+    write!(f, "(")?;
+    for (i, c) in columns.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        fmt_identifier(f, &c.name)?;
+        write!(
+            f,
+            " {} {}",
+            c.data_type,
+            if c.allow_null { "NULL" } else { "NOT NULL" }
+        )?;
+    }
+    write!(f, ")")
+}
+
+/// Write the value of an option or setting as a word or integer where possible and as a
+/// string literal otherwise
+fn fmt_option_value(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    let is_word = value
+        .chars()
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic())
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let is_integer = value.len() > 0 && value.chars().all(|c| c.is_ascii_digit());
+    if is_word || is_integer {
+        write!(f, "{}", value)
+    } else {
+        write!(f, "'{}'", value.replace("'", "''"))
+    }
+}
+
+/// Write the `WITH` clause of a COPY statement
+fn fmt_copy_options(f: &mut fmt::Formatter, options: &[(String, String)]) -> fmt::Result {
+    if options.len() == 0 {
+        return Ok(());
+    }
+    write!(f, " WITH (")?;
+    for (i, (name, value)) in options.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{} ", name)?;
+        fmt_option_value(f, value)?;
+    }
+    write!(f, ")")
+}
+
+/// Write a comma-separated list of expressions
+fn fmt_list(f: &mut fmt::Formatter, list: &[ASTNode]) -> fmt::Result {
+    for (i, e) in list.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", e)?;
+    }
+    Ok(())
+}
+
+/// Write an expression, enclosed in parentheses if `parens` is true
+fn fmt_operand(f: &mut fmt::Formatter, expr: &ASTNode, parens: bool) -> fmt::Result {
+    if parens {
+        write!(f, "({})", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+impl fmt::Display for ASTNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ASTNode::SQLIdentifier(id) => fmt_identifier(f, &id.value),
+            ASTNode::SQLQuotedIdentifier(id) => {
+                write!(f, "\"{}\"", id.value.replace("\"", "\"\""))
+            }
+            ASTNode::SQLWildcard => write!(f, "*"),
+            ASTNode::SQLCompoundIdentifier(ids) => {
+                for (i, id) in ids.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ".")?;
+                    }
+                    fmt_identifier(f, &id.value)?;
+                }
+                Ok(())
+            }
+            ASTNode::SQLIsNull(expr) => {
+                fmt_operand(f, expr, expr.binary_precedence() < IS_NULL_PRECEDENCE)?;
+                write!(f, " IS NULL")
+            }
+            ASTNode::SQLIsNotNull(expr) => {
+                fmt_operand(f, expr, expr.binary_precedence() < IS_NULL_PRECEDENCE)?;
+                write!(f, " IS NOT NULL")
+            }
+            ASTNode::SQLBinaryExpr { left, op, right } => {
+                let precedence = op.precedence();
+                // operators are left associative, so only the right operand needs parentheses
+                // when it has the same precedence
+                fmt_operand(f, left, left.binary_precedence() < precedence)?;
+                write!(f, " {} ", op)?;
+                let right_parens = match **right {
+                    ASTNode::SQLIsNull(_) | ASTNode::SQLIsNotNull(_) => {
+                        precedence >= IS_NULL_PRECEDENCE
+                    }
+                    _ => right.binary_precedence() <= precedence,
+                };
+                fmt_operand(f, right, right_parens)
+            }
+            ASTNode::SQLCast { expr, data_type } => write!(f, "CAST({} AS {})", expr, data_type),
+            ASTNode::SQLNested(expr) => write!(f, "({})", expr),
+            ASTNode::SQLUnary {
+                operator: SQLOperator::Not,
+                rex,
+            } => match **rex {
+                ASTNode::SQLBinaryExpr { .. } => write!(f, "NOT ({})", rex),
+                _ => write!(f, "NOT {}", rex),
+            },
+            ASTNode::SQLUnary { operator, rex } => {
+                write!(f, "{}", operator)?;
+                match **rex {
+                    ASTNode::SQLBinaryExpr { .. }
+                    | ASTNode::SQLIsNull(_)
+                    | ASTNode::SQLIsNotNull(_) => write!(f, "({})", rex),
+                    // avoid writing `--`, which would start a comment
+                    _ => {
+                        let s = rex.to_string();
+                        if s.starts_with('-') {
+                            write!(f, " {}", s)
+                        } else {
+                            write!(f, "{}", s)
+                        }
+                    }
+                }
+            }
+            ASTNode::SQLLiteralLong(n) => write!(f, "{}", n),
+            ASTNode::SQLLiteralDouble(n) => {
+                // always include a decimal point so that the value is parsed as a double
+                let s = n.to_string();
+                if s.contains('.') {
+                    write!(f, "{}", s)
+                } else {
+                    write!(f, "{}.0", s)
+                }
+            }
+            ASTNode::SQLLiteralString(s) => write!(f, "'{}'", s.replace("'", "''")),
+            ASTNode::SQLFunction { id, args } => {
+                write!(f, "{}(", id)?;
+                fmt_list(f, args)?;
+                write!(f, ")")
+            }
+            ASTNode::SQLOrderBy { expr, asc } => {
+                write!(f, "{} {}", expr, if *asc { "ASC" } else { "DESC" })
+            }
+            ASTNode::SQLSelect {
+                projection,
+                relation,
+                selection,
+                order_by,
+                group_by,
+                having,
+                limit,
+            } => {
+                write!(f, "SELECT ")?;
+                fmt_list(f, projection)?;
+                if let Some(relation) = relation {
+                    write!(f, " FROM {}", relation)?;
+                }
+                if let Some(selection) = selection {
+                    write!(f, " WHERE {}", selection)?;
+                }
+                if let Some(group_by) = group_by {
+                    write!(f, " GROUP BY ")?;
+                    fmt_list(f, group_by)?;
+                }
+                if let Some(having) = having {
+                    write!(f, " HAVING {}", having)?;
+                }
+                if let Some(order_by) = order_by {
+                    write!(f, " ORDER BY ")?;
+                    fmt_list(f, order_by)?;
+                }
+                if let Some(limit) = limit {
+                    write!(f, " LIMIT {}", limit)?;
+                }
+                Ok(())
+            }
+            ASTNode::SQLCreateTable {
+                name,
+                columns,
+                file_type,
+                header_row,
+                location,
+                if_not_exists,
+                partition_columns,
+                options,
+            } => {
+                write!(f, "CREATE EXTERNAL TABLE ")?;
+                if *if_not_exists {
+                    write!(f, "IF NOT EXISTS ")?;
+                }
+                fmt_identifier(f, name)?;
+                if columns.len() > 0 {
+                    write!(f, " ")?;
+                    fmt_column_defs(f, columns)?;
+                }
+                fmt_stored_as(f, file_type, *header_row)?;
+                if partition_columns.len() > 0 {
+                    write!(f, " PARTITIONED BY ")?;
+                    fmt_column_defs(f, partition_columns)?;
+                }
+                fmt_location(f, location)?;
+                if options.len() > 0 {
+                    write!(f, " OPTIONS (")?;
+                    for (i, (name, value)) in options.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(
+                            f,
+                            "'{}' '{}'",
+                            name.replace("'", "''"),
+                            value.replace("'", "''")
+                        )?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            ASTNode::SQLCreateTableAs {
+                name,
+                file_type,
+                header_row,
+                location,
+                query,
+                if_not_exists,
+            } => {
+                write!(f, "CREATE TABLE ")?;
+                if *if_not_exists {
+                    write!(f, "IF NOT EXISTS ")?;
+                }
+                fmt_identifier(f, name)?;
+                if let (Some(file_type), Some(location)) = (file_type, location) {
+                    fmt_storage(f, file_type, *header_row, location)?;
+                }
+                write!(f, " AS {}", query)
+            }
+            ASTNode::SQLInsert { table_name, query } => {
+                write!(f, "INSERT INTO ")?;
+                fmt_identifier(f, table_name)?;
+                write!(f, " {}", query)
+            }
+            ASTNode::SQLCreateView {
+                name,
+                query,
+                or_replace,
+                if_not_exists,
+            } => {
+                write!(f, "CREATE ")?;
+                if *or_replace {
+                    write!(f, "OR REPLACE ")?;
+                }
+                write!(f, "VIEW ")?;
+                if *if_not_exists {
+                    write!(f, "IF NOT EXISTS ")?;
+                }
+                fmt_identifier(f, name)?;
+                write!(f, " AS {}", query)
+            }
+            ASTNode::SQLCreateFunction {
+                name,
+                args,
+                return_type,
+                body,
+                temporary,
+            } => {
+                write!(f, "CREATE ")?;
+                if *temporary {
+                    write!(f, "TEMPORARY ")?;
+                }
+                write!(f, "FUNCTION ")?;
+                fmt_identifier(f, name)?;
+                write!(f, "(")?;
+                for (i, (arg_name, data_type)) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    fmt_identifier(f, arg_name)?;
+                    write!(f, " {}", data_type)?;
+                }
+                write!(
+                    f,
+                    ") RETURNS {} AS '{}'",
+                    return_type,
+                    body.replace("'", "''")
+                )
+            }
+            ASTNode::SQLCopyTo {
+                source,
+                location,
+                options,
+            } => {
+                match **source {
+                    ASTNode::SQLSelect { .. } => write!(f, "COPY ({})", source)?,
+                    _ => write!(f, "COPY {}", source)?,
+                }
+                write!(f, " TO '{}'", location.replace("'", "''"))?;
+                fmt_copy_options(f, options)
+            }
+            ASTNode::SQLCopyFrom {
+                table_name,
+                location,
+                options,
+            } => {
+                write!(f, "COPY ")?;
+                fmt_identifier(f, table_name)?;
+                write!(f, " FROM '{}'", location.replace("'", "''"))?;
+                fmt_copy_options(f, options)
+            }
+            ASTNode::SQLDropTable {
+                name,
+                if_exists,
+                view,
+            } => {
+                write!(f, "DROP {} ", if *view { "VIEW" } else { "TABLE" })?;
+                if *if_exists {
+                    write!(f, "IF EXISTS ")?;
+                }
+                fmt_identifier(f, name)
+            }
+            ASTNode::SQLSetVariable { name, value } => {
+                write!(f, "SET {} = ", name)?;
+                fmt_option_value(f, value)
+            }
+            ASTNode::SQLShowVariable { name } => match name {
+                Some(name) => write!(f, "SHOW {}", name),
+                None => write!(f, "SHOW ALL"),
+            },
+            ASTNode::SQLShowTables => write!(f, "SHOW TABLES"),
+            ASTNode::SQLShowColumns { table_name } => {
+                write!(f, "SHOW COLUMNS FROM ")?;
+                fmt_identifier(f, table_name)
+            }
+            ASTNode::SQLExplain { analyze, statement } => {
+                write!(f, "EXPLAIN ")?;
+                if *analyze {
+                    write!(f, "ANALYZE ")?;
+                }
+                write!(f, "{}", statement)
+            }
+            ASTNode::SQLAnalyzeTable { table_name } => {
+                write!(f, "ANALYZE TABLE ")?;
+                fmt_identifier(f, table_name)
+            }
+        }
+    }
+}
+
+impl ASTNode {
+    /// Precedence of the top-level binary operator of this expression, or a higher precedence
+    /// than any operator if this expression does not need parentheses as an operand
+    fn binary_precedence(&self) -> u8 {
+        match self {
+            ASTNode::SQLBinaryExpr { op, .. } => op.precedence(),
+            ASTNode::SQLUnary {
+                operator: SQLOperator::Not,
+                ..
+            } => SQLOperator::Not.precedence(),
+            _ => u8::max_value(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sqlparser::Parser;
+    use super::*;
+
+    fn round_trip(sql: &str) {
+        let ast = Parser::parse_sql(sql.to_string()).unwrap();
+        assert_eq!(sql, ast.to_string());
+        assert_eq!(ast, Parser::parse_sql(ast.to_string()).unwrap());
+    }
+
+    #[test]
+    fn display_select() {
+        round_trip(
+            "SELECT a, \"first name\", COUNT(*), CAST(b AS FLOAT64), 'it''s', -1, 1.0 \
+             FROM \"my table\" WHERE a IS NULL AND b IS NOT NULL \
+             GROUP BY a HAVING COUNT(*) > 1 ORDER BY a ASC, b DESC LIMIT 10",
+        );
+    }
+
+    #[test]
+    fn display_operator_precedence() {
+        round_trip("SELECT a - b - c, a - (b - c), (a + b) * c, a + b * c, -(a + b), a - -1");
+        round_trip("SELECT a FROM t WHERE (a OR b) AND c OR d IS NULL");
+        round_trip("SELECT a FROM t WHERE a = b IS NULL AND (a = b) IS NOT NULL");
+        round_trip("SELECT a FROM t WHERE NOT a AND NOT (b = c) OR (NOT d) = (NOT NOT e)");
+    }
+
+    #[test]
+    fn display_adds_required_parentheses() {
+        let ast = ASTNode::SQLBinaryExpr {
+            left: Box::new(ASTNode::SQLBinaryExpr {
+                left: Box::new(ASTNode::SQLIdentifier(SQLIdent::new("a"))),
+                op: SQLOperator::Plus,
+                right: Box::new(ASTNode::SQLIdentifier(SQLIdent::new("b"))),
+            }),
+            op: SQLOperator::Multiply,
+            right: Box::new(ASTNode::SQLIdentifier(SQLIdent::new("location"))),
+        };
+        assert_eq!("(a + b) * \"location\"", ast.to_string());
+    }
+
+    #[test]
+    fn display_create_table() {
+        round_trip(
+            "CREATE EXTERNAL TABLE uk_cities (city VARCHAR(100) NOT NULL, lat FLOAT64 NULL) \
+             STORED AS CSV WITHOUT HEADER ROW LOCATION 'test/data/uk_cities.csv'",
+        );
+        round_trip("CREATE EXTERNAL TABLE t STORED AS NDJSON LOCATION 'a.json'");
+        round_trip("CREATE TEMPORARY FUNCTION quarter(m INT64) RETURNS INT64 AS '(m - 1) / 3 + 1'");
+        round_trip("CREATE FUNCTION \"Greet Me\"() RETURNS VARCHAR(10) AS 'concat(''hi'', ''!'')'");
+        round_trip(
+            "CREATE EXTERNAL TABLE t STORED AS CSV WITH HEADER ROW \
+             PARTITIONED BY (year INT32 NOT NULL) LOCATION 'data' \
+             OPTIONS ('delimiter' '|', 'compression' 'gzip')",
+        );
+        round_trip("EXPLAIN ANALYZE SELECT 1");
+    }
 }
@@ -0,0 +1,130 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SQL Dialects
+//!
+//! A dialect controls how SQL text is tokenized: which words are keywords, which characters
+//! quote identifiers and string literals, and which dialect-specific syntax is accepted. All
+//! dialects produce the same AST, so the query planner does not need to know which dialect was
+//! used.
+//!
+//! | Dialect      | Quoted identifiers | String literals      | Extensions                  |
+//! |--------------|--------------------|----------------------|-----------------------------|
+//! | `ansi`       | `"name"`           | `'text'`             |                             |
+//! | `postgresql` | `"name"`           | `'text'`             | `expr::type` casts, `ILIKE` |
+//! | `hive`       | `` `name` ``       | `'text'` or `"text"` | see `HiveDialect`           |
+
+use std::rc::Rc;
+
+use super::sqltokenizer::is_keyword;
+
+/// A SQL dialect
+pub trait Dialect {
+    /// Name of the dialect e.g. "ansi"
+    fn name(&self) -> &str;
+
+    /// Determine whether an upper case word is a keyword. Dialect-specific operators such as
+    /// `ILIKE` are enabled by making them keywords.
+    fn is_keyword(&self, word: &str) -> bool {
+        is_keyword(word)
+    }
+
+    /// Determine whether a character opens and closes a quoted identifier
+    fn is_identifier_quote(&self, ch: char) -> bool {
+        ch == '"'
+    }
+
+    /// Determine whether a character opens and closes a string literal
+    fn is_string_quote(&self, ch: char) -> bool {
+        ch == '\''
+    }
+
+    /// Determine whether casts can be written as `expr::type`
+    fn supports_double_colon_cast(&self) -> bool {
+        false
+    }
+}
+
+/// ANSI SQL, which is the default dialect
+pub struct AnsiDialect {}
+
+impl Dialect for AnsiDialect {
+    fn name(&self) -> &str {
+        "ansi"
+    }
+}
+
+/// PostgreSQL dialect, which adds `::` casts and `ILIKE`
+pub struct PostgreSqlDialect {}
+
+impl Dialect for PostgreSqlDialect {
+    fn name(&self) -> &str {
+        "postgresql"
+    }
+
+    fn is_keyword(&self, word: &str) -> bool {
+        word == "ILIKE" || is_keyword(word)
+    }
+
+    fn supports_double_colon_cast(&self) -> bool {
+        true
+    }
+}
+
+/// Keywords of the Hive dialect that are not ANSI keywords
+const HIVE_KEYWORDS: &'static [&'static str] = &[
+    "BIGINT",
+    "DELIMITED",
+    "FIELDS",
+    "FORMAT",
+    "SMALLINT",
+    "SORT",
+    "TERMINATED",
+    "TEXTFILE",
+    "TINYINT",
+];
+
+/// Hive dialect, where identifiers are quoted with backticks and strings may be enclosed in
+/// either single or double quotes. External tables can be declared with Hive's `ROW FORMAT
+/// DELIMITED FIELDS TERMINATED BY` and `STORED AS TEXTFILE` clauses, queries can be sorted with
+/// `SORT BY`, and the Hive integer type names can be used.
+pub struct HiveDialect {}
+
+impl Dialect for HiveDialect {
+    fn name(&self) -> &str {
+        "hive"
+    }
+
+    fn is_keyword(&self, word: &str) -> bool {
+        HIVE_KEYWORDS.contains(&word) || is_keyword(word)
+    }
+
+    fn is_identifier_quote(&self, ch: char) -> bool {
+        ch == '`'
+    }
+
+    fn is_string_quote(&self, ch: char) -> bool {
+        ch == '\'' || ch == '"'
+    }
+}
+
+/// Look up a dialect by name, ignoring case
+pub fn dialect_from_name(name: &str) -> Option<Rc<Dialect>> {
+    match name.to_lowercase().as_ref() {
+        "ansi" => Some(Rc::new(AnsiDialect {})),
+        "postgresql" | "postgres" => Some(Rc::new(PostgreSqlDialect {})),
+        "hive" => Some(Rc::new(HiveDialect {})),
+        _ => None,
+    }
+}
@@ -14,7 +14,11 @@
 
 //! SQL Parser
 
+use std::fmt;
+use std::rc::Rc;
+
 use super::sqlast::*;
+use super::sqldialect::*;
 use super::sqltokenizer::*;
 
 #[derive(Debug, Clone)]
@@ -23,6 +27,15 @@ pub enum ParserError {
     ParserError(String),
 }
 
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParserError::TokenizerError(s) => write!(f, "Tokenizer error: {}", s),
+            ParserError::ParserError(s) => write!(f, "Parser error: {}", s),
+        }
+    }
+}
+
 macro_rules! parser_err {
     ($MSG:expr) => {
         Err(ParserError::ParserError($MSG.to_string()))
@@ -31,13 +44,23 @@ macro_rules! parser_err {
 
 impl From<TokenizerError> for ParserError {
     fn from(e: TokenizerError) -> Self {
-        ParserError::TokenizerError(format!("{:?}", e))
+        ParserError::TokenizerError(e.to_string())
     }
 }
 
+/// Precedence of unary operators, which bind more tightly than any binary operator
+const UNARY_PRECEDENCE: u8 = 50;
+/// Precedence of NOT, which binds more tightly than AND but less tightly than comparisons
+const NOT_PRECEDENCE: u8 = 12;
+
+/// Precedence of `::` casts, which bind more tightly than unary operators
+const DOUBLE_COLON_PRECEDENCE: u8 = 60;
+
 /// SQL Parser
 pub struct Parser {
     tokens: Vec<Token>,
+    /// Location of each token, if known
+    locations: Vec<Location>,
     index: usize,
 }
 
@@ -46,16 +69,141 @@ impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
         Parser {
             tokens: tokens,
+            locations: vec![],
             index: 0,
         }
     }
 
-    /// Parse a SQL statement and produce an Abstract Syntax Tree (AST)
+    /// Parse the specified tokens, using their locations to report errors
+    pub fn new_with_location(tokens: Vec<(Token, Location)>) -> Self {
+        let (tokens, locations) = tokens.into_iter().unzip();
+        Parser {
+            tokens,
+            locations,
+            index: 0,
+        }
+    }
+
+    /// Parse a SQL statement and produce an Abstract Syntax Tree (AST). The statement may
+    /// optionally be terminated with a semicolon.
     pub fn parse_sql(sql: String) -> Result<ASTNode, ParserError> {
-        let mut tokenizer = Tokenizer::new(&sql);
-        let tokens = tokenizer.tokenize()?;
-        let mut parser = Parser::new(tokens);
-        parser.parse()
+        Parser::parse_sql_with_dialect(sql, Rc::new(AnsiDialect {}))
+    }
+
+    /// Parse a SQL statement written in the specified dialect
+    pub fn parse_sql_with_dialect(
+        sql: String,
+        dialect: Rc<Dialect>,
+    ) -> Result<ASTNode, ParserError> {
+        let mut statements = Parser::parse_statements_with_dialect(sql, dialect)?;
+        match statements.len() {
+            1 => Ok(statements.remove(0).0),
+            0 => parser_err!("No SQL statement found"),
+            n => parser_err!(format!("Expected one SQL statement but found {}", n)),
+        }
+    }
+
+    /// Parse a script containing SQL statements separated by semicolons
+    pub fn parse_statements(sql: String) -> Result<Vec<ASTNode>, ParserError> {
+        Ok(Parser::parse_statements_with_location(sql)?
+            .into_iter()
+            .map(|(ast, _)| ast)
+            .collect())
+    }
+
+    /// Parse a script containing SQL statements separated by semicolons and return each
+    /// statement along with the location where it starts
+    pub fn parse_statements_with_location(
+        sql: String,
+    ) -> Result<Vec<(ASTNode, Location)>, ParserError> {
+        Parser::parse_statements_with_dialect(sql, Rc::new(AnsiDialect {}))
+    }
+
+    /// Parse a script written in the specified dialect and return each statement along with
+    /// the location where it starts
+    pub fn parse_statements_with_dialect(
+        sql: String,
+        dialect: Rc<Dialect>,
+    ) -> Result<Vec<(ASTNode, Location)>, ParserError> {
+        let mut tokenizer = Tokenizer::new_with_dialect(&sql, dialect);
+        let tokens = tokenizer.tokenize_with_location().map_err(|e| {
+            ParserError::TokenizerError(annotate_sql(&sql, &e.location, &e.message))
+        })?;
+        let mut parser = Parser::new_with_location(tokens);
+        parser
+            .parse_statement_list()
+            .map_err(|e| parser.annotate_error(&sql, e))
+    }
+
+    /// Parse a single SQL expression written in the specified dialect, such as the body of a
+    /// SQL function
+    pub fn parse_expr_with_dialect(
+        sql: String,
+        dialect: Rc<Dialect>,
+    ) -> Result<ASTNode, ParserError> {
+        let mut tokenizer = Tokenizer::new_with_dialect(&sql, dialect);
+        let tokens = tokenizer.tokenize_with_location().map_err(|e| {
+            ParserError::TokenizerError(annotate_sql(&sql, &e.location, &e.message))
+        })?;
+        let mut parser = Parser::new_with_location(tokens);
+        let mut parse_expr = || {
+            let expr = parser.parse()?;
+            match parser.next_token() {
+                None => Ok(expr),
+                Some(t) => parser_err!(format!("Unexpected token after expression: {:?}", t)),
+            }
+        };
+        let result = parse_expr();
+        result.map_err(|e| parser.annotate_error(&sql, e))
+    }
+
+    /// Parse statements separated by semicolons until there are no more tokens
+    fn parse_statement_list(&mut self) -> Result<Vec<(ASTNode, Location)>, ParserError> {
+        let mut statements = vec![];
+        loop {
+            // skip empty statements
+            while self.peek_token() == Some(Token::SemiColon) {
+                self.next_token();
+            }
+
+            let location = match self.locations.get(self.index) {
+                Some(location) => *location,
+                None => break,
+            };
+
+            statements.push((self.parse()?, location));
+
+            match self.next_token() {
+                Some(Token::SemiColon) | None => {}
+                Some(t) => {
+                    return parser_err!(format!(
+                        "Expected ';' or end of input after statement but found {:?}",
+                        t
+                    ))
+                }
+            }
+        }
+        Ok(statements)
+    }
+
+    /// Add the location of the most recently consumed token and the offending line of SQL to
+    /// a parser error
+    fn annotate_error(&self, sql: &str, e: ParserError) -> ParserError {
+        if self.locations.is_empty() {
+            return e;
+        }
+        let index = if self.index > 0 {
+            self.index - 1
+        } else {
+            0
+        };
+        let location = &self.locations[index.min(self.locations.len() - 1)];
+        match e {
+            ParserError::ParserError(msg) => {
+                ParserError::ParserError(annotate_sql(sql, location, &msg))
+            }
+            other => other,
+        }
     }
 
     /// Parse a new expression
@@ -96,10 +244,25 @@ impl Parser {
                     Token::Keyword(k) => match k.to_uppercase().as_ref() {
                         "SELECT" => Ok(self.parse_select()?),
                         "CREATE" => Ok(self.parse_create()?),
+                        "EXPLAIN" => Ok(self.parse_explain()?),
+                        "ANALYZE" => Ok(self.parse_analyze()?),
+                        "DROP" => Ok(self.parse_drop()?),
+                        "INSERT" => Ok(self.parse_insert()?),
+                        "SHOW" => Ok(self.parse_show()?),
+                        "COPY" => Ok(self.parse_copy()?),
+                        "SET" => Ok(self.parse_set()?),
+                        "DESCRIBE" => Ok(ASTNode::SQLShowColumns {
+                            table_name: self.parse_table_name()?,
+                        }),
+                        "NOT" => Ok(ASTNode::SQLUnary {
+                            operator: SQLOperator::Not,
+                            rex: Box::new(self.parse_expr(NOT_PRECEDENCE)?),
+                        }),
                         _ => return parser_err!(format!("No prefix parser for keyword {}", k)),
                     },
                     Token::Mult => Ok(ASTNode::SQLWildcard),
                     Token::Identifier(id) => {
+                        let location = self.prev_location();
                         match self.peek_token() {
                             Some(Token::LParen) => {
                                 self.next_token(); // skip lparen
@@ -113,11 +276,14 @@ impl Parser {
                                 }
                             }
                             Some(Token::Period) => {
-                                let mut id_parts: Vec<String> = vec![id];
+                                let mut id_parts = vec![SQLIdent::with_location(id, location)];
                                 while self.peek_token() == Some(Token::Period) {
                                     self.consume_token(&Token::Period)?;
                                     match self.next_token() {
-                                        Some(Token::Identifier(id)) => id_parts.push(id),
+                                        Some(Token::Identifier(id))
+                                        | Some(Token::QuotedIdentifier(id)) => id_parts.push(
+                                            SQLIdent::with_location(id, self.prev_location()),
+                                        ),
                                         _ => {
                                             return parser_err!(format!(
                                                 "Error parsing compound identifier"
@@ -127,9 +293,14 @@ impl Parser {
                                 }
                                 Ok(ASTNode::SQLCompoundIdentifier(id_parts))
                             }
-                            _ => Ok(ASTNode::SQLIdentifier(id)),
+                            _ => Ok(ASTNode::SQLIdentifier(SQLIdent::with_location(
+                                id, location,
+                            ))),
                         }
                     }
+                    Token::QuotedIdentifier(id) => Ok(ASTNode::SQLQuotedIdentifier(
+                        SQLIdent::with_location(id, self.prev_location()),
+                    )),
                     Token::Number(ref n) if n.contains(".") => match n.parse::<f64>() {
                         Ok(n) => Ok(ASTNode::SQLLiteralDouble(n)),
                         Err(e) => parser_err!(format!("Could not parse '{}' as i64: {}", n, e)),
@@ -139,6 +310,41 @@ impl Parser {
                         Err(e) => parser_err!(format!("Could not parse '{}' as i64: {}", n, e)),
                     },
                     Token::String(ref s) => Ok(ASTNode::SQLLiteralString(s.to_string())),
+                    Token::LParen => {
+                        let expr = self.parse_expr(0)?;
+                        match self.next_token() {
+                            Some(Token::RParen) => Ok(ASTNode::SQLNested(Box::new(expr))),
+                            other => parser_err!(format!("Expected ')' but found {:?}", other)),
+                        }
+                    }
+                    Token::Minus => match self.peek_token() {
+                        // negative numeric literal
+                        Some(Token::Number(ref n)) => {
+                            self.next_token();
+                            let n = format!("-{}", n);
+                            if n.contains(".") {
+                                match n.parse::<f64>() {
+                                    Ok(n) => Ok(ASTNode::SQLLiteralDouble(n)),
+                                    Err(e) => parser_err!(format!(
+                                        "Could not parse '{}' as f64: {}",
+                                        n, e
+                                    )),
+                                }
+                            } else {
+                                match n.parse::<i64>() {
+                                    Ok(n) => Ok(ASTNode::SQLLiteralLong(n)),
+                                    Err(e) => parser_err!(format!(
+                                        "Could not parse '{}' as i64: {}",
+                                        n, e
+                                    )),
+                                }
+                            }
+                        }
+                        _ => Ok(ASTNode::SQLUnary {
+                            operator: SQLOperator::Minus,
+                            rex: Box::new(self.parse_expr(UNARY_PRECEDENCE)?),
+                        }),
+                    },
                     _ => parser_err!(format!(
                         "Prefix parser expected a keyword but found {:?}",
                         t
@@ -199,6 +405,10 @@ impl Parser {
                     op: self.to_sql_operator(&tok)?,
                     right: Box::new(self.parse_expr(precedence)?),
                 })),
+                Token::DoubleColon => Ok(Some(ASTNode::SQLCast {
+                    expr: Box::new(expr),
+                    data_type: self.parse_data_type()?,
+                })),
                 _ => parser_err!(format!("No infix parser for token {:?}", tok)),
             },
             None => Ok(None),
@@ -221,6 +431,8 @@ impl Parser {
             &Token::Mod => Ok(SQLOperator::Modulus),
             &Token::Keyword(ref k) if k == "AND" => Ok(SQLOperator::And),
             &Token::Keyword(ref k) if k == "OR" => Ok(SQLOperator::Or),
+            &Token::Keyword(ref k) if k == "LIKE" => Ok(SQLOperator::Like),
+            &Token::Keyword(ref k) if k == "ILIKE" => Ok(SQLOperator::ILike),
             _ => parser_err!(format!("Unsupported SQL operator {:?}", tok)),
         }
     }
@@ -245,8 +457,10 @@ impl Parser {
             &Token::Eq | &Token::Lt | &Token::LtEq | &Token::Neq | &Token::Gt | &Token::GtEq => {
                 Ok(20)
             }
+            &Token::Keyword(ref k) if k == "LIKE" || k == "ILIKE" => Ok(20),
             &Token::Plus | &Token::Minus => Ok(30),
             &Token::Mult | &Token::Div | &Token::Mod => Ok(40),
+            &Token::DoubleColon => Ok(DOUBLE_COLON_PRECEDENCE),
             _ => Ok(0),
         }
     }
@@ -270,6 +484,15 @@ impl Parser {
         }
     }
 
+    /// Get the location of the most recently consumed token, if it is known
+    fn prev_location(&self) -> Option<Location> {
+        if self.index > 0 {
+            self.locations.get(self.index - 1).cloned()
+        } else {
+            None
+        }
+    }
+
     /// Get the previous token and decrement the token index
     fn prev_token(&mut self) -> Option<Token> {
         if self.index > 0 {
@@ -334,93 +557,491 @@ impl Parser {
         }
     }
 
+    /// Parse an identifier, which may be quoted, and return its name
+    fn parse_identifier(&mut self) -> Option<String> {
+        match self.next_token() {
+            Some(Token::Identifier(id)) | Some(Token::QuotedIdentifier(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Parse a SQL EXPLAIN [ANALYZE] statement
+    fn parse_explain(&mut self) -> Result<ASTNode, ParserError> {
+        let analyze = self.parse_keyword("ANALYZE");
+        let statement = self.parse_expr(0)?;
+        match statement {
+            ASTNode::SQLSelect { .. } => Ok(ASTNode::SQLExplain {
+                analyze,
+                statement: Box::new(statement),
+            }),
+            _ => parser_err!("EXPLAIN is only supported for SELECT statements"),
+        }
+    }
+
+    /// Parse a SQL ANALYZE TABLE statement
+    fn parse_analyze(&mut self) -> Result<ASTNode, ParserError> {
+        if self.parse_keyword("TABLE") {
+            Ok(ASTNode::SQLAnalyzeTable {
+                table_name: self.parse_table_name()?,
+            })
+        } else {
+            parser_err!(format!(
+                "Expected TABLE after ANALYZE but found {:?}",
+                self.peek_token()
+            ))
+        }
+    }
+
+    /// Parse a table or view name
+    fn parse_table_name(&mut self) -> Result<String, ParserError> {
+        match self.parse_identifier() {
+            Some(name) => Ok(name),
+            None => parser_err!(format!(
+                "Expected table name but found {:?}",
+                self.prev_token()
+            )),
+        }
+    }
+
+    /// Parse a SQL DROP TABLE [IF EXISTS] or DROP VIEW [IF EXISTS] statement
+    fn parse_drop(&mut self) -> Result<ASTNode, ParserError> {
+        let view = self.parse_keyword("VIEW");
+        if view || self.parse_keyword("TABLE") {
+            let if_exists = self.parse_keywords(vec!["IF", "EXISTS"]);
+            Ok(ASTNode::SQLDropTable {
+                name: self.parse_table_name()?,
+                if_exists,
+                view,
+            })
+        } else {
+            parser_err!(format!(
+                "Expected TABLE or VIEW after DROP but found {:?}",
+                self.peek_token()
+            ))
+        }
+    }
+
+    /// Parse a SQL SHOW TABLES, SHOW COLUMNS FROM, SHOW ALL or SHOW setting statement
+    fn parse_show(&mut self) -> Result<ASTNode, ParserError> {
+        if self.parse_keyword("TABLES") {
+            Ok(ASTNode::SQLShowTables)
+        } else if self.parse_keywords(vec!["COLUMNS", "FROM"]) {
+            Ok(ASTNode::SQLShowColumns {
+                table_name: self.parse_table_name()?,
+            })
+        } else if self.parse_keyword("ALL") {
+            Ok(ASTNode::SQLShowVariable { name: None })
+        } else {
+            match self.next_token() {
+                Some(Token::Identifier(name)) => Ok(ASTNode::SQLShowVariable {
+                    name: Some(name.to_lowercase()),
+                }),
+                other => parser_err!(format!(
+                    "Expected TABLES, COLUMNS, ALL or a setting name after SHOW but found {:?}",
+                    other
+                )),
+            }
+        }
+    }
+
+    /// Parse a SQL SET setting = value statement. `TO` can be used instead of `=`.
+    fn parse_set(&mut self) -> Result<ASTNode, ParserError> {
+        let name = match self.next_token() {
+            Some(Token::Identifier(name)) => name.to_lowercase(),
+            other => return parser_err!(format!("Expected setting name, found {:?}", other)),
+        };
+        if !self.consume_token(&Token::Eq)? && !self.parse_keyword("TO") {
+            return parser_err!(format!(
+                "Expected '=' or TO after setting name but found {:?}",
+                self.peek_token()
+            ));
+        }
+        match self.next_token() {
+            Some(Token::Identifier(value))
+            | Some(Token::Keyword(value))
+            | Some(Token::String(value))
+            | Some(Token::Number(value)) => Ok(ASTNode::SQLSetVariable { name, value }),
+            other => parser_err!(format!(
+                "Expected value for setting {}, found {:?}",
+                name, other
+            )),
+        }
+    }
+
+    /// Parse a SQL CREATE [OR REPLACE] VIEW [IF NOT EXISTS] statement, after the VIEW keyword
+    fn parse_create_view(&mut self, or_replace: bool) -> Result<ASTNode, ParserError> {
+        let if_not_exists = self.parse_keywords(vec!["IF", "NOT", "EXISTS"]);
+        if or_replace && if_not_exists {
+            return parser_err!("OR REPLACE and IF NOT EXISTS cannot both be specified");
+        }
+        let name = self.parse_table_name()?;
+        if !self.parse_keyword("AS") {
+            return parser_err!(format!(
+                "Expected AS after view name but found {:?}",
+                self.peek_token()
+            ));
+        }
+        Ok(ASTNode::SQLCreateView {
+            name,
+            query: self.parse_query("CREATE VIEW")?,
+            or_replace,
+            if_not_exists,
+        })
+    }
+
+    /// Parse an optional `STORED AS` clause and return the file type and, for CSV files,
+    /// whether there is a header row
+    fn parse_stored_as(&mut self) -> Result<Option<(FileType, bool)>, ParserError> {
+        if self.parse_keywords(vec!["STORED", "AS", "CSV"]) {
+            let headers = if self.parse_keywords(vec!["WITH", "HEADER", "ROW"]) {
+                true
+            } else if self.parse_keywords(vec!["WITHOUT", "HEADER", "ROW"]) {
+                false
+            } else {
+                true
+            };
+            Ok(Some((FileType::CSV, headers)))
+        } else if self.parse_keywords(vec!["STORED", "AS", "NDJSON"]) {
+            Ok(Some((FileType::NdJson, true)))
+        } else if self.parse_keywords(vec!["STORED", "AS", "PARQUET"]) {
+            Ok(Some((FileType::Parquet, true)))
+        } else if self.parse_keywords(vec!["STORED", "AS", "TEXTFILE"]) {
+            // Hive text files are delimited files without a header row
+            Ok(Some((FileType::CSV, false)))
+        } else if self.parse_keyword("STORED") {
+            parser_err!(format!(
+                "Expected CSV, NDJSON, PARQUET or TEXTFILE after STORED AS, found {:?}",
+                self.peek_token()
+            ))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parse an optional Hive `ROW FORMAT DELIMITED [FIELDS TERMINATED BY 'c']` clause and
+    /// return the field delimiter, if one is given
+    fn parse_row_format(&mut self) -> Result<Option<String>, ParserError> {
+        if !self.parse_keywords(vec!["ROW", "FORMAT"]) {
+            return Ok(None);
+        }
+        if !self.parse_keyword("DELIMITED") {
+            return parser_err!(format!(
+                "Expected DELIMITED after ROW FORMAT, found {:?}",
+                self.peek_token()
+            ));
+        }
+        if self.parse_keywords(vec!["FIELDS", "TERMINATED", "BY"]) {
+            Ok(Some(self.parse_literal_string()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parse an optional `PARTITIONED BY (name type, ...)` clause
+    fn parse_partitioned_by(&mut self) -> Result<Vec<SQLColumnDef>, ParserError> {
+        if self.parse_keywords(vec!["PARTITIONED", "BY"]) {
+            if !self.consume_token(&Token::LParen)? {
+                return parser_err!("Expected '(' after PARTITIONED BY");
+            }
+            self.parse_column_defs()
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    /// Parse a SELECT statement that provides the rows for another statement
+    fn parse_query(&mut self, statement: &str) -> Result<Box<ASTNode>, ParserError> {
+        match self.parse_expr(0)? {
+            query @ ASTNode::SQLSelect { .. } => Ok(Box::new(query)),
+            _ => parser_err!(format!("Expected SELECT statement in {}", statement)),
+        }
+    }
+
+    /// Parse a SQL CREATE TABLE [IF NOT EXISTS] ... AS SELECT statement, after the TABLE
+    /// keyword. Tables without a STORED AS clause are held in memory.
+    fn parse_create_table_as(&mut self) -> Result<ASTNode, ParserError> {
+        let if_not_exists = self.parse_keywords(vec!["IF", "NOT", "EXISTS"]);
+        let name = self.parse_table_name()?;
+        let (file_type, header_row, location) = match self.parse_stored_as()? {
+            Some((file_type, header_row)) => {
+                if !self.parse_keyword("LOCATION") {
+                    return parser_err!("Missing 'LOCATION' clause");
+                }
+                (Some(file_type), header_row, Some(self.parse_literal_string()?))
+            }
+            None => (None, false, None),
+        };
+        if !self.parse_keyword("AS") {
+            return parser_err!(format!(
+                "Expected AS SELECT after table definition but found {:?}",
+                self.peek_token()
+            ));
+        }
+        Ok(ASTNode::SQLCreateTableAs {
+            name,
+            file_type,
+            header_row,
+            location,
+            query: self.parse_query("CREATE TABLE")?,
+            if_not_exists,
+        })
+    }
+
+    /// Parse a SQL INSERT INTO ... SELECT statement
+    fn parse_insert(&mut self) -> Result<ASTNode, ParserError> {
+        if !self.parse_keyword("INTO") {
+            return parser_err!(format!(
+                "Expected INTO after INSERT but found {:?}",
+                self.peek_token()
+            ));
+        }
+        let table_name = self.parse_table_name()?;
+        Ok(ASTNode::SQLInsert {
+            table_name,
+            query: self.parse_query("INSERT")?,
+        })
+    }
+
+    /// Parse a SQL COPY (SELECT ...) TO, COPY table TO or COPY table FROM statement
+    fn parse_copy(&mut self) -> Result<ASTNode, ParserError> {
+        let source = if self.consume_token(&Token::LParen)? {
+            let query = self.parse_query("COPY")?;
+            if !self.consume_token(&Token::RParen)? {
+                return parser_err!(format!(
+                    "Expected ')' after COPY query but found {:?}",
+                    self.peek_token()
+                ));
+            }
+            query
+        } else {
+            let table_name = self.parse_table_name()?;
+            if self.parse_keyword("FROM") {
+                return Ok(ASTNode::SQLCopyFrom {
+                    table_name,
+                    location: self.parse_literal_string()?,
+                    options: self.parse_copy_options()?,
+                });
+            }
+            Box::new(ASTNode::SQLIdentifier(SQLIdent::with_location(
+                table_name,
+                self.prev_location(),
+            )))
+        };
+        if !self.parse_keyword("TO") {
+            return parser_err!(format!(
+                "Expected TO in COPY statement but found {:?}",
+                self.peek_token()
+            ));
+        }
+        Ok(ASTNode::SQLCopyTo {
+            source,
+            location: self.parse_literal_string()?,
+            options: self.parse_copy_options()?,
+        })
+    }
+
+    /// Parse the optional `WITH (name value, ...)` clause of a COPY statement. Option names are
+    /// converted to upper case and values can be words, numbers or string literals.
+    fn parse_copy_options(&mut self) -> Result<Vec<(String, String)>, ParserError> {
+        let mut options = vec![];
+        if !self.parse_keyword("WITH") {
+            return Ok(options);
+        }
+        if !self.consume_token(&Token::LParen)? {
+            return parser_err!(format!(
+                "Expected '(' after WITH but found {:?}",
+                self.peek_token()
+            ));
+        }
+        loop {
+            let name = match self.next_token() {
+                Some(Token::Identifier(s)) | Some(Token::Keyword(s)) => s.to_uppercase(),
+                other => return parser_err!(format!("Expected option name, found {:?}", other)),
+            };
+            let value = match self.next_token() {
+                Some(Token::Identifier(s))
+                | Some(Token::Keyword(s))
+                | Some(Token::String(s))
+                | Some(Token::Number(s)) => s,
+                other => {
+                    return parser_err!(format!(
+                        "Expected value for option {}, found {:?}",
+                        name, other
+                    ))
+                }
+            };
+            options.push((name, value));
+            match self.next_token() {
+                Some(Token::Comma) => {}
+                Some(Token::RParen) => break,
+                other => return parser_err!(format!("Expected ',' or ')', found {:?}", other)),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Parse a list of column definitions that follows an opening parenthesis
+    fn parse_column_defs(&mut self) -> Result<Vec<SQLColumnDef>, ParserError> {
+        let mut columns = vec![];
+        loop {
+            if let Some(column_name) = self.parse_identifier() {
+                if let Ok(data_type) = self.parse_data_type() {
+                    let allow_null = if self.parse_keywords(vec!["NOT", "NULL"]) {
+                        false
+                    } else if self.parse_keyword("NULL") {
+                        true
+                    } else {
+                        true
+                    };
+
+                    columns.push(SQLColumnDef {
+                        name: column_name,
+                        data_type: data_type,
+                        allow_null,
+                    });
+
+                    match self.next_token() {
+                        Some(Token::Comma) => {}
+                        Some(Token::RParen) => break,
+                        _ => {
+                            return parser_err!("Expected ',' or ')' after column definition");
+                        }
+                    }
+                } else {
+                    return parser_err!("Error parsing data type in column definition");
+                }
+            } else {
+                return parser_err!("Error parsing column name");
+            }
+        }
+        Ok(columns)
+    }
+
+    /// Parse the options of an external table in the form `('name' 'value', ...)`
+    fn parse_table_options(&mut self) -> Result<Vec<(String, String)>, ParserError> {
+        if !self.consume_token(&Token::LParen)? {
+            return parser_err!("Expected '(' after OPTIONS");
+        }
+        let mut options = vec![];
+        loop {
+            let name = self.parse_literal_string()?;
+            let value = self.parse_literal_string()?;
+            options.push((name, value));
+            match self.next_token() {
+                Some(Token::Comma) => {}
+                Some(Token::RParen) => break,
+                other => return parser_err!(format!("Expected ',' or ')', found {:?}", other)),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Parse a SQL CREATE [TEMPORARY] FUNCTION statement, after the FUNCTION keyword
+    fn parse_create_function(&mut self, temporary: bool) -> Result<ASTNode, ParserError> {
+        let name = match self.parse_identifier() {
+            Some(name) => name,
+            None => return parser_err!("Expected function name after CREATE FUNCTION"),
+        };
+        if !self.consume_token(&Token::LParen)? {
+            return parser_err!("Expected '(' after function name");
+        }
+        let mut args = vec![];
+        if !self.consume_token(&Token::RParen)? {
+            loop {
+                let arg_name = match self.parse_identifier() {
+                    Some(arg_name) => arg_name,
+                    None => return parser_err!("Error parsing argument name"),
+                };
+                args.push((arg_name, self.parse_data_type()?));
+                match self.next_token() {
+                    Some(Token::Comma) => {}
+                    Some(Token::RParen) => break,
+                    other => return parser_err!(format!("Expected ',' or ')', found {:?}", other)),
+                }
+            }
+        }
+        if !self.parse_keyword("RETURNS") {
+            return parser_err!("Expected RETURNS after function arguments");
+        }
+        let return_type = self.parse_data_type()?;
+        if !self.parse_keyword("AS") {
+            return parser_err!("Expected AS after function return type");
+        }
+        let body = self.parse_literal_string()?;
+        Ok(ASTNode::SQLCreateFunction {
+            name,
+            args,
+            return_type,
+            body,
+            temporary,
+        })
+    }
+
     /// Parse a SQL CREATE statement
     fn parse_create(&mut self) -> Result<ASTNode, ParserError> {
-        if self.parse_keywords(vec!["EXTERNAL", "TABLE"]) {
+        if self.parse_keywords(vec!["OR", "REPLACE", "VIEW"]) {
+            self.parse_create_view(true)
+        } else if self.parse_keyword("VIEW") {
+            self.parse_create_view(false)
+        } else if self.parse_keyword("TABLE") {
+            self.parse_create_table_as()
+        } else if self.parse_keyword("FUNCTION") {
+            self.parse_create_function(false)
+        } else if self.parse_keywords(vec!["TEMPORARY", "FUNCTION"]) {
+            self.parse_create_function(true)
+        } else if self.parse_keywords(vec!["EXTERNAL", "TABLE"]) {
+            let if_not_exists = self.parse_keywords(vec!["IF", "NOT", "EXISTS"]);
             match self.next_token() {
-                Some(Token::Identifier(id)) => {
+                Some(Token::Identifier(id)) | Some(Token::QuotedIdentifier(id)) => {
                     // parse optional column list (schema)
-                    let mut columns = vec![];
-                    if self.consume_token(&Token::LParen)? {
-                        loop {
-                            if let Some(Token::Identifier(column_name)) = self.next_token() {
-                                if let Ok(data_type) = self.parse_data_type() {
-                                    let allow_null = if self.parse_keywords(vec!["NOT", "NULL"]) {
-                                        false
-                                    } else if self.parse_keyword("NULL") {
-                                        true
-                                    } else {
-                                        true
-                                    };
-
-                                    match self.peek_token() {
-                                        Some(Token::Comma) => {
-                                            self.next_token();
-                                            columns.push(SQLColumnDef {
-                                                name: column_name,
-                                                data_type: data_type,
-                                                allow_null,
-                                            });
-                                        }
-                                        Some(Token::RParen) => {
-                                            self.next_token();
-                                            columns.push(SQLColumnDef {
-                                                name: column_name,
-                                                data_type: data_type,
-                                                allow_null,
-                                            });
-                                            break;
-                                        }
-                                        _ => {
-                                            return parser_err!(
-                                                "Expected ',' or ')' after column definition"
-                                            );
-                                        }
-                                    }
-                                } else {
-                                    return parser_err!(
-                                        "Error parsing data type in column definition"
-                                    );
-                                }
-                            } else {
-                                return parser_err!("Error parsing column name");
-                            }
-                        }
-                    }
+                    let columns = if self.consume_token(&Token::LParen)? {
+                        self.parse_column_defs()?
+                    } else {
+                        vec![]
+                    };
 
-                    //println!("Parsed {} column defs", columns.len());
+                    // Hive puts the partition columns and row format before STORED AS
+                    let mut partition_columns = self.parse_partitioned_by()?;
+                    let delimiter = self.parse_row_format()?;
 
-                    let mut headers = true;
-                    let file_type: FileType = if self.parse_keywords(vec!["STORED", "AS", "CSV"]) {
-                        if self.parse_keywords(vec!["WITH", "HEADER", "ROW"]) {
-                            headers = true;
-                        } else if self.parse_keywords(vec!["WITHOUT", "HEADER", "ROW"]) {
-                            headers = false;
+                    let (file_type, headers) = match self.parse_stored_as()? {
+                        Some(stored_as) => stored_as,
+                        None => {
+                            return parser_err!(format!(
+                                "Expected 'STORED AS' clause, found {:?}",
+                                self.peek_token()
+                            ))
                         }
-                        FileType::CSV
-                    } else if self.parse_keywords(vec!["STORED", "AS", "NDJSON"]) {
-                        FileType::NdJson
-                    } else if self.parse_keywords(vec!["STORED", "AS", "PARQUET"]) {
-                        FileType::Parquet
-                    } else {
-                        return parser_err!(format!(
-                            "Expected 'STORED AS' clause, found {:?}",
-                            self.peek_token()
-                        ));
                     };
 
+                    if partition_columns.is_empty() {
+                        partition_columns = self.parse_partitioned_by()?;
+                    }
+
                     let location: String = if self.parse_keywords(vec!["LOCATION"]) {
                         self.parse_literal_string()?
                     } else {
                         return parser_err!("Missing 'LOCATION' clause");
                     };
 
+                    let mut options = if self.parse_keyword("OPTIONS") {
+                        self.parse_table_options()?
+                    } else {
+                        vec![]
+                    };
+                    if let Some(delimiter) = delimiter {
+                        options.push(("delimiter".to_string(), delimiter));
+                    }
+
                     Ok(ASTNode::SQLCreateTable {
                         name: id,
                         columns,
                         file_type,
                         header_row: headers,
                         location,
+                        if_not_exists,
+                        partition_columns,
+                        options,
                     })
                 }
                 _ => parser_err!(format!(
@@ -463,10 +1084,10 @@ impl Parser {
                 "UINT16" => Ok(SQLType::UInt16),
                 "UINT32" => Ok(SQLType::UInt32),
                 "UINT64" => Ok(SQLType::UInt64),
-                "INT8" => Ok(SQLType::Int8),
-                "INT16" => Ok(SQLType::Int16),
+                "INT8" | "TINYINT" => Ok(SQLType::Int8),
+                "INT16" | "SMALLINT" => Ok(SQLType::Int16),
                 "INT32" | "INT" | "INTEGER" => Ok(SQLType::Int32),
-                "INT64" | "LONG" => Ok(SQLType::Int64),
+                "INT64" | "LONG" | "BIGINT" => Ok(SQLType::Int64),
                 "FLOAT32" | "FLOAT" => Ok(SQLType::Float32),
                 "FLOAT64" | "DOUBLE" => Ok(SQLType::Double64),
                 "UTF8" | "VARCHAR" | "STRING" => {
@@ -514,7 +1135,10 @@ impl Parser {
             None
         };
 
-        let order_by = if self.parse_keywords(vec!["ORDER", "BY"]) {
+        // Hive's SORT BY sorts the rows of each reducer, which are all of the rows here
+        let order_by = if self.parse_keywords(vec!["ORDER", "BY"])
+            || self.parse_keywords(vec!["SORT", "BY"])
+        {
             Some(self.parse_order_by_expr_list()?)
         } else {
             None
@@ -526,13 +1150,9 @@ impl Parser {
             None
         };
 
-        if let Some(next_token) = self.peek_token() {
-            parser_err!(format!(
-                "Unexpected token at end of SELECT: {:?}",
-                next_token
-            ))
-        } else {
-            Ok(ASTNode::SQLSelect {
+        // a closing parenthesis ends a query that is nested in another statement
+        match self.peek_token() {
+            None | Some(Token::SemiColon) | Some(Token::RParen) => Ok(ASTNode::SQLSelect {
                 projection,
                 selection,
                 relation,
@@ -540,7 +1160,14 @@ impl Parser {
                 order_by,
                 group_by,
                 having,
-            })
+            }),
+            Some(next_token) => {
+                self.next_token();
+                parser_err!(format!(
+                    "Unexpected token at end of SELECT: {:?}",
+                    next_token
+                ))
+            }
         }
     }
 
@@ -570,25 +1197,12 @@ impl Parser {
             let expr = self.parse_expr(0)?;
 
             // look for optional ASC / DESC specifier
-            let asc = match self.peek_token() {
-                Some(Token::Keyword(k)) => {
-                    self.next_token(); // consume it
-                    match k.to_uppercase().as_ref() {
-                        "ASC" => true,
-                        "DESC" => false,
-                        _ => {
-                            return parser_err!(format!(
-                                "Invalid modifier for ORDER BY expression: {:?}",
-                                k
-                            ))
-                        }
-                    }
-                }
-                Some(Token::Comma) => true,
-                Some(other) => {
-                    return parser_err!(format!("Unexpected token after ORDER BY expr: {:?}", other))
-                }
-                None => true,
+            let asc = if self.parse_keyword("ASC") {
+                true
+            } else if self.parse_keyword("DESC") {
+                false
+            } else {
+                true
             };
 
             expr_list.push(ASTNode::SQLOrderBy {
@@ -698,12 +1312,12 @@ mod tests {
         let ast = parse_sql(&sql);
         assert_eq!(
             SQLBinaryExpr {
-                left: Box::new(SQLIdentifier("a".to_string())),
+                left: Box::new(SQLIdentifier(SQLIdent::new("a"))),
                 op: Plus,
                 right: Box::new(SQLBinaryExpr {
-                    left: Box::new(SQLIdentifier("b".to_string())),
+                    left: Box::new(SQLIdentifier(SQLIdent::new("b"))),
                     op: Multiply,
-                    right: Box::new(SQLIdentifier("c".to_string()))
+                    right: Box::new(SQLIdentifier(SQLIdent::new("c")))
                 })
             },
             ast
@@ -719,12 +1333,12 @@ mod tests {
         assert_eq!(
             SQLBinaryExpr {
                 left: Box::new(SQLBinaryExpr {
-                    left: Box::new(SQLIdentifier("a".to_string())),
+                    left: Box::new(SQLIdentifier(SQLIdent::new("a"))),
                     op: Multiply,
-                    right: Box::new(SQLIdentifier("b".to_string()))
+                    right: Box::new(SQLIdentifier(SQLIdent::new("b")))
                 }),
                 op: Plus,
-                right: Box::new(SQLIdentifier("c".to_string()))
+                right: Box::new(SQLIdentifier(SQLIdent::new("c")))
             },
             ast
         );
@@ -735,7 +1349,7 @@ mod tests {
         use self::ASTNode::*;
         let sql = String::from("a IS NULL");
         let ast = parse_sql(&sql);
-        assert_eq!(SQLIsNull(Box::new(SQLIdentifier("a".to_string()))), ast);
+        assert_eq!(SQLIsNull(Box::new(SQLIdentifier(SQLIdent::new("a")))), ast);
     }
 
     #[test]
@@ -743,7 +1357,10 @@ mod tests {
         use self::ASTNode::*;
         let sql = String::from("a IS NOT NULL");
         let ast = parse_sql(&sql);
-        assert_eq!(SQLIsNotNull(Box::new(SQLIdentifier("a".to_string()))), ast);
+        assert_eq!(
+            SQLIsNotNull(Box::new(SQLIdentifier(SQLIdent::new("a")))),
+            ast
+        );
     }
 
     #[test]
@@ -757,11 +1374,11 @@ mod tests {
                 assert_eq!(
                     Some(vec![
                         ASTNode::SQLOrderBy {
-                            expr: Box::new(ASTNode::SQLIdentifier("lname".to_string())),
+                            expr: Box::new(ASTNode::SQLIdentifier(SQLIdent::new("lname"))),
                             asc: true,
                         },
                         ASTNode::SQLOrderBy {
-                            expr: Box::new(ASTNode::SQLIdentifier("fname".to_string())),
+                            expr: Box::new(ASTNode::SQLIdentifier(SQLIdent::new("fname"))),
                             asc: false,
                         },
                     ]),
@@ -780,8 +1397,8 @@ mod tests {
             ASTNode::SQLSelect { group_by, .. } => {
                 assert_eq!(
                     Some(vec![
-                        ASTNode::SQLIdentifier("lname".to_string()),
-                        ASTNode::SQLIdentifier("fname".to_string()),
+                        ASTNode::SQLIdentifier(SQLIdent::new("lname")),
+                        ASTNode::SQLIdentifier(SQLIdent::new("fname")),
                     ]),
                     group_by
                 );
@@ -814,7 +1431,7 @@ mod tests {
                 assert_eq!(1, projection.len());
                 assert_eq!(
                     ASTNode::SQLCast {
-                        expr: Box::new(ASTNode::SQLIdentifier("id".to_string())),
+                        expr: Box::new(ASTNode::SQLIdentifier(SQLIdent::new("id"))),
                         data_type: SQLType::Double64
                     },
                     projection[0]
@@ -842,8 +1459,11 @@ mod tests {
                 file_type,
                 header_row,
                 location,
+                if_not_exists,
+                ..
             } => {
                 assert_eq!("uk_cities", name);
+                assert_eq!(false, if_not_exists);
                 assert_eq!(3, columns.len());
                 assert_eq!(FileType::CSV, file_type);
                 assert_eq!(true, header_row);
@@ -868,6 +1488,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_create_external_table_quoted_identifiers() {
+        let sql = String::from(
+            "CREATE EXTERNAL TABLE \"UK Cities\" (\
+             \"city name\" VARCHAR(100) NOT NULL,\
+             \"location\" DOUBLE NULL) \
+             STORED AS CSV WITH HEADER ROW \
+             LOCATION '/mnt/ssd/uk_cities.csv'",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLCreateTable { name, columns, .. } => {
+                assert_eq!("UK Cities", name);
+                assert_eq!("city name", columns[0].name);
+                assert_eq!("location", columns[1].name);
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn parse_select_quoted_identifier() {
+        let sql = String::from("SELECT \"first name\" FROM customer WHERE \"location\" = 'it''s'");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect {
+                projection,
+                selection,
+                ..
+            } => {
+                assert_eq!(
+                    ASTNode::SQLQuotedIdentifier(SQLIdent::new("first name")),
+                    projection[0]
+                );
+                assert_eq!(
+                    Some(Box::new(ASTNode::SQLBinaryExpr {
+                        left: Box::new(ASTNode::SQLQuotedIdentifier(SQLIdent::new("location"))),
+                        op: SQLOperator::Eq,
+                        right: Box::new(ASTNode::SQLLiteralString("it's".to_string())),
+                    })),
+                    selection
+                );
+            }
+            _ => assert!(false),
+        }
+    }
+
     #[test]
     fn parse_create_external_table_csv_without_header_row() {
         let sql = String::from(
@@ -886,8 +1551,11 @@ mod tests {
                 file_type,
                 header_row,
                 location,
+                if_not_exists,
+                ..
             } => {
                 assert_eq!("uk_cities", name);
+                assert_eq!(false, if_not_exists);
                 assert_eq!(3, columns.len());
                 assert_eq!(FileType::CSV, file_type);
                 assert_eq!(false, header_row);
@@ -922,6 +1590,79 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_create_function() {
+        let sql = String::from(
+            "CREATE TEMPORARY FUNCTION fiscal_quarter(m INT, y INT) RETURNS INT \
+             AS '(m + 2) / 3'",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLCreateFunction {
+                name,
+                args,
+                return_type,
+                body,
+                temporary,
+            } => {
+                assert_eq!("fiscal_quarter", name);
+                assert_eq!(
+                    vec![
+                        ("m".to_string(), SQLType::Int32),
+                        ("y".to_string(), SQLType::Int32),
+                    ],
+                    args
+                );
+                assert_eq!(SQLType::Int32, return_type);
+                assert_eq!("(m + 2) / 3", body);
+                assert!(temporary);
+            }
+            _ => assert!(false),
+        }
+
+        assert!(Parser::parse_sql("CREATE FUNCTION f() RETURNS INT AS '1'".to_string()).is_ok());
+        assert!(Parser::parse_sql("CREATE FUNCTION f() AS '1'".to_string()).is_err());
+        assert!(
+            Parser::parse_expr_with_dialect("1 +".to_string(), Rc::new(AnsiDialect {})).is_err()
+        );
+    }
+
+    #[test]
+    fn parse_create_external_table_with_partitions_and_options() {
+        let sql = String::from(
+            "CREATE EXTERNAL TABLE events \
+             STORED AS CSV WITH HEADER ROW \
+             PARTITIONED BY (year INT, month INT) \
+             LOCATION '/data/events' \
+             OPTIONS ('delimiter' '|', 'compression' 'gzip')",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLCreateTable {
+                ref columns,
+                ref partition_columns,
+                ref options,
+                ..
+            } => {
+                assert_eq!(0, columns.len());
+                assert_eq!(2, partition_columns.len());
+                assert_eq!("year", partition_columns[0].name);
+                assert_eq!("month", partition_columns[1].name);
+                assert_eq!(
+                    vec![
+                        ("delimiter".to_string(), "|".to_string()),
+                        ("compression".to_string(), "gzip".to_string()),
+                    ],
+                    *options
+                );
+            }
+            _ => assert!(false),
+        }
+
+        assert!(Parser::parse_sql(
+            "CREATE EXTERNAL TABLE t STORED AS CSV LOCATION 'x' OPTIONS ('delimiter')".to_string()
+        )
+        .is_err());
+    }
+
     #[test]
     fn parse_scalar_function_in_projection() {
         let sql = String::from("SELECT sqrt(id) FROM foo");
@@ -930,7 +1671,7 @@ mod tests {
             assert_eq!(
                 vec![ASTNode::SQLFunction {
                     id: String::from("sqrt"),
-                    args: vec![ASTNode::SQLIdentifier(String::from("id"))],
+                    args: vec![ASTNode::SQLIdentifier(SQLIdent::new("id"))],
                 }],
                 projection
             );
@@ -953,13 +1694,243 @@ mod tests {
             ASTNode::SQLSelect { ref projection, .. } => {
                 assert_eq!(
                     projection[0],
-                    ASTNode::SQLIdentifier("@@version".to_string())
+                    ASTNode::SQLIdentifier(SQLIdent::new("@@version"))
                 );
             }
             _ => panic!(),
         }
     }
 
+    #[test]
+    fn parse_explain() {
+        match parse_sql("EXPLAIN SELECT a FROM foo") {
+            ASTNode::SQLExplain { analyze, statement } => {
+                assert!(!analyze);
+                match *statement {
+                    ASTNode::SQLSelect { .. } => {}
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+
+        match parse_sql("EXPLAIN ANALYZE SELECT a FROM foo") {
+            ASTNode::SQLExplain { analyze, .. } => assert!(analyze),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_statements() {
+        let sql = "-- create a table\n\
+                   CREATE EXTERNAL TABLE t (a INT) STORED AS CSV LOCATION 'a;b.csv';\n\
+                   SELECT a FROM t WHERE a != ';' ORDER BY a DESC LIMIT 5; ;\n\
+                   /* ; */ SELECT 1";
+        let statements = Parser::parse_statements_with_location(sql.to_string()).unwrap();
+        assert_eq!(3, statements.len());
+        match statements[0] {
+            (ASTNode::SQLCreateTable { ref location, .. }, l) => {
+                assert_eq!("a;b.csv", location);
+                assert_eq!(Location { line: 2, column: 1 }, l);
+            }
+            _ => panic!(),
+        }
+        match statements[1] {
+            (ASTNode::SQLSelect { ref order_by, .. }, l) => {
+                assert_eq!(1, order_by.as_ref().unwrap().len());
+                assert_eq!(Location { line: 3, column: 1 }, l);
+            }
+            _ => panic!(),
+        }
+        assert_eq!(Location { line: 4, column: 9 }, statements[2].1);
+    }
+
+    #[test]
+    fn parse_statements_missing_semicolon() {
+        assert!(Parser::parse_statements("SELECT 1 SELECT 2".to_string()).is_err());
+        assert!(Parser::parse_sql("SELECT 1; SELECT 2".to_string()).is_err());
+        assert!(Parser::parse_sql("SELECT 1;".to_string()).is_ok());
+    }
+
+    #[test]
+    fn parse_like() {
+        let sql = String::from("SELECT name FROM customer WHERE name LIKE 'A%' AND id > 1");
+        let ast = Parser::parse_sql(sql).unwrap();
+        match ast {
+            ASTNode::SQLSelect { selection, .. } => match *selection.unwrap() {
+                ASTNode::SQLBinaryExpr { left, op, .. } => {
+                    assert_eq!(SQLOperator::And, op);
+                    assert_eq!(
+                        ASTNode::SQLBinaryExpr {
+                            left: Box::new(ASTNode::SQLIdentifier(SQLIdent::new("name"))),
+                            op: SQLOperator::Like,
+                            right: Box::new(ASTNode::SQLLiteralString("A%".to_string())),
+                        },
+                        *left
+                    );
+                }
+                _ => assert!(false),
+            },
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn parse_postgresql_dialect() {
+        let sql = String::from("SELECT -a::DOUBLE FROM t WHERE b ILIKE 'x%'");
+        let ast = Parser::parse_sql_with_dialect(sql.clone(), Rc::new(PostgreSqlDialect {}));
+        assert_eq!(
+            "SELECT -CAST(a AS FLOAT64) FROM t WHERE b ILIKE 'x%'",
+            ast.unwrap().to_string()
+        );
+        assert!(Parser::parse_sql(sql).is_err());
+    }
+
+    #[test]
+    fn parse_hive_dialect() {
+        let sql = String::from("SELECT `a b` FROM t WHERE c = \"it's\"");
+        let ast = Parser::parse_sql_with_dialect(sql, Rc::new(HiveDialect {}));
+        assert_eq!(
+            "SELECT \"a b\" FROM t WHERE c = 'it''s'",
+            ast.unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn parse_hive_dialect_syntax() {
+        let sql = String::from(
+            "CREATE EXTERNAL TABLE t (id BIGINT, n SMALLINT, c TINYINT) \
+             PARTITIONED BY (year INT) \
+             ROW FORMAT DELIMITED FIELDS TERMINATED BY '|' \
+             STORED AS TEXTFILE LOCATION '/data/t'",
+        );
+        let ast = Parser::parse_sql_with_dialect(sql.clone(), Rc::new(HiveDialect {}));
+        assert_eq!(
+            "CREATE EXTERNAL TABLE t (id INT64 NULL, n INT16 NULL, c INT8 NULL) \
+             STORED AS CSV WITHOUT HEADER ROW PARTITIONED BY (year INT32 NULL) \
+             LOCATION '/data/t' OPTIONS ('delimiter' '|')",
+            ast.unwrap().to_string()
+        );
+        // the Hive keywords are identifiers in other dialects
+        assert!(Parser::parse_sql(sql).is_err());
+
+        let sql = String::from("SELECT a FROM t SORT BY a DESC");
+        let ast = Parser::parse_sql_with_dialect(sql.clone(), Rc::new(HiveDialect {}));
+        assert_eq!("SELECT a FROM t ORDER BY a DESC", ast.unwrap().to_string());
+        assert!(Parser::parse_sql(sql).is_err());
+    }
+
+    #[test]
+    fn parse_catalog_statements() {
+        let statements = vec![
+            "DROP TABLE uk_cities",
+            "DROP TABLE IF EXISTS uk_cities",
+            "DROP VIEW IF EXISTS v",
+            "SHOW TABLES",
+            "SHOW COLUMNS FROM uk_cities",
+            "CREATE VIEW v AS SELECT a FROM t WHERE b > 1",
+            "CREATE OR REPLACE VIEW v AS SELECT a FROM t",
+            "CREATE VIEW IF NOT EXISTS v AS SELECT a FROM t",
+            "CREATE EXTERNAL TABLE IF NOT EXISTS t STORED AS PARQUET LOCATION 'foo.parquet'",
+            "ANALYZE TABLE t",
+        ];
+        for sql in statements {
+            assert_eq!(sql, parse_sql(sql).to_string());
+        }
+
+        assert_eq!(
+            ASTNode::SQLShowColumns {
+                table_name: "uk_cities".to_string()
+            },
+            parse_sql("DESCRIBE uk_cities")
+        );
+        assert_eq!(
+            ASTNode::SQLDropTable {
+                name: "v".to_string(),
+                if_exists: false,
+                view: true,
+            },
+            parse_sql("DROP VIEW v")
+        );
+    }
+
+    #[test]
+    fn parse_copy() {
+        let statements = vec![
+            "COPY (SELECT a FROM t WHERE b > 1) TO 'out.csv' WITH (FORMAT CSV, HEADER true, \
+             DELIMITER '|')",
+            "COPY t TO 'out.json'",
+            "COPY t FROM 'data/t.csv' WITH (HEADER false)",
+        ];
+        for sql in statements {
+            assert_eq!(sql, parse_sql(sql).to_string());
+        }
+
+        assert_eq!(
+            ASTNode::SQLCopyFrom {
+                table_name: "t".to_string(),
+                location: "in.csv".to_string(),
+                options: vec![
+                    ("FORMAT".to_string(), "CSV".to_string()),
+                    ("DELIMITER".to_string(), ";".to_string()),
+                ],
+            },
+            parse_sql("COPY t FROM 'in.csv' WITH (format CSV, delimiter ';')")
+        );
+
+        assert!(Parser::parse_sql("COPY (SELECT a FROM t) FROM 'in.csv'".to_string()).is_err());
+        assert!(Parser::parse_sql("SELECT a FROM t)".to_string()).is_err());
+        assert!(Parser::parse_sql("COPY t TO 'out.csv' WITH (FORMAT)".to_string()).is_err());
+        assert!(Parser::parse_sql("COPY t TO 'out.csv' WITH FORMAT csv".to_string()).is_err());
+    }
+
+    #[test]
+    fn parse_settings() {
+        let statements = vec![
+            "SET batch_size = 100",
+            "SET csv_delimiter = '|'",
+            "SHOW batch_size",
+            "SHOW ALL",
+        ];
+        for sql in statements {
+            assert_eq!(sql, parse_sql(sql).to_string());
+        }
+
+        assert_eq!(
+            ASTNode::SQLSetVariable {
+                name: "case_sensitive".to_string(),
+                value: "true".to_string(),
+            },
+            parse_sql("SET Case_Sensitive TO true")
+        );
+        assert!(Parser::parse_sql("SET batch_size".to_string()).is_err());
+        assert!(Parser::parse_sql("SET batch_size = ".to_string()).is_err());
+    }
+
+    #[test]
+    fn parse_invalid_create_view() {
+        assert!(Parser::parse_sql(
+            "CREATE OR REPLACE VIEW IF NOT EXISTS v AS SELECT 1".to_string()
+        )
+        .is_err());
+        assert!(Parser::parse_sql("CREATE VIEW v AS 1".to_string()).is_err());
+        assert!(Parser::parse_sql("CREATE VIEW v SELECT 1".to_string()).is_err());
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let sql = "SELECT a, b\nFROM foo\nLIMIT 5 6";
+        match Parser::parse_sql(sql.to_string()) {
+            Err(ParserError::ParserError(msg)) => assert_eq!(
+                "Unexpected token at end of SELECT: Number(\"6\") at line 3, column 9\
+                 \nLIMIT 5 6\
+                 \n        ^",
+                msg
+            ),
+            other => panic!("{:?}", other),
+        }
+    }
+
     fn parse_sql(sql: &str) -> ASTNode {
         let mut tokenizer = Tokenizer::new(&sql);
         let tokens = tokenizer.tokenize().unwrap();
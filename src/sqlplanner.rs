@@ -13,31 +13,109 @@
 // limitations under the License.
 
 //! SQL Query Planner (produces logical plan from SQL AST)
+//!
+//! Table and column names are resolved using the following case-folding rule. Quoted
+//! identifiers (e.g. `"First Name"`) are case-sensitive and must match a name exactly.
+//! Unquoted identifiers match a name exactly if there is one, otherwise they match the name
+//! that is equal ignoring case, as long as there is only one such name. When the planner is
+//! case-sensitive, unquoted identifiers must also match a name exactly.
 
 use std::collections::HashSet;
 use std::rc::Rc;
 use std::string::String;
 
+use super::analyzer::{relation_name, resolve_column};
+use super::coercion::{binary_operand_type, cast_expr};
+use super::errors::ExecutionError;
 use super::logical::*;
+use super::planbuilder::LogicalPlanBuilder;
 use super::sqlast::*;
+use super::sqltokenizer::*;
 use super::types::*;
 
 use arrow::datatypes::*;
 
 pub trait SchemaProvider {
+    /// Get the schema for the table with exactly this name
     fn get_table_meta(&self, name: &str) -> Option<Rc<Schema>>;
     fn get_function_meta(&self, name: &str) -> Option<Rc<FunctionMeta>>;
+
+    /// Names of all tables, used to resolve unquoted table names that differ in case
+    fn get_table_names(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// Find the position of the name that an identifier refers to, using the case-folding rule
+/// described in the module documentation. It is an error for an identifier to match more
+/// than one name.
+pub fn resolve_identifier(
+    names: &[&str],
+    id: &str,
+    quoted: bool,
+) -> Result<Option<usize>, String> {
+    let mut matches: Vec<usize> = (0..names.len()).filter(|i| names[*i] == id).collect();
+    if matches.len() == 0 && !quoted {
+        let lower_id = id.to_lowercase();
+        matches = (0..names.len())
+            .filter(|i| names[*i].to_lowercase() == lower_id)
+            .collect();
+    }
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(Some(matches[0])),
+        _ => Err(format!(
+            "Ambiguous identifier '{}' could refer to any of {:?}",
+            id,
+            matches.iter().map(|i| names[*i]).collect::<Vec<&str>>()
+        )),
+    }
 }
 
 /// SQL query planner
+#[derive(Clone)]
 pub struct SqlToRel {
     schema_provider: Rc<SchemaProvider>,
+    /// SQL text that the AST was parsed from, used to show the location of unknown identifiers
+    source: Option<String>,
+    /// Whether unquoted identifiers are matched case-sensitively
+    case_sensitive: bool,
+    /// Table that the columns of the SELECT being planned belong to, which column names can
+    /// be qualified with
+    relation: Option<String>,
 }
 
 impl SqlToRel {
     /// Create a new query planner
     pub fn new(schema_provider: Rc<SchemaProvider>) -> Self {
-        SqlToRel { schema_provider }
+        SqlToRel {
+            schema_provider,
+            source: None,
+            case_sensitive: false,
+            relation: None,
+        }
+    }
+
+    /// Match unquoted identifiers case-sensitively, in the same way as quoted identifiers
+    pub fn with_case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Use the SQL text that the AST being planned was parsed from to report the location of
+    /// errors
+    pub fn with_source(mut self, sql: &str) -> Self {
+        self.source = Some(sql.to_string());
+        self
+    }
+
+    /// Add the location of an identifier to an error message, if the identifier was parsed from
+    /// the SQL text
+    fn identifier_error(&self, id: &SQLIdent, message: String) -> String {
+        match (&self.source, &id.location) {
+            (Some(ref sql), Some(ref location)) => annotate_sql(sql, location, &message),
+            _ => message,
+        }
     }
 
     /// Generate a logic plan from a SQL AST node
@@ -62,19 +140,20 @@ impl SqlToRel {
                 };
 
                 let input_schema = input.schema();
+                let scope = self.with_relation(relation_name(&input));
 
                 // selection first
-                let selection_plan = match selection {
-                    &Some(ref filter_expr) => Some(LogicalPlan::Selection {
-                        expr: self.sql_to_rex(&filter_expr, &input_schema.clone())?,
-                        input: input.clone(),
-                    }),
-                    _ => None,
-                };
+                let mut builder =
+                    LogicalPlanBuilder::new(input.clone()).with_case_sensitive(self.case_sensitive);
+                if let &Some(ref filter_expr) = selection {
+                    builder = builder
+                        .filter(scope.sql_to_rex(&filter_expr, &input_schema)?)
+                        .map_err(plan_error)?;
+                }
 
                 let expr: Vec<Expr> = projection
                     .iter()
-                    .map(|e| self.sql_to_rex(&e, &input_schema))
+                    .map(|e| scope.sql_to_rex(&e, &input_schema))
                     .collect::<Result<Vec<Expr>, String>>()?;
 
                 // collect aggregate expressions
@@ -88,48 +167,22 @@ impl SqlToRel {
                     .collect();
 
                 if aggr_expr.len() > 0 {
-                    let aggregate_input: Rc<LogicalPlan> = match selection_plan {
-                        Some(s) => Rc::new(s),
-                        _ => input.clone(),
-                    };
-
                     let group_expr: Vec<Expr> = match group_by {
                         Some(gbe) => gbe
                             .iter()
-                            .map(|e| self.sql_to_rex(&e, &input_schema))
+                            .map(|e| scope.sql_to_rex(&e, &input_schema))
                             .collect::<Result<Vec<Expr>, String>>()?,
                         None => vec![],
                     };
                     //println!("GROUP BY: {:?}", group_expr);
 
-                    let mut all_fields: Vec<Expr> = group_expr.clone();
-                    aggr_expr.iter().for_each(|x| all_fields.push(x.clone()));
-
-                    let aggr_schema = Schema::new(exprlist_to_fields(&all_fields, input_schema));
-
                     //TODO: selection, projection, everything else
-                    Ok(Rc::new(LogicalPlan::Aggregate {
-                        input: aggregate_input,
-                        group_expr,
-                        aggr_expr,
-                        schema: Rc::new(aggr_schema),
-                    }))
+                    builder
+                        .aggregate(group_expr, aggr_expr)
+                        .and_then(|b| b.build())
+                        .map_err(plan_error)
                 } else {
-                    let projection_input: Rc<LogicalPlan> = match selection_plan {
-                        Some(s) => Rc::new(s),
-                        _ => input.clone(),
-                    };
-
-                    let projection_schema = Rc::new(Schema::new(exprlist_to_fields(
-                        &expr,
-                        input_schema.as_ref(),
-                    )));
-
-                    let projection = LogicalPlan::Projection {
-                        expr: expr,
-                        input: projection_input,
-                        schema: projection_schema.clone(),
-                    };
+                    builder = builder.project(expr).map_err(plan_error)?;
 
                     // aggregate queries
                     //                    match group_by {
@@ -141,53 +194,32 @@ impl SqlToRel {
                         return Err(String::from("HAVING is not implemented yet"));
                     }
 
-                    let order_by_plan = match order_by {
-                        &Some(ref order_by_expr) => {
-                            let input_schema = projection.schema();
-                            let order_by_rex: Result<Vec<Expr>, String> = order_by_expr
-                                .iter()
-                                .map(|e| self.sql_to_rex(e, &input_schema))
-                                .collect();
-
-                            LogicalPlan::Sort {
-                                expr: order_by_rex?,
-                                input: Rc::new(projection.clone()),
-                                schema: input_schema.clone(),
-                            }
-                        }
-                        _ => projection,
-                    };
+                    if let &Some(ref order_by_expr) = order_by {
+                        let projection = builder.build().map_err(plan_error)?;
+                        let input_schema = projection.schema();
+                        let scope = self.with_relation(relation_name(&projection));
+                        let order_by_rex: Result<Vec<Expr>, String> = order_by_expr
+                            .iter()
+                            .map(|e| scope.sql_to_rex(e, &input_schema))
+                            .collect();
+                        builder = builder.sort(order_by_rex?).map_err(plan_error)?;
+                    }
 
-                    let limit_plan = match limit {
-                        &Some(ref limit_ast_node) => {
-                            let limit_count = match **limit_ast_node {
-                                ASTNode::SQLLiteralLong(n) => n,
-                                _ => return Err(String::from("LIMIT parameter is not a number")),
-                            };
-                            LogicalPlan::Limit {
-                                limit: limit_count as usize,
-                                schema: order_by_plan.schema().clone(),
-                                input: Rc::new(order_by_plan),
-                            }
-                        }
-                        _ => order_by_plan,
-                    };
+                    if let &Some(ref limit_ast_node) = limit {
+                        let limit_count = match **limit_ast_node {
+                            ASTNode::SQLLiteralLong(n) => n,
+                            _ => return Err(String::from("LIMIT parameter is not a number")),
+                        };
+                        builder = builder.limit(limit_count as usize).map_err(plan_error)?;
+                    }
 
-                    Ok(Rc::new(limit_plan))
+                    builder.build().map_err(plan_error)
                 }
             }
 
-            &ASTNode::SQLIdentifier(ref id) => {
-                match self.schema_provider.get_table_meta(id.as_ref()) {
-                    Some(schema) => Ok(Rc::new(LogicalPlan::TableScan {
-                        schema_name: String::from("default"),
-                        table_name: id.clone(),
-                        schema: schema.clone(),
-                        projection: None,
-                    })),
-                    None => Err(format!("no schema found for table {}", id)),
-                }
-            }
+            &ASTNode::SQLIdentifier(ref id) => self.table_scan(id, false),
+
+            &ASTNode::SQLQuotedIdentifier(ref id) => self.table_scan(id, true),
 
             _ => Err(format!(
                 "sql_to_rel does not support this relation: {:?}",
@@ -196,6 +228,84 @@ impl SqlToRel {
         }
     }
 
+    /// Create a scan of the table that an identifier refers to
+    fn table_scan(&self, ident: &SQLIdent, quoted: bool) -> Result<Rc<LogicalPlan>, String> {
+        let id = ident.value.as_str();
+        let quoted = quoted || self.case_sensitive;
+        let table_name = match self.schema_provider.get_table_meta(id) {
+            Some(_) => Some(id.to_string()),
+            None if quoted => None,
+            None => {
+                let names = self.schema_provider.get_table_names();
+                let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+                resolve_identifier(&names, id, false)?.map(|i| names[i].to_string())
+            }
+        };
+        match table_name {
+            Some(table_name) => match self.schema_provider.get_table_meta(&table_name) {
+                Some(schema) => LogicalPlanBuilder::scan("default", &table_name, &schema, None)
+                    .and_then(|b| b.build())
+                    .map_err(plan_error),
+                None => Err(format!("no schema found for table {}", id)),
+            },
+            None => Err(self.identifier_error(ident, format!("no schema found for table {}", id))),
+        }
+    }
+
+    /// Copy the planner to plan the expressions of a SELECT whose columns belong to a table
+    fn with_relation(&self, relation: Option<&str>) -> SqlToRel {
+        SqlToRel {
+            relation: relation.map(|r| r.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// Create a reference to the column that an identifier refers to, which may be qualified
+    /// with the name of its table
+    fn column(
+        &self,
+        qualifier: Option<&SQLIdent>,
+        id: &SQLIdent,
+        quoted: bool,
+        schema: &Schema,
+    ) -> Result<Expr, String> {
+        let relation = self.relation.as_ref().map(|r| r.as_str());
+        resolve_column(
+            schema,
+            relation,
+            qualifier.map(|q| q.value.as_str()),
+            &id.value,
+            quoted || self.case_sensitive,
+        )
+        .map(Expr::Column)
+        .map_err(|e| self.identifier_error(qualifier.unwrap_or(id), e))
+    }
+
+    /// Plan the body of a function defined by a SQL expression and substitute the arguments
+    /// for the references to its parameters. The body is planned against a schema containing
+    /// the parameters, so it cannot refer to the columns of the query that calls it.
+    pub fn inline_macro(&self, fm: &FunctionMeta, args: &[Expr]) -> Result<Expr, String> {
+        let body = match fm.body() {
+            Some(body) => body,
+            None => return Err(format!("Function '{}' is not a SQL function", fm.name())),
+        };
+        let params = Schema::new(fm.args().clone());
+        // errors refer to the function body, so are not annotated with the query text
+        let planner =
+            SqlToRel::new(self.schema_provider.clone()).with_case_sensitive(self.case_sensitive);
+        let expr = planner
+            .sql_to_rex(body, &params)
+            .and_then(|e| e.cast_to(fm.return_type(), &params))
+            .map_err(|e| format!("Error in function '{}': {}", fm.name(), e))?;
+        if contains_aggregate(&expr) {
+            return Err(format!(
+                "Function '{}' cannot contain aggregate functions",
+                fm.name()
+            ));
+        }
+        Ok(replace_columns(&expr, args))
+    }
+
     /// Generate a relational expression from a SQL expression
     pub fn sql_to_rex(&self, sql: &ASTNode, schema: &Schema) -> Result<Expr, String> {
         match sql {
@@ -205,17 +315,16 @@ impl SqlToRel {
                 Ok(Expr::Literal(ScalarValue::Utf8(Rc::new(s.clone()))))
             }
 
-            &ASTNode::SQLIdentifier(ref id) => {
-                match schema.columns().iter().position(|c| c.name().eq(id)) {
-                    Some(index) => Ok(Expr::Column(index)),
-                    None => Err(format!(
-                        "Invalid identifier '{}' for schema {}",
-                        id,
-                        schema.to_string()
-                    )),
-                }
+            &ASTNode::SQLIdentifier(ref id) => self.column(None, id, false, schema),
+
+            &ASTNode::SQLQuotedIdentifier(ref id) => self.column(None, id, true, schema),
+
+            &ASTNode::SQLCompoundIdentifier(ref ids) if ids.len() == 2 => {
+                self.column(Some(&ids[0]), &ids[1], false, schema)
             }
 
+            &ASTNode::SQLNested(ref expr) => self.sql_to_rex(expr, schema),
+
             &ASTNode::SQLWildcard => {
                 //                schema.columns().iter().enumerate()
                 //                    .map(|(i,c)| Ok(Expr::Column(i))).collect()
@@ -238,6 +347,20 @@ impl SqlToRel {
                 Ok(Expr::IsNotNull(Rc::new(self.sql_to_rex(expr, schema)?)))
             }
 
+            &ASTNode::SQLUnary {
+                operator: SQLOperator::Not,
+                ref rex,
+            } => {
+                let expr = self.sql_to_rex(rex, schema)?;
+                match expr.get_type(schema)? {
+                    DataType::Boolean => Ok(Expr::Not(Rc::new(expr))),
+                    other => Err(format!(
+                        "NOT requires a boolean expression but found {:?}",
+                        other
+                    )),
+                }
+            }
+
             &ASTNode::SQLBinaryExpr {
                 ref left,
                 ref op,
@@ -257,27 +380,23 @@ impl SqlToRel {
                     &SQLOperator::Modulus => Operator::Modulus,
                     &SQLOperator::And => Operator::And,
                     &SQLOperator::Or => Operator::Or,
+                    &SQLOperator::Like => Operator::Like,
+                    &SQLOperator::ILike => Operator::ILike,
+                    &SQLOperator::Not => return Err("NOT is not a binary operator".to_string()),
                 };
 
                 let left_expr = self.sql_to_rex(&left, &schema)?;
                 let right_expr = self.sql_to_rex(&right, &schema)?;
-                let left_type = left_expr.get_type(schema);
-                let right_type = right_expr.get_type(schema);
-
-                match get_supertype(&left_type, &right_type) {
-                    Some(supertype) => Ok(Expr::BinaryExpr {
-                        left: Rc::new(left_expr.cast_to(&supertype, schema)?),
-                        op: operator,
-                        right: Rc::new(right_expr.cast_to(&supertype, schema)?),
-                    }),
-                    None => {
-                        return Err(format!(
-                            "No common supertype found for binary operator {:?} \
-                             with input types {:?} and {:?}",
-                            operator, left_type, right_type
-                        ))
-                    }
-                }
+                let operand_type = binary_operand_type(
+                    &left_expr.get_type(schema)?,
+                    &operator,
+                    &right_expr.get_type(schema)?,
+                )?;
+                Ok(Expr::BinaryExpr {
+                    left: Rc::new(cast_expr(left_expr, &operand_type, schema)?),
+                    op: operator,
+                    right: Rc::new(cast_expr(right_expr, &operand_type, schema)?),
+                })
             }
 
             &ASTNode::SQLOrderBy { ref expr, asc } => Ok(Expr::Sort {
@@ -295,7 +414,7 @@ impl SqlToRel {
                             .collect::<Result<Vec<Expr>, String>>()?;
 
                         // return type is same as the argument type for these aggregate functions
-                        let return_type = rex_args[0].get_type(schema).clone();
+                        let return_type = rex_args[0].get_type(schema)?;
 
                         Ok(Expr::AggregateFunction {
                             name: id.clone(),
@@ -327,17 +446,29 @@ impl SqlToRel {
                                 .map(|a| self.sql_to_rex(a, schema))
                                 .collect::<Result<Vec<Expr>, String>>()?;
 
+                            if rex_args.len() != fm.args().len() {
+                                return Err(format!(
+                                    "Function '{}' expects {} arguments but was given {}",
+                                    id,
+                                    fm.args().len(),
+                                    rex_args.len()
+                                ));
+                            }
+
                             let mut safe_args: Vec<Expr> = vec![];
                             for i in 0..rex_args.len() {
                                 safe_args
                                     .push(rex_args[i].cast_to(fm.args()[i].data_type(), schema)?);
                             }
 
-                            Ok(Expr::ScalarFunction {
-                                name: id.clone(),
-                                args: safe_args,
-                                return_type: fm.return_type().clone(),
-                            })
+                            match fm.function_type() {
+                                FunctionType::Macro => self.inline_macro(&fm, &safe_args),
+                                _ => Ok(Expr::ScalarFunction {
+                                    name: id.clone(),
+                                    args: safe_args,
+                                    return_type: fm.return_type().clone(),
+                                }),
+                            }
                         }
                         _ => Err(format!("Invalid function '{}'", id)),
                     },
@@ -370,9 +501,15 @@ pub fn convert_data_type(sql: &SQLType) -> DataType {
     }
 }
 
-pub fn expr_to_field(e: &Expr, input_schema: &Schema) -> Field {
-    match e {
+/// Get the field of the column that an expression produces, which is an error if the
+/// expression refers to a column that is not in the input or cannot be projected
+pub fn expr_to_field(e: &Expr, input_schema: &Schema) -> Result<Field, String> {
+    Ok(match e {
         Expr::Column(i) => input_schema.columns()[*i].clone(),
+        Expr::ColumnRef { ref name, .. } => {
+            let i = resolve_column(input_schema, None, None, name, false)?;
+            input_schema.columns()[i].clone()
+        }
         Expr::Literal(ref lit) => Field::new("lit", lit.get_datatype(), true),
         Expr::ScalarFunction {
             ref name,
@@ -385,137 +522,203 @@ pub fn expr_to_field(e: &Expr, input_schema: &Schema) -> Field {
             ..
         } => Field::new(name, return_type.clone(), true),
         Expr::Cast { ref data_type, .. } => Field::new("cast", data_type.clone(), true),
-        Expr::BinaryExpr {
-            ref left,
-            ref right,
-            ..
-        } => {
-            let left_type = left.get_type(input_schema);
-            let right_type = right.get_type(input_schema);
-            Field::new(
-                "binary_expr",
-                get_supertype(&left_type, &right_type).unwrap(),
-                true,
-            )
+        Expr::BinaryExpr { .. } => Field::new("binary_expr", e.get_type(input_schema)?, true),
+        Expr::Not(_) => Field::new("not", DataType::Boolean, true),
+        Expr::IsNull(_) => Field::new("is_null", DataType::Boolean, false),
+        Expr::IsNotNull(_) => Field::new("is_not_null", DataType::Boolean, false),
+        Expr::Sort { .. } => {
+            return Err(format!(
+                "Cannot determine schema type for expression {:?}",
+                e
+            ))
         }
-        _ => unimplemented!("Cannot determine schema type for expression {:?}", e),
-    }
+    })
 }
 
-pub fn exprlist_to_fields(expr: &Vec<Expr>, input_schema: &Schema) -> Vec<Field> {
+pub fn exprlist_to_fields(expr: &Vec<Expr>, input_schema: &Schema) -> Result<Vec<Field>, String> {
     expr.iter()
         .map(|e| expr_to_field(e, input_schema))
         .collect()
 }
 
-fn collect_expr(e: &Expr, accum: &mut HashSet<usize>) {
+/// Collect the indices of the columns that an expression refers to
+pub fn collect_expr(e: &Expr, accum: &mut HashSet<usize>) {
     match e {
         Expr::Column(i) => {
             accum.insert(*i);
         }
-        Expr::Cast { ref expr, .. } => collect_expr(expr, accum),
-        Expr::Literal(_) => {}
-        Expr::IsNotNull(ref expr) => collect_expr(expr, accum),
-        Expr::IsNull(ref expr) => collect_expr(expr, accum),
-        Expr::BinaryExpr {
-            ref left,
-            ref right,
-            ..
-        } => {
-            collect_expr(left, accum);
-            collect_expr(right, accum);
-        }
-        Expr::AggregateFunction { ref args, .. } => {
-            args.iter().for_each(|e| collect_expr(e, accum));
-        }
-        Expr::ScalarFunction { ref args, .. } => {
-            args.iter().for_each(|e| collect_expr(e, accum));
-        }
-        Expr::Sort { ref expr, .. } => collect_expr(expr, accum),
+        _ => e
+            .children()
+            .into_iter()
+            .for_each(|c| collect_expr(c, accum)),
+    }
+}
+
+/// Convert an error from building a plan to the error type of the planner
+fn plan_error(e: ExecutionError) -> String {
+    e.to_string()
+}
+
+/// Determine whether an expression contains an aggregate function
+pub fn contains_aggregate(e: &Expr) -> bool {
+    match e {
+        Expr::AggregateFunction { .. } => true,
+        _ => e.children().into_iter().any(contains_aggregate),
     }
 }
 
+/// Replace each column reference in an expression with the expression at that index
+pub fn replace_columns(e: &Expr, columns: &[Expr]) -> Expr {
+    match e {
+        Expr::Column(i) => columns[*i].clone(),
+        _ => e.with_new_children(
+            e.children()
+                .into_iter()
+                .map(|c| replace_columns(c, columns))
+                .collect(),
+        ),
+    }
+}
+
+/// Get the column indices in a projection in ascending order, so that pushing down the same
+/// projection always produces the same plan
+fn sorted_indices(projection: &HashSet<usize>) -> Vec<usize> {
+    let mut indices: Vec<usize> = projection.iter().cloned().collect();
+    indices.sort();
+    indices
+}
+
+/// Push a projection down to the tables and files that a plan reads, so that they only read
+/// the columns that are needed. The columns of every plan below are renumbered to match the
+/// narrower schemas, and the result has the columns of the plan that are in the projection,
+/// in ascending order.
 pub fn push_down_projection(
     plan: &Rc<LogicalPlan>,
     projection: &HashSet<usize>,
 ) -> Rc<LogicalPlan> {
     //println!("push_down_projection() projection={:?}", projection);
+    let (new_plan, mapping) = prune_columns(plan, projection);
+    let required = sorted_indices(projection);
+    let expr: Vec<Expr> = required
+        .iter()
+        .map(|i| Expr::Column(mapping[*i].unwrap()))
+        .collect();
+    if expr.iter().enumerate().all(|(i, e)| *e == Expr::Column(i))
+        && expr.len() == new_plan.schema().columns().len()
+    {
+        return new_plan;
+    }
+    Rc::new(LogicalPlan::Projection {
+        expr,
+        input: new_plan,
+        schema: Rc::new(project_schema(plan.schema(), &required)),
+    })
+}
+
+/// Remove the columns that are not required from a plan, returning the new plan and the index
+/// of each column of the old plan in the new plan. Columns that are required are always kept.
+fn prune_columns(
+    plan: &Rc<LogicalPlan>,
+    required: &HashSet<usize>,
+) -> (Rc<LogicalPlan>, Vec<Option<usize>>) {
     match plan.as_ref() {
-        LogicalPlan::Aggregate {
+        LogicalPlan::Projection {
+            ref expr,
             ref input,
-            ref group_expr,
-            ref aggr_expr,
             ref schema,
         } => {
-            //TODO: apply projection first
+            // a projection must have at least one column
+            let mut keep = sorted_indices(required);
+            if keep.is_empty() {
+                keep.push(0);
+            }
             let mut accum: HashSet<usize> = HashSet::new();
-            group_expr.iter().for_each(|e| collect_expr(e, &mut accum));
-            aggr_expr.iter().for_each(|e| collect_expr(e, &mut accum));
-            Rc::new(LogicalPlan::Aggregate {
-                input: push_down_projection(&input, &accum),
-                group_expr: group_expr.clone(),
-                aggr_expr: aggr_expr.clone(),
-                schema: schema.clone(),
-            })
+            keep.iter()
+                .for_each(|i| collect_expr(&expr[*i], &mut accum));
+            let (input, input_mapping) = prune_columns(input, &accum);
+            let new_plan = LogicalPlan::Projection {
+                expr: keep
+                    .iter()
+                    .map(|i| remap_columns(&expr[*i], &input_mapping))
+                    .collect(),
+                input,
+                schema: Rc::new(project_schema(schema, &keep)),
+            };
+            let mapping = (0..expr.len())
+                .map(|i| keep.iter().position(|k| *k == i))
+                .collect();
+            (Rc::new(new_plan), mapping)
         }
-        LogicalPlan::Selection {
-            ref expr,
-            ref input,
-        } => {
-            let mut accum: HashSet<usize> = projection.clone();
-            collect_expr(expr, &mut accum);
-            Rc::new(LogicalPlan::Selection {
-                expr: expr.clone(),
-                input: push_down_projection(&input, &accum),
-            })
+        LogicalPlan::Selection { .. } | LogicalPlan::Sort { .. } | LogicalPlan::Limit { .. } => {
+            // these plans have the columns of their input
+            let mut accum: HashSet<usize> = required.clone();
+            let expr = plan.expressions();
+            expr.iter().for_each(|e| collect_expr(e, &mut accum));
+            let (input, mapping) = prune_columns(plan.inputs()[0], &accum);
+            let new_plan = plan
+                .with_new_inputs(&[input])
+                .with_new_expressions(expr.iter().map(|e| remap_columns(e, &mapping)).collect());
+            (Rc::new(new_plan), mapping)
+        }
+        LogicalPlan::Aggregate { ref schema, .. } => {
+            // every grouping and aggregate expression is kept
+            let mut accum: HashSet<usize> = HashSet::new();
+            let expr = plan.expressions();
+            expr.iter().for_each(|e| collect_expr(e, &mut accum));
+            let (input, input_mapping) = prune_columns(plan.inputs()[0], &accum);
+            let new_plan = plan.with_new_inputs(&[input]).with_new_expressions(
+                expr.iter()
+                    .map(|e| remap_columns(e, &input_mapping))
+                    .collect(),
+            );
+            let mapping = (0..schema.columns().len()).map(Some).collect();
+            (Rc::new(new_plan), mapping)
+        }
+        LogicalPlan::TableScan { ref projection, .. }
+        | LogicalPlan::CsvFile { ref projection, .. }
+        | LogicalPlan::NdJsonFile { ref projection, .. }
+        | LogicalPlan::ParquetFile { ref projection, .. } => {
+            // the index in the table of each column that the scan currently reads
+            let columns: Vec<usize> = match projection {
+                Some(p) => p.clone(),
+                None => (0..plan.schema().columns().len()).collect(),
+            };
+            // at least one column is read so that the number of rows is known
+            let mut keep: Vec<usize> = sorted_indices(required)
+                .iter()
+                .map(|i| columns[*i])
+                .collect();
+            if keep.is_empty() {
+                keep.push(columns[0]);
+            }
+            keep.sort();
+            keep.dedup();
+            let mapping = columns
+                .iter()
+                .map(|c| keep.iter().position(|k| k == c))
+                .collect();
+            (Rc::new(plan.with_projection(keep)), mapping)
+        }
+        LogicalPlan::InMemory { ref schema, .. } | LogicalPlan::EmptyRelation { ref schema } => {
+            let mapping = (0..schema.columns().len()).map(Some).collect();
+            (plan.clone(), mapping)
         }
-        LogicalPlan::TableScan {
-            ref schema_name,
-            ref table_name,
-            ref schema,
-            ..
-        } => Rc::new(LogicalPlan::TableScan {
-            schema_name: schema_name.to_string(),
-            table_name: table_name.to_string(),
-            schema: schema.clone(),
-            projection: Some(projection.iter().cloned().collect()),
-        }),
-        LogicalPlan::CsvFile {
-            ref filename,
-            ref schema,
-            ref has_header,
-            ..
-        } => Rc::new(LogicalPlan::CsvFile {
-            filename: filename.to_string(),
-            schema: schema.clone(),
-            has_header: *has_header,
-            projection: Some(projection.iter().cloned().collect()),
-        }),
-        LogicalPlan::NdJsonFile {
-            ref filename,
-            ref schema,
-            ..
-        } => Rc::new(LogicalPlan::NdJsonFile {
-            filename: filename.to_string(),
-            schema: schema.clone(),
-            projection: Some(projection.iter().cloned().collect()),
-        }),
-        LogicalPlan::ParquetFile {
-            ref filename,
-            ref schema,
-            ..
-        } => Rc::new(LogicalPlan::ParquetFile {
-            filename: filename.to_string(),
-            schema: schema.clone(),
-            projection: Some(projection.iter().cloned().collect()),
-        }),
-        LogicalPlan::Projection { .. } => plan.clone(),
-        LogicalPlan::Limit { .. } => plan.clone(),
-        LogicalPlan::Sort { .. } => plan.clone(),
-        LogicalPlan::EmptyRelation { .. } => plan.clone(),
     }
 }
 
+/// Renumber the columns in an expression after columns have been removed from its input
+fn remap_columns(e: &Expr, mapping: &[Option<usize>]) -> Expr {
+    // columns that were removed are not referenced, so they are replaced with nulls
+    let columns: Vec<Expr> = mapping
+        .iter()
+        .map(|m| match m {
+            Some(i) => Expr::Column(*i),
+            None => Expr::Literal(ScalarValue::Null),
+        })
+        .collect();
+    replace_columns(e, &columns)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -550,6 +753,80 @@ mod tests {
         quick_test(sql, expected);
     }
 
+    #[test]
+    fn select_case_insensitive_identifiers() {
+        let sql = "SELECT ID, First_Name FROM PERSON WHERE \"state\" = 'CO'";
+        let expected = "Projection: #0, #1\
+                        \n  Selection: #4 Eq Utf8(\"CO\")\
+                        \n    TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_quoted_identifier_is_case_sensitive() {
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let ast = Parser::parse_sql("SELECT \"ID\" FROM person".to_string()).unwrap();
+        assert!(planner.sql_to_rel(&ast).is_err());
+    }
+
+    #[test]
+    fn select_invalid_identifier_location() {
+        let sql = "SELECT id,\n  age, salry FROM person";
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {})).with_source(sql);
+        let ast = Parser::parse_sql(sql.to_string()).unwrap();
+        let err = planner.sql_to_rel(&ast).unwrap_err();
+        assert!(err.starts_with("Invalid identifier 'salry' for schema"));
+        assert!(err.contains("Did you mean 'salary'?"));
+        assert!(err.ends_with("at line 2, column 8\n  age, salry FROM person\n       ^"));
+    }
+
+    #[test]
+    fn select_repeated_invalid_identifier_location() {
+        // the function name is the same as the unknown column that follows it
+        let sql = "SELECT sqrt(age),\n  sqrt FROM person";
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {})).with_source(sql);
+        let ast = Parser::parse_sql(sql.to_string()).unwrap();
+        let err = planner.sql_to_rel(&ast).unwrap_err();
+        assert!(err.starts_with("Invalid identifier 'sqrt' for schema"));
+        assert!(err.ends_with("at line 2, column 3\n  sqrt FROM person\n  ^"));
+    }
+
+    #[test]
+    fn select_qualified_identifiers() {
+        let sql = "SELECT person.id, Person.first_name FROM person \
+                   WHERE person.state = 'CO' ORDER BY person.id";
+        let expected = "Sort: #0 ASC\
+                        \n  Projection: #0, #1\
+                        \n    Selection: #4 Eq Utf8(\"CO\")\
+                        \n      TableScan: person projection=None";
+        quick_test(sql, expected);
+
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let ast = Parser::parse_sql("SELECT persons.id FROM person".to_string()).unwrap();
+        assert_eq!(
+            "Invalid table 'persons' in column reference 'persons.id'. Did you mean 'person'?",
+            planner.sql_to_rel(&ast).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_resolve_identifier() {
+        let names = vec!["a", "B", "c", "C"];
+        assert_eq!(Ok(Some(0)), resolve_identifier(&names, "a", true));
+        assert_eq!(Ok(Some(0)), resolve_identifier(&names, "A", false));
+        assert_eq!(Ok(None), resolve_identifier(&names, "A", true));
+        assert_eq!(Ok(Some(1)), resolve_identifier(&names, "b", false));
+        assert_eq!(Ok(Some(3)), resolve_identifier(&names, "C", false));
+        assert!(resolve_identifier(&names, "d", false).unwrap().is_none());
+        assert!(
+            resolve_identifier(&vec!["x", "X"], "Y", false)
+                .unwrap()
+                .is_none()
+        );
+        assert!(resolve_identifier(&vec!["xy", "XY"], "Xy", false).is_err());
+        assert!(resolve_identifier(&vec!["a", "a"], "a", true).is_err());
+    }
+
     #[test]
     fn select_compound_selection() {
         let sql = "SELECT id, first_name, last_name \
@@ -726,6 +1003,26 @@ mod tests {
     //
     //    }
 
+    #[test]
+    fn expr_to_field_errors() {
+        let schema = Schema::new(vec![Field::new("name", DataType::Utf8, false)]);
+        let column_ref = |name: &str| Expr::ColumnRef {
+            relation: None,
+            name: name.to_string(),
+        };
+        assert_eq!(
+            Field::new("name", DataType::Utf8, false),
+            expr_to_field(&column_ref("NAME"), &schema).unwrap()
+        );
+        let err = expr_to_field(&column_ref("nme"), &schema).unwrap_err();
+        assert!(err.ends_with("Did you mean 'name'?"), "{}", err);
+        let sort = Expr::Sort {
+            expr: Rc::new(Expr::Column(0)),
+            asc: true,
+        };
+        assert!(expr_to_field(&sort, &schema).is_err());
+    }
+
     /// Create logical plan, write with formatter, compare to expected output
     fn quick_test(sql: &str, expected: &str) {
         let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
@@ -751,6 +1048,10 @@ mod tests {
             }
         }
 
+        fn get_table_names(&self) -> Vec<String> {
+            vec!["person".to_string()]
+        }
+
         fn get_function_meta(&self, name: &str) -> Option<Rc<FunctionMeta>> {
             match name {
                 "sqrt" => Some(Rc::new(FunctionMeta::new(
@@ -13,17 +13,30 @@
 // limitations under the License.
 
 //! SQL Tokenizer
-
+//!
+//! Comments (`-- ...` to the end of the line and `/* ... */`) are treated as whitespace.
+//! Identifiers may be enclosed in double quotes so that they can contain spaces or clash with
+//! keywords, e.g. `"first name"` or `"location"`. A quote character is escaped inside a quoted
+//! identifier or string literal by doubling it, e.g. `"a ""b"""` or `'it''s'`.
+//!
+//! Keywords and quote characters depend on the SQL dialect (see `sqldialect`).
+
+use std::fmt;
 use std::iter::Peekable;
+use std::rc::Rc;
 use std::str::Chars;
 
 use fnv::FnvHashSet;
 
+use super::sqldialect::*;
+
 /// SQL Token enumeration
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     /// SQL identifier e.g. table or column name
     Identifier(String),
+    /// SQL identifier enclosed in double quotes e.g. "first name"
+    QuotedIdentifier(String),
     /// SQL keyword  e.g. Keyword("SELECT")
     Keyword(String),
     /// Numeric literal
@@ -62,11 +75,95 @@ pub enum Token {
     RParen,
     /// Period (used for compound identifiers or projections into nested types)
     Period,
+    /// Semicolon (used to separate statements)
+    SemiColon,
+    /// Double colon `::` (used for casts in some dialects)
+    DoubleColon,
+}
+
+/// Location of a token or error in a SQL statement. Lines and columns start at 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
 }
 
 /// Tokenizer error
 #[derive(Debug)]
-pub struct TokenizerError(String);
+pub struct TokenizerError {
+    pub message: String,
+    pub location: Location,
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.location)
+    }
+}
+
+/// Format an error message with the location of the error and the offending line of SQL,
+/// with a caret pointing to the column where the error was found
+pub fn annotate_sql(sql: &str, location: &Location, message: &str) -> String {
+    let line = sql.lines().nth(location.line - 1).unwrap_or("");
+    format!(
+        "{} at {}\n{}\n{}^",
+        message,
+        location,
+        line,
+        " ".repeat(location.column - 1)
+    )
+}
+
+/// Character iterator that keeps track of the current line and column
+struct LocatedChars<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> LocatedChars<'a> {
+    fn new(s: &'a str) -> Self {
+        LocatedChars {
+            chars: s.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    fn location(&self) -> Location {
+        Location {
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+impl<'a> Iterator for LocatedChars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.chars.next();
+        match ch {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            Some(_) => self.column += 1,
+            None => {}
+        }
+        ch
+    }
+}
 
 lazy_static! {
     static ref KEYWORDS: FnvHashSet<&'static str> = {
@@ -101,11 +198,32 @@ lazy_static! {
         m.insert("STORED");
         m.insert("CSV");
         m.insert("PARQUET");
+        m.insert("NDJSON");
         m.insert("LOCATION");
         m.insert("WITH");
         m.insert("WITHOUT");
         m.insert("HEADER");
         m.insert("ROW");
+        m.insert("EXPLAIN");
+        m.insert("ANALYZE");
+        m.insert("LIKE");
+        m.insert("DROP");
+        m.insert("IF");
+        m.insert("EXISTS");
+        m.insert("SHOW");
+        m.insert("TABLES");
+        m.insert("COLUMNS");
+        m.insert("DESCRIBE");
+        m.insert("VIEW");
+        m.insert("REPLACE");
+        m.insert("INTO");
+        m.insert("COPY");
+        m.insert("TO");
+        m.insert("PARTITIONED");
+        m.insert("OPTIONS");
+        m.insert("FUNCTION");
+        m.insert("TEMPORARY");
+        m.insert("RETURNS");
 
         // SQL types
         m.insert("STRING");
@@ -134,45 +252,67 @@ lazy_static! {
     };
 }
 
+/// Determine whether a word is an ANSI SQL keyword, ignoring case
+pub fn is_keyword(s: &str) -> bool {
+    KEYWORDS.contains(s.to_uppercase().as_str())
+}
+
 /// SQL Tokenizer
 pub struct Tokenizer {
     pub query: String,
+    dialect: Rc<Dialect>,
 }
 
 impl Tokenizer {
-    /// Create a new SQL tokenizer for the specified SQL statement
+    /// Create a new SQL tokenizer for the specified SQL statement, using the ANSI dialect
     pub fn new(query: &str) -> Self {
+        Tokenizer::new_with_dialect(query, Rc::new(AnsiDialect {}))
+    }
+
+    /// Create a new SQL tokenizer for the specified SQL statement and dialect
+    pub fn new_with_dialect(query: &str, dialect: Rc<Dialect>) -> Self {
         Self {
             query: query.to_string(),
+            dialect,
         }
     }
 
     /// Tokenize the statement and produce a vector of tokens
     pub fn tokenize(&mut self) -> Result<Vec<Token>, TokenizerError> {
-        let mut peekable = self.query.chars().peekable();
-
-        let mut tokens: Vec<Token> = vec![];
+        Ok(self
+            .tokenize_with_location()?
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect())
+    }
 
-        while let Some(token) = self.next_token(&mut peekable)? {
-            tokens.push(token);
+    /// Tokenize the statement and produce a vector of tokens along with the location of the
+    /// start of each token
+    pub fn tokenize_with_location(&mut self) -> Result<Vec<(Token, Location)>, TokenizerError> {
+        let mut chars = LocatedChars::new(&self.query);
+
+        let mut tokens: Vec<(Token, Location)> = vec![];
+
+        loop {
+            let location = chars.location();
+            match self.next_token(&mut chars) {
+                Ok(Some(Token::Whitespace)) => {}
+                Ok(Some(token)) => tokens.push((token, location)),
+                Ok(None) => break,
+                Err(message) => return Err(TokenizerError { message, location }),
+            }
         }
 
-        Ok(tokens
-            .into_iter()
-            .filter(|t| match t {
-                Token::Whitespace => false,
-                _ => true,
-            })
-            .collect())
+        Ok(tokens)
     }
 
     /// Get the next token or return None
-    fn next_token(&self, chars: &mut Peekable<Chars>) -> Result<Option<Token>, TokenizerError> {
+    fn next_token(&self, chars: &mut LocatedChars) -> Result<Option<Token>, String> {
         //println!("next_token: {:?}", chars.peek());
         match chars.peek() {
             Some(&ch) => match ch {
                 // whitespace
-                ' ' | '\t' | '\n' => {
+                ' ' | '\t' | '\n' | '\r' => {
                     chars.next(); // consume
                     Ok(Some(Token::Whitespace))
                 }
@@ -189,32 +329,24 @@ impl Tokenizer {
                         }
                     }
                     let upper_str = s.to_uppercase();
-                    if KEYWORDS.contains(upper_str.as_str()) {
+                    if self.dialect.is_keyword(upper_str.as_str()) {
                         Ok(Some(Token::Keyword(upper_str)))
                     } else {
                         Ok(Some(Token::Identifier(s)))
                     }
                 }
                 // string
-                '\'' => {
-                    //TODO: handle escaped quotes in string
-                    //TODO: handle EOF before terminating quote
-                    let mut s = String::new();
+                ch if self.dialect.is_string_quote(ch) => {
                     chars.next(); // consume
-                    while let Some(&ch) = chars.peek() {
-                        match ch {
-                            '\'' => {
-                                chars.next(); // consume
-                                break;
-                            }
-                            _ => {
-                                chars.next(); // consume
-                                s.push(ch);
-                            }
-                        }
-                    }
+                    let s = self.tokenize_quoted(chars, ch, "string literal")?;
                     Ok(Some(Token::String(s)))
                 }
+                // quoted identifier
+                ch if self.dialect.is_identifier_quote(ch) => {
+                    chars.next(); // consume
+                    let s = self.tokenize_quoted(chars, ch, "quoted identifier")?;
+                    Ok(Some(Token::QuotedIdentifier(s)))
+                }
                 // numbers
                 '0'...'9' => {
                     let mut s = String::new();
@@ -234,6 +366,17 @@ impl Tokenizer {
                     chars.next();
                     Ok(Some(Token::Comma))
                 }
+                ';' => {
+                    chars.next();
+                    Ok(Some(Token::SemiColon))
+                }
+                ':' if self.dialect.supports_double_colon_cast() => {
+                    chars.next(); // consume
+                    match chars.next() {
+                        Some(':') => Ok(Some(Token::DoubleColon)),
+                        _ => Err("Expected ':' after ':'".to_string()),
+                    }
+                }
                 '(' => {
                     chars.next();
                     Ok(Some(Token::LParen))
@@ -248,16 +391,44 @@ impl Tokenizer {
                     Ok(Some(Token::Plus))
                 }
                 '-' => {
-                    chars.next();
-                    Ok(Some(Token::Minus))
+                    chars.next(); // consume
+                    match chars.peek() {
+                        Some(&'-') => {
+                            // single line comment
+                            while let Some(ch) = chars.next() {
+                                if ch == '\n' {
+                                    break;
+                                }
+                            }
+                            Ok(Some(Token::Whitespace))
+                        }
+                        _ => Ok(Some(Token::Minus)),
+                    }
                 }
                 '*' => {
                     chars.next();
                     Ok(Some(Token::Mult))
                 }
                 '/' => {
-                    chars.next();
-                    Ok(Some(Token::Div))
+                    chars.next(); // consume
+                    match chars.peek() {
+                        Some(&'*') => {
+                            // multi line comment
+                            chars.next(); // consume
+                            let mut prev = ' ';
+                            loop {
+                                match chars.next() {
+                                    Some('/') if prev == '*' => break,
+                                    Some(ch) => prev = ch,
+                                    None => {
+                                        return Err("Unterminated comment".to_string())
+                                    }
+                                }
+                            }
+                            Ok(Some(Token::Whitespace))
+                        }
+                        _ => Ok(Some(Token::Div)),
+                    }
                 }
                 '%' => {
                     chars.next();
@@ -279,9 +450,9 @@ impl Tokenizer {
                                 chars.next();
                                 Ok(Some(Token::Neq))
                             }
-                            _ => Err(TokenizerError(format!("TBD"))),
+                            _ => Err(format!("Expected '=' after '!' but found '{}'", ch)),
                         },
-                        None => Err(TokenizerError(format!("TBD"))),
+                        None => Err("Expected '=' after '!'".to_string()),
                     }
                 }
                 '<' => {
@@ -314,14 +485,41 @@ impl Tokenizer {
                         None => Ok(Some(Token::Gt)),
                     }
                 }
-                _ => Err(TokenizerError(format!(
-                    "unhandled char '{}' in tokenizer",
-                    ch
-                ))),
+                _ => Err(format!("unhandled char '{}' in tokenizer", ch)),
             },
             None => Ok(None),
         }
     }
+
+    /// Read the remainder of a quoted string or identifier after the opening quote has been
+    /// consumed. A doubled quote character is an escaped quote.
+    fn tokenize_quoted(
+        &self,
+        chars: &mut LocatedChars,
+        quote: char,
+        kind: &str,
+    ) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match chars.next() {
+                Some(ch) if ch == quote => {
+                    if chars.peek() == Some(&quote) {
+                        chars.next(); // consume
+                        s.push(quote);
+                    } else {
+                        return Ok(s);
+                    }
+                }
+                Some(ch) => s.push(ch),
+                None => {
+                    return Err(format!(
+                        "Unterminated {} starting with {}{}",
+                        kind, quote, s
+                    ))
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -401,6 +599,76 @@ mod tests {
         compare(expected, tokens);
     }
 
+    #[test]
+    fn tokenize_escaped_string() {
+        let sql = String::from("SELECT 'it''s', ''");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize().unwrap();
+
+        let expected = vec![
+            Token::Keyword(String::from("SELECT")),
+            Token::String(String::from("it's")),
+            Token::Comma,
+            Token::String(String::from("")),
+        ];
+
+        compare(expected, tokens);
+    }
+
+    #[test]
+    fn tokenize_unterminated_string() {
+        let sql = String::from("SELECT 'abc");
+        let mut tokenizer = Tokenizer::new(&sql);
+        assert!(tokenizer.tokenize().is_err());
+    }
+
+    #[test]
+    fn tokenize_quoted_identifier() {
+        let sql = String::from("SELECT \"first name\", \"location\", \"a \"\"b\"\"\" FROM t");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize().unwrap();
+
+        let expected = vec![
+            Token::Keyword(String::from("SELECT")),
+            Token::QuotedIdentifier(String::from("first name")),
+            Token::Comma,
+            Token::QuotedIdentifier(String::from("location")),
+            Token::Comma,
+            Token::QuotedIdentifier(String::from("a \"b\"")),
+            Token::Keyword(String::from("FROM")),
+            Token::Identifier(String::from("t")),
+        ];
+
+        compare(expected, tokens);
+    }
+
+    #[test]
+    fn tokenize_comments() {
+        let sql = String::from("SELECT a -- the a column\n, b /* the\nb column */ - 1 / 2");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize().unwrap();
+
+        let expected = vec![
+            Token::Keyword(String::from("SELECT")),
+            Token::Identifier(String::from("a")),
+            Token::Comma,
+            Token::Identifier(String::from("b")),
+            Token::Minus,
+            Token::Number(String::from("1")),
+            Token::Div,
+            Token::Number(String::from("2")),
+        ];
+
+        compare(expected, tokens);
+    }
+
+    #[test]
+    fn tokenize_unterminated_comment() {
+        let sql = String::from("SELECT a /* comment");
+        let mut tokenizer = Tokenizer::new(&sql);
+        assert!(tokenizer.tokenize().is_err());
+    }
+
     #[test]
     fn tokenize_is_null() {
         let sql = String::from("a IS NULL");
@@ -416,6 +684,71 @@ mod tests {
         compare(expected, tokens);
     }
 
+    #[test]
+    fn tokenize_with_location() {
+        let sql = String::from("SELECT a,\n  'b'\n\tFROM t");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize_with_location().unwrap();
+        let locations: Vec<(usize, usize)> =
+            tokens.iter().map(|(_, l)| (l.line, l.column)).collect();
+        assert_eq!(vec![(1, 1), (1, 8), (1, 9), (2, 3), (3, 2), (3, 7)], locations);
+    }
+
+    #[test]
+    fn tokenize_error_location() {
+        let sql = String::from("SELECT a,\n  b ! c");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let e = tokenizer.tokenize().unwrap_err();
+        assert_eq!(Location { line: 2, column: 5 }, e.location);
+        assert_eq!(
+            "Expected '=' after '!' but found ' ' at line 2, column 5\n  b ! c\n    ^",
+            annotate_sql(&sql, &e.location, &e.message)
+        );
+    }
+
+    #[test]
+    fn tokenize_hive_quotes() {
+        let sql = String::from("SELECT `first name` FROM t WHERE c = \"x\"");
+        let mut tokenizer = Tokenizer::new_with_dialect(&sql, Rc::new(HiveDialect {}));
+        let tokens = tokenizer.tokenize().unwrap();
+
+        let expected = vec![
+            Token::Keyword(String::from("SELECT")),
+            Token::QuotedIdentifier(String::from("first name")),
+            Token::Keyword(String::from("FROM")),
+            Token::Identifier(String::from("t")),
+            Token::Keyword(String::from("WHERE")),
+            Token::Identifier(String::from("c")),
+            Token::Eq,
+            Token::String(String::from("x")),
+        ];
+
+        compare(expected, tokens);
+    }
+
+    #[test]
+    fn tokenize_postgresql_cast() {
+        let sql = String::from("a::INT ILIKE");
+        let mut tokenizer = Tokenizer::new_with_dialect(&sql, Rc::new(PostgreSqlDialect {}));
+        let tokens = tokenizer.tokenize().unwrap();
+
+        let expected = vec![
+            Token::Identifier(String::from("a")),
+            Token::DoubleColon,
+            Token::Keyword(String::from("INT")),
+            Token::Keyword(String::from("ILIKE")),
+        ];
+
+        compare(expected, tokens);
+
+        // neither is supported by ANSI SQL
+        assert!(Tokenizer::new(&sql).tokenize().is_err());
+        assert_eq!(
+            vec![Token::Identifier(String::from("ILIKE"))],
+            Tokenizer::new("ILIKE").tokenize().unwrap()
+        );
+    }
+
     fn compare(expected: Vec<Token>, actual: Vec<Token>) {
         //println!("------------------------------");
         //println!("tokens   = {:?}", actual);
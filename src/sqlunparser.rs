@@ -0,0 +1,325 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SQL Unparser (produces a SQL AST from a logical plan)
+//!
+//! Plans are converted to a single SELECT statement, so they must have the shape of a plan
+//! created by the SQL query planner: an optional limit, sort, projection or aggregate and
+//! selection over a table scan (or no relation at all). Use `ExecutionContext::table` to create
+//! DataFrames that scan registered tables.
+
+use std::rc::Rc;
+
+use super::logical::*;
+use super::sqlast::*;
+use super::types::*;
+
+use arrow::datatypes::*;
+
+/// Convert a logical plan to a SQL SELECT statement
+pub fn plan_to_sql(plan: &LogicalPlan) -> Result<ASTNode, String> {
+    let mut plan = plan;
+
+    let limit = match plan {
+        LogicalPlan::Limit { limit, input, .. } => {
+            plan = input.as_ref();
+            Some(Box::new(ASTNode::SQLLiteralLong(*limit as i64)))
+        }
+        _ => None,
+    };
+
+    let order_by = match plan {
+        LogicalPlan::Sort { expr, input, .. } => {
+            plan = input.as_ref();
+            Some(exprlist_to_sql(expr, input.schema())?)
+        }
+        _ => None,
+    };
+
+    let (projection, group_by) = match plan {
+        LogicalPlan::Projection { expr, input, .. } => {
+            plan = input.as_ref();
+            (exprlist_to_sql(expr, input.schema())?, None)
+        }
+        LogicalPlan::Aggregate {
+            group_expr,
+            aggr_expr,
+            input,
+            ..
+        } => {
+            plan = input.as_ref();
+            let group_by = exprlist_to_sql(group_expr, input.schema())?;
+            let mut projection = group_by.clone();
+            projection.extend(exprlist_to_sql(aggr_expr, input.schema())?);
+            (
+                projection,
+                if group_by.len() > 0 {
+                    Some(group_by)
+                } else {
+                    None
+                },
+            )
+        }
+        // select all columns
+        _ => (
+            plan.schema()
+                .columns()
+                .iter()
+                .map(|c| identifier(c.name()))
+                .collect(),
+            None,
+        ),
+    };
+
+    // consecutive selections are combined into a single WHERE clause
+    let mut selection: Option<Box<ASTNode>> = None;
+    while let LogicalPlan::Selection { expr, input } = plan {
+        plan = input.as_ref();
+        let expr = expr_to_sql(expr, input.schema())?;
+        selection = Some(Box::new(match selection {
+            Some(s) => ASTNode::SQLBinaryExpr {
+                left: Box::new(expr),
+                op: SQLOperator::And,
+                right: s,
+            },
+            None => expr,
+        }));
+    }
+
+    let relation = match plan {
+        LogicalPlan::TableScan { table_name, .. } => Some(Box::new(identifier(table_name))),
+        LogicalPlan::EmptyRelation { .. } => None,
+        other => {
+            return Err(format!(
+                "Cannot convert plan to a single SELECT statement: {:?}",
+                other
+            ))
+        }
+    };
+
+    Ok(ASTNode::SQLSelect {
+        projection,
+        relation,
+        selection,
+        order_by,
+        group_by,
+        having: None,
+        limit,
+    })
+}
+
+/// Convert a list of relational expressions to SQL expressions
+fn exprlist_to_sql(expr: &Vec<Expr>, schema: &Schema) -> Result<Vec<ASTNode>, String> {
+    expr.iter().map(|e| expr_to_sql(e, schema)).collect()
+}
+
+/// Convert a relational expression to a SQL expression, using the input schema to determine
+/// column names
+pub fn expr_to_sql(expr: &Expr, schema: &Schema) -> Result<ASTNode, String> {
+    match expr {
+        Expr::Column(i) => Ok(identifier(schema.column(*i).name())),
+        Expr::ColumnRef {
+            relation: Some(relation),
+            name,
+        } => Ok(ASTNode::SQLCompoundIdentifier(vec![
+            SQLIdent::new(relation),
+            SQLIdent::new(name),
+        ])),
+        Expr::ColumnRef { name, .. } => Ok(identifier(name)),
+        Expr::Literal(value) => literal_to_sql(value),
+        Expr::BinaryExpr { left, op, right } => Ok(ASTNode::SQLBinaryExpr {
+            left: Box::new(expr_to_sql(left, schema)?),
+            op: operator_to_sql(op),
+            right: Box::new(expr_to_sql(right, schema)?),
+        }),
+        Expr::IsNull(e) => Ok(ASTNode::SQLIsNull(Box::new(expr_to_sql(e, schema)?))),
+        Expr::IsNotNull(e) => Ok(ASTNode::SQLIsNotNull(Box::new(expr_to_sql(e, schema)?))),
+        Expr::Not(e) => Ok(ASTNode::SQLUnary {
+            operator: SQLOperator::Not,
+            rex: Box::new(expr_to_sql(e, schema)?),
+        }),
+        Expr::Cast { expr, data_type } => Ok(ASTNode::SQLCast {
+            expr: Box::new(expr_to_sql(expr, schema)?),
+            data_type: data_type_to_sql(data_type)?,
+        }),
+        Expr::Sort { expr, asc } => Ok(ASTNode::SQLOrderBy {
+            expr: Box::new(expr_to_sql(expr, schema)?),
+            asc: *asc,
+        }),
+        Expr::ScalarFunction { name, args, .. } | Expr::AggregateFunction { name, args, .. } => {
+            Ok(ASTNode::SQLFunction {
+                id: name.clone(),
+                args: exprlist_to_sql(args, schema)?,
+            })
+        }
+    }
+}
+
+/// Create an identifier, which is quoted if necessary
+fn identifier(name: &str) -> ASTNode {
+    if is_plain_identifier(name) {
+        ASTNode::SQLIdentifier(SQLIdent::new(name))
+    } else {
+        ASTNode::SQLQuotedIdentifier(SQLIdent::new(name))
+    }
+}
+
+/// Integer literals other than Int64 are written as a cast of an Int64 literal
+fn cast_literal(n: i64, data_type: SQLType) -> ASTNode {
+    ASTNode::SQLCast {
+        expr: Box::new(ASTNode::SQLLiteralLong(n)),
+        data_type,
+    }
+}
+
+fn literal_to_sql(value: &ScalarValue) -> Result<ASTNode, String> {
+    match value {
+        ScalarValue::Int64(n) => Ok(ASTNode::SQLLiteralLong(*n)),
+        ScalarValue::Float64(n) => Ok(ASTNode::SQLLiteralDouble(*n)),
+        ScalarValue::Float32(n) => Ok(ASTNode::SQLCast {
+            expr: Box::new(ASTNode::SQLLiteralDouble(*n as f64)),
+            data_type: SQLType::Float32,
+        }),
+        ScalarValue::Int8(n) => Ok(cast_literal(*n as i64, SQLType::Int8)),
+        ScalarValue::Int16(n) => Ok(cast_literal(*n as i64, SQLType::Int16)),
+        ScalarValue::Int32(n) => Ok(cast_literal(*n as i64, SQLType::Int32)),
+        ScalarValue::UInt8(n) => Ok(cast_literal(*n as i64, SQLType::UInt8)),
+        ScalarValue::UInt16(n) => Ok(cast_literal(*n as i64, SQLType::UInt16)),
+        ScalarValue::UInt32(n) => Ok(cast_literal(*n as i64, SQLType::UInt32)),
+        ScalarValue::UInt64(n) if *n <= i64::max_value() as u64 => {
+            Ok(cast_literal(*n as i64, SQLType::UInt64))
+        }
+        ScalarValue::Utf8(s) => Ok(ASTNode::SQLLiteralString(s.as_ref().clone())),
+        other => Err(format!("Cannot convert literal {:?} to SQL", other)),
+    }
+}
+
+fn operator_to_sql(op: &Operator) -> SQLOperator {
+    match op {
+        Operator::Eq => SQLOperator::Eq,
+        Operator::NotEq => SQLOperator::NotEq,
+        Operator::Lt => SQLOperator::Lt,
+        Operator::LtEq => SQLOperator::LtEq,
+        Operator::Gt => SQLOperator::Gt,
+        Operator::GtEq => SQLOperator::GtEq,
+        Operator::Plus => SQLOperator::Plus,
+        Operator::Minus => SQLOperator::Minus,
+        Operator::Multiply => SQLOperator::Multiply,
+        Operator::Divide => SQLOperator::Divide,
+        Operator::Modulus => SQLOperator::Modulus,
+        Operator::And => SQLOperator::And,
+        Operator::Or => SQLOperator::Or,
+        Operator::Like => SQLOperator::Like,
+        Operator::ILike => SQLOperator::ILike,
+    }
+}
+
+fn data_type_to_sql(data_type: &DataType) -> Result<SQLType, String> {
+    match data_type {
+        DataType::Boolean => Ok(SQLType::Boolean),
+        DataType::UInt8 => Ok(SQLType::UInt8),
+        DataType::UInt16 => Ok(SQLType::UInt16),
+        DataType::UInt32 => Ok(SQLType::UInt32),
+        DataType::UInt64 => Ok(SQLType::UInt64),
+        DataType::Int8 => Ok(SQLType::Int8),
+        DataType::Int16 => Ok(SQLType::Int16),
+        DataType::Int32 => Ok(SQLType::Int32),
+        DataType::Int64 => Ok(SQLType::Int64),
+        DataType::Float32 => Ok(SQLType::Float32),
+        DataType::Float64 => Ok(SQLType::Double64),
+        DataType::Utf8 => Ok(SQLType::Utf8(100)),
+        other => Err(format!("Cannot convert data type {:?} to SQL", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::datasources::common::FileOptions;
+    use super::super::sqlparser::Parser;
+    use super::super::sqlplanner::*;
+    use super::*;
+
+    #[test]
+    fn unparse_select() {
+        round_trip(
+            "SELECT id, \"first name\" FROM person WHERE age > 21 AND state = 'CO' LIMIT 10",
+        );
+        round_trip("SELECT id FROM person ORDER BY id DESC");
+        round_trip("SELECT sqrt(CAST(age AS FLOAT64)) FROM person");
+        round_trip("SELECT 1 + 2");
+    }
+
+    #[test]
+    fn unparse_aggregate() {
+        round_trip("SELECT state, MIN(age), MAX(age) FROM person GROUP BY state");
+        round_trip("SELECT COUNT(id) FROM person WHERE age IS NOT NULL");
+    }
+
+    #[test]
+    fn unparse_file_fails() {
+        let schema = Rc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let plan = LogicalPlan::CsvFile {
+            filename: "people.csv".to_string(),
+            schema: schema.clone(),
+            has_header: true,
+            projection: None,
+            projected_schema: schema,
+            options: FileOptions::default(),
+            filters: vec![],
+        };
+        assert!(plan_to_sql(&plan).is_err());
+    }
+
+    /// Plan the SQL, convert the plan back to SQL and check that planning that SQL produces
+    /// the same plan
+    fn round_trip(sql: &str) {
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let plan = planner
+            .sql_to_rel(&Parser::parse_sql(sql.to_string()).unwrap())
+            .unwrap();
+        let unparsed = plan_to_sql(&plan).unwrap().to_string();
+        let plan2 = planner
+            .sql_to_rel(&Parser::parse_sql(unparsed.clone()).unwrap())
+            .unwrap();
+        assert_eq!(format!("{:?}", plan), format!("{:?}", plan2), "{}", unparsed);
+    }
+
+    struct MockSchemaProvider {}
+
+    impl SchemaProvider for MockSchemaProvider {
+        fn get_table_meta(&self, name: &str) -> Option<Rc<Schema>> {
+            match name {
+                "person" => Some(Rc::new(Schema::new(vec![
+                    Field::new("id", DataType::UInt32, false),
+                    Field::new("first name", DataType::Utf8, false),
+                    Field::new("age", DataType::Int32, false),
+                    Field::new("state", DataType::Utf8, false),
+                ]))),
+                _ => None,
+            }
+        }
+
+        fn get_function_meta(&self, name: &str) -> Option<Rc<FunctionMeta>> {
+            match name {
+                "sqrt" => Some(Rc::new(FunctionMeta::new(
+                    "sqrt".to_string(),
+                    vec![Field::new("n", DataType::Float64, false)],
+                    DataType::Float64,
+                    FunctionType::Scalar,
+                ))),
+                _ => None,
+            }
+        }
+    }
+}
@@ -14,6 +14,8 @@
 
 //! Datatype definitions
 
+use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::Formatter;
 use std::ops::Add;
@@ -79,6 +81,28 @@ impl Array {
     pub fn validity_bitmap(&self) -> &Option<Bitmap> {
         &self.validity_bitmap
     }
+
+    /// Determine whether the value at the specified index is null
+    pub fn is_null(&self, index: usize) -> bool {
+        match self.validity_bitmap {
+            Some(ref bitmap) => !bitmap.is_set(index),
+            None => false,
+        }
+    }
+
+    /// Approximate number of bytes used by the values in the array
+    pub fn memory_size(&self) -> usize {
+        let len = self.len();
+        match self.data {
+            ArrayData::Boolean(_) | ArrayData::Int8(_) | ArrayData::UInt8(_) => len,
+            ArrayData::Int16(_) | ArrayData::UInt16(_) => len * 2,
+            ArrayData::Float32(_) | ArrayData::Int32(_) | ArrayData::UInt32(_) => len * 4,
+            ArrayData::Float64(_) | ArrayData::Int64(_) | ArrayData::UInt64(_) => len * 8,
+            // string values plus their offsets
+            ArrayData::Utf8(ref list) => (0..len).map(|i| list.get(i).len() + 4).sum(),
+            ArrayData::Struct(ref fields) => fields.iter().map(|f| f.memory_size()).sum(),
+        }
+    }
 }
 
 macro_rules! arraydata_from_primitive {
@@ -259,15 +283,15 @@ pub fn can_coerce_from(left: &DataType, other: &DataType) -> bool {
             _ => false,
         },
         Int16 => match other {
-            Int8 | Int16 => true,
+            Int8 | Int16 | UInt8 => true,
             _ => false,
         },
         Int32 => match other {
-            Int8 | Int16 | Int32 => true,
+            Int8 | Int16 | Int32 | UInt8 | UInt16 => true,
             _ => false,
         },
         Int64 => match other {
-            Int8 | Int16 | Int32 | Int64 => true,
+            Int8 | Int16 | Int32 | Int64 | UInt8 | UInt16 | UInt32 => true,
             _ => false,
         },
         UInt8 => match other {
@@ -341,6 +365,169 @@ impl ScalarValue {
     }
 }
 
+/// Apply an arithmetic operation to two scalar values of the same numeric type, returning
+/// `None` if the types differ or integer arithmetic overflows or divides by zero
+macro_rules! scalar_arithmetic {
+    ($L:expr, $R:expr, $INT_OP:ident, $FLOAT_OP:tt) => {
+        match ($L, $R) {
+            (ScalarValue::Int8(a), ScalarValue::Int8(b)) => a.$INT_OP(*b).map(ScalarValue::Int8),
+            (ScalarValue::Int16(a), ScalarValue::Int16(b)) => a.$INT_OP(*b).map(ScalarValue::Int16),
+            (ScalarValue::Int32(a), ScalarValue::Int32(b)) => a.$INT_OP(*b).map(ScalarValue::Int32),
+            (ScalarValue::Int64(a), ScalarValue::Int64(b)) => a.$INT_OP(*b).map(ScalarValue::Int64),
+            (ScalarValue::UInt8(a), ScalarValue::UInt8(b)) => a.$INT_OP(*b).map(ScalarValue::UInt8),
+            (ScalarValue::UInt16(a), ScalarValue::UInt16(b)) => {
+                a.$INT_OP(*b).map(ScalarValue::UInt16)
+            }
+            (ScalarValue::UInt32(a), ScalarValue::UInt32(b)) => {
+                a.$INT_OP(*b).map(ScalarValue::UInt32)
+            }
+            (ScalarValue::UInt64(a), ScalarValue::UInt64(b)) => {
+                a.$INT_OP(*b).map(ScalarValue::UInt64)
+            }
+            (ScalarValue::Float32(a), ScalarValue::Float32(b)) => {
+                Some(ScalarValue::Float32(a $FLOAT_OP b))
+            }
+            (ScalarValue::Float64(a), ScalarValue::Float64(b)) => {
+                Some(ScalarValue::Float64(a $FLOAT_OP b))
+            }
+            _ => None,
+        }
+    };
+}
+
+/// Cast an integer value to a numeric type, returning `None` if the value is out of the range
+/// of an integer type
+macro_rules! cast_integer {
+    ($V:expr, $TO:expr) => {{
+        let v = i128::from(*$V);
+        match $TO {
+            DataType::Int8 => i8::try_from(v).ok().map(ScalarValue::Int8),
+            DataType::Int16 => i16::try_from(v).ok().map(ScalarValue::Int16),
+            DataType::Int32 => i32::try_from(v).ok().map(ScalarValue::Int32),
+            DataType::Int64 => i64::try_from(v).ok().map(ScalarValue::Int64),
+            DataType::UInt8 => u8::try_from(v).ok().map(ScalarValue::UInt8),
+            DataType::UInt16 => u16::try_from(v).ok().map(ScalarValue::UInt16),
+            DataType::UInt32 => u32::try_from(v).ok().map(ScalarValue::UInt32),
+            DataType::UInt64 => u64::try_from(v).ok().map(ScalarValue::UInt64),
+            DataType::Float32 => Some(ScalarValue::Float32(*$V as f32)),
+            DataType::Float64 => Some(ScalarValue::Float64(*$V as f64)),
+            _ => None,
+        }
+    }};
+}
+
+/// Truncate a float to an integer type, returning `None` if the value is not finite or the
+/// truncated value is out of the range of the type
+macro_rules! float_to_integer {
+    ($V:expr, $TY:ty) => {
+        if $V.is_finite()
+            && $V > <$TY>::min_value() as f64 - 1.0
+            && $V < <$TY>::max_value() as f64 + 1.0
+        {
+            Some($V as $TY)
+        } else {
+            None
+        }
+    };
+}
+
+/// Cast a float value to a numeric type, returning `None` if the value cannot be represented
+macro_rules! cast_float {
+    ($V:expr, $TO:expr) => {{
+        let v = f64::from(*$V);
+        match $TO {
+            DataType::Int8 => float_to_integer!(v, i8).map(ScalarValue::Int8),
+            DataType::Int16 => float_to_integer!(v, i16).map(ScalarValue::Int16),
+            DataType::Int32 => float_to_integer!(v, i32).map(ScalarValue::Int32),
+            DataType::Int64 => float_to_integer!(v, i64).map(ScalarValue::Int64),
+            DataType::UInt8 => float_to_integer!(v, u8).map(ScalarValue::UInt8),
+            DataType::UInt16 => float_to_integer!(v, u16).map(ScalarValue::UInt16),
+            DataType::UInt32 => float_to_integer!(v, u32).map(ScalarValue::UInt32),
+            DataType::UInt64 => float_to_integer!(v, u64).map(ScalarValue::UInt64),
+            // a finite value that is too large for f32 would become infinite
+            DataType::Float32 if v.is_finite() && (v as f32).is_infinite() => None,
+            DataType::Float32 => Some(ScalarValue::Float32(v as f32)),
+            DataType::Float64 => Some(ScalarValue::Float64(v)),
+            _ => None,
+        }
+    }};
+}
+
+impl ScalarValue {
+    pub fn checked_add(&self, other: &ScalarValue) -> Option<ScalarValue> {
+        scalar_arithmetic!(self, other, checked_add, +)
+    }
+
+    pub fn checked_sub(&self, other: &ScalarValue) -> Option<ScalarValue> {
+        scalar_arithmetic!(self, other, checked_sub, -)
+    }
+
+    pub fn checked_mul(&self, other: &ScalarValue) -> Option<ScalarValue> {
+        scalar_arithmetic!(self, other, checked_mul, *)
+    }
+
+    pub fn checked_div(&self, other: &ScalarValue) -> Option<ScalarValue> {
+        scalar_arithmetic!(self, other, checked_div, /)
+    }
+
+    pub fn checked_rem(&self, other: &ScalarValue) -> Option<ScalarValue> {
+        scalar_arithmetic!(self, other, checked_rem, %)
+    }
+
+    /// Cast a numeric value to another numeric type, or parse a string as another type,
+    /// returning `None` if the value cannot be represented, for example because it is out
+    /// of the range of an integer type
+    pub fn cast_to(&self, data_type: &DataType) -> Option<ScalarValue> {
+        match self {
+            ScalarValue::Int8(v) => cast_integer!(v, data_type),
+            ScalarValue::Int16(v) => cast_integer!(v, data_type),
+            ScalarValue::Int32(v) => cast_integer!(v, data_type),
+            ScalarValue::Int64(v) => cast_integer!(v, data_type),
+            ScalarValue::UInt8(v) => cast_integer!(v, data_type),
+            ScalarValue::UInt16(v) => cast_integer!(v, data_type),
+            ScalarValue::UInt32(v) => cast_integer!(v, data_type),
+            ScalarValue::UInt64(v) => cast_integer!(v, data_type),
+            ScalarValue::Float32(v) => cast_float!(v, data_type),
+            ScalarValue::Float64(v) => cast_float!(v, data_type),
+            ScalarValue::Utf8(ref v) => match data_type {
+                DataType::Utf8 => Some(self.clone()),
+                DataType::Boolean => v.parse().ok().map(ScalarValue::Boolean),
+                DataType::Int8 => v.parse().ok().map(ScalarValue::Int8),
+                DataType::Int16 => v.parse().ok().map(ScalarValue::Int16),
+                DataType::Int32 => v.parse().ok().map(ScalarValue::Int32),
+                DataType::Int64 => v.parse().ok().map(ScalarValue::Int64),
+                DataType::UInt8 => v.parse().ok().map(ScalarValue::UInt8),
+                DataType::UInt16 => v.parse().ok().map(ScalarValue::UInt16),
+                DataType::UInt32 => v.parse().ok().map(ScalarValue::UInt32),
+                DataType::UInt64 => v.parse().ok().map(ScalarValue::UInt64),
+                DataType::Float32 => v.parse().ok().map(ScalarValue::Float32),
+                DataType::Float64 => v.parse().ok().map(ScalarValue::Float64),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Compare two values of the same type, returning `None` if they cannot be compared
+    pub fn compare(&self, other: &ScalarValue) -> Option<Ordering> {
+        match (self, other) {
+            (ScalarValue::Boolean(a), ScalarValue::Boolean(b)) => a.partial_cmp(b),
+            (ScalarValue::Int8(a), ScalarValue::Int8(b)) => a.partial_cmp(b),
+            (ScalarValue::Int16(a), ScalarValue::Int16(b)) => a.partial_cmp(b),
+            (ScalarValue::Int32(a), ScalarValue::Int32(b)) => a.partial_cmp(b),
+            (ScalarValue::Int64(a), ScalarValue::Int64(b)) => a.partial_cmp(b),
+            (ScalarValue::UInt8(a), ScalarValue::UInt8(b)) => a.partial_cmp(b),
+            (ScalarValue::UInt16(a), ScalarValue::UInt16(b)) => a.partial_cmp(b),
+            (ScalarValue::UInt32(a), ScalarValue::UInt32(b)) => a.partial_cmp(b),
+            (ScalarValue::UInt64(a), ScalarValue::UInt64(b)) => a.partial_cmp(b),
+            (ScalarValue::Float32(a), ScalarValue::Float32(b)) => a.partial_cmp(b),
+            (ScalarValue::Float64(a), ScalarValue::Float64(b)) => a.partial_cmp(b),
+            (ScalarValue::Utf8(a), ScalarValue::Utf8(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
 impl Add for ScalarValue {
     type Output = ScalarValue;
 
@@ -421,33 +608,27 @@ pub fn get_supertype(l: &DataType, r: &DataType) -> Option<DataType> {
 fn _get_supertype(l: &DataType, r: &DataType) -> Option<DataType> {
     use self::DataType::*;
     match (l, r) {
-        (UInt8, Int8) => Some(Int8),
+        // an unsigned integer and a signed integer are widened to a signed integer that can
+        // hold both, and no integer type can hold both UInt64 and a signed integer
+        (UInt8, Int8) => Some(Int16),
         (UInt8, Int16) => Some(Int16),
         (UInt8, Int32) => Some(Int32),
         (UInt8, Int64) => Some(Int64),
 
-        (UInt16, Int16) => Some(Int16),
+        (UInt16, Int8) => Some(Int32),
+        (UInt16, Int16) => Some(Int32),
         (UInt16, Int32) => Some(Int32),
         (UInt16, Int64) => Some(Int64),
 
-        (UInt32, Int32) => Some(Int32),
+        (UInt32, Int8) => Some(Int64),
+        (UInt32, Int16) => Some(Int64),
+        (UInt32, Int32) => Some(Int64),
         (UInt32, Int64) => Some(Int64),
 
-        (UInt64, Int64) => Some(Int64),
-
-        (Int8, UInt8) => Some(Int8),
-
-        (Int16, UInt8) => Some(Int16),
-        (Int16, UInt16) => Some(Int16),
-
-        (Int32, UInt8) => Some(Int32),
-        (Int32, UInt16) => Some(Int32),
-        (Int32, UInt32) => Some(Int32),
-
-        (Int64, UInt8) => Some(Int64),
-        (Int64, UInt16) => Some(Int64),
-        (Int64, UInt32) => Some(Int64),
-        (Int64, UInt64) => Some(Int64),
+        (UInt64, Int8) => Some(Float64),
+        (UInt64, Int16) => Some(Float64),
+        (UInt64, Int32) => Some(Float64),
+        (UInt64, Int64) => Some(Float64),
 
         (UInt8, UInt8) => Some(UInt8),
         (UInt8, UInt16) => Some(UInt16),
@@ -539,6 +720,36 @@ pub trait AggregateFunction {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_scalar_arithmetic() {
+        let (a, b) = (ScalarValue::Int32(7), ScalarValue::Int32(2));
+        assert_eq!(Some(ScalarValue::Int32(9)), a.checked_add(&b));
+        assert_eq!(Some(ScalarValue::Int32(3)), a.checked_div(&b));
+        assert_eq!(Some(ScalarValue::Int32(1)), a.checked_rem(&b));
+        assert_eq!(None, a.checked_div(&ScalarValue::Int32(0)));
+        assert_eq!(
+            None,
+            ScalarValue::UInt8(255).checked_add(&ScalarValue::UInt8(1))
+        );
+        assert_eq!(None, a.checked_add(&ScalarValue::Int64(2)));
+        assert_eq!(
+            Some(ScalarValue::Float64(3.5)),
+            a.cast_to(&DataType::Float64)
+                .unwrap()
+                .checked_div(&ScalarValue::Float64(2.0))
+        );
+        assert_eq!(None, ScalarValue::Int64(300).cast_to(&DataType::Int8));
+        assert_eq!(
+            Some(ScalarValue::Int8(-128)),
+            ScalarValue::Int64(-128).cast_to(&DataType::Int8)
+        );
+        assert_eq!(Some(Ordering::Less), b.compare(&a));
+        assert_eq!(
+            None,
+            b.compare(&ScalarValue::Utf8(Rc::new("a".to_string())))
+        );
+    }
+
     #[test]
     fn test_value_scalar_fmt_debug() {
         let value = Value::Scalar(Rc::new(ScalarValue::Float64(1.23456)));
@@ -0,0 +1,377 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Visitors and rewriters for logical plans and expressions
+//!
+//! The traversals use `LogicalPlan::inputs` and `Expr::children` to find the nodes below a
+//! node, so visitors and rewriters only need to handle the variants that they are interested
+//! in. Visitors see each node before and after the nodes below it. Rewriters work bottom up:
+//! each node is rewritten after the nodes below it have been rewritten and the node has been
+//! copied with the new inputs. An expression rewriter can also replace a whole expression
+//! before the expressions below it are rewritten.
+
+use std::rc::Rc;
+
+use super::errors::*;
+use super::logical::{Expr, LogicalPlan};
+
+/// Visits the plans in a plan tree
+pub trait PlanVisitor {
+    /// Called before the inputs of a plan are visited. Returning false skips the inputs.
+    fn pre_visit(&mut self, plan: &LogicalPlan) -> Result<bool>;
+
+    /// Called after the inputs of a plan have been visited
+    fn post_visit(&mut self, _plan: &LogicalPlan) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Rewrites the plans in a plan tree
+pub trait PlanRewriter {
+    /// Rewrite a plan whose inputs have already been rewritten
+    fn rewrite(&mut self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>>;
+}
+
+/// Visits the expressions in an expression tree
+pub trait ExprVisitor {
+    /// Called before the inputs of an expression are visited. Returning false skips the
+    /// inputs.
+    fn pre_visit(&mut self, expr: &Expr) -> Result<bool>;
+
+    /// Called after the inputs of an expression have been visited
+    fn post_visit(&mut self, _expr: &Expr) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Rewrites the expressions in an expression tree
+pub trait ExprRewriter {
+    /// Called before the inputs of an expression are rewritten. Returning an expression
+    /// replaces this expression, and neither its inputs nor the replacement are rewritten.
+    fn pre_rewrite(&mut self, _expr: &Expr) -> Result<Option<Expr>> {
+        Ok(None)
+    }
+
+    /// Rewrite an expression whose inputs have already been rewritten
+    fn rewrite(&mut self, expr: Expr) -> Result<Expr>;
+}
+
+/// Visit a plan and all of the plans below it
+pub fn visit_plan(plan: &LogicalPlan, visitor: &mut PlanVisitor) -> Result<()> {
+    if visitor.pre_visit(plan)? {
+        for input in plan.inputs() {
+            visit_plan(input, visitor)?;
+        }
+    }
+    visitor.post_visit(plan)
+}
+
+/// Rewrite a plan and all of the plans below it. Plans whose inputs are unchanged are not
+/// copied before they are rewritten.
+pub fn rewrite_plan(
+    plan: &Rc<LogicalPlan>,
+    rewriter: &mut PlanRewriter,
+) -> Result<Rc<LogicalPlan>> {
+    let inputs = plan.inputs();
+    let new_inputs = inputs
+        .iter()
+        .map(|input| rewrite_plan(input, rewriter))
+        .collect::<Result<Vec<_>>>()?;
+    let unchanged = inputs
+        .iter()
+        .zip(new_inputs.iter())
+        .all(|(a, b)| Rc::ptr_eq(a, b));
+    if unchanged {
+        rewriter.rewrite(plan)
+    } else {
+        rewriter.rewrite(&Rc::new(plan.with_new_inputs(&new_inputs)))
+    }
+}
+
+/// Visit an expression and all of the expressions below it
+pub fn visit_expr(expr: &Expr, visitor: &mut ExprVisitor) -> Result<()> {
+    if visitor.pre_visit(expr)? {
+        for child in expr.children() {
+            visit_expr(child, visitor)?;
+        }
+    }
+    visitor.post_visit(expr)
+}
+
+/// Rewrite an expression and all of the expressions below it
+pub fn rewrite_expr(expr: &Expr, rewriter: &mut ExprRewriter) -> Result<Expr> {
+    if let Some(replacement) = rewriter.pre_rewrite(expr)? {
+        return Ok(replacement);
+    }
+    let children = expr
+        .children()
+        .into_iter()
+        .map(|child| rewrite_expr(child, rewriter))
+        .collect::<Result<Vec<_>>>()?;
+    rewriter.rewrite(expr.with_new_children(children))
+}
+
+/// Rewrite the expressions of a plan and all of the plans below it. The schemas of the plans
+/// are not changed, so the rewritten expressions must have the same types.
+pub fn rewrite_plan_exprs(
+    plan: &Rc<LogicalPlan>,
+    rewriter: &mut ExprRewriter,
+) -> Result<Rc<LogicalPlan>> {
+    rewrite_plan(plan, &mut PlanExprRewriter { rewriter })
+}
+
+/// Rewrites the expressions of each plan that it rewrites
+struct PlanExprRewriter<'a> {
+    rewriter: &'a mut ExprRewriter,
+}
+
+impl<'a> PlanRewriter for PlanExprRewriter<'a> {
+    fn rewrite(&mut self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        let expr = plan.expressions();
+        if expr.is_empty() {
+            return Ok(plan.clone());
+        }
+        let new_expr = expr
+            .into_iter()
+            .map(|e| rewrite_expr(e, self.rewriter))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Rc::new(plan.with_new_expressions(new_expr)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use logical::Operator;
+    use types::ScalarValue;
+
+    fn plan() -> Rc<LogicalPlan> {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Int64, false),
+        ]));
+        let scan = Rc::new(LogicalPlan::TableScan {
+            schema_name: "default".to_string(),
+            table_name: "t".to_string(),
+            schema: schema.clone(),
+            projection: None,
+            projected_schema: schema.clone(),
+            filters: vec![],
+        });
+        let selection = Rc::new(LogicalPlan::Selection {
+            expr: Expr::Column(1).lt(&Expr::Literal(ScalarValue::Int64(5))),
+            input: scan,
+        });
+        let aggregate = Rc::new(LogicalPlan::Aggregate {
+            input: selection,
+            group_expr: vec![Expr::Column(0)],
+            aggr_expr: vec![Expr::AggregateFunction {
+                name: "MAX".to_string(),
+                args: vec![Expr::Column(1)],
+                return_type: DataType::Int64,
+            }],
+            schema: schema.clone(),
+        });
+        Rc::new(LogicalPlan::Limit {
+            limit: 10,
+            input: aggregate,
+            schema,
+        })
+    }
+
+    /// Records the order in which plans are visited
+    struct PlanNames {
+        names: Vec<String>,
+        skip_below: &'static str,
+    }
+
+    fn plan_name(plan: &LogicalPlan) -> String {
+        let s = format!("{:?}", plan);
+        s[..s.find(':').unwrap()].to_string()
+    }
+
+    impl PlanVisitor for PlanNames {
+        fn pre_visit(&mut self, plan: &LogicalPlan) -> Result<bool> {
+            let name = plan_name(plan);
+            let visit_inputs = name != self.skip_below;
+            self.names.push(format!("pre {}", name));
+            Ok(visit_inputs)
+        }
+
+        fn post_visit(&mut self, plan: &LogicalPlan) -> Result<()> {
+            self.names.push(format!("post {}", plan_name(plan)));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_visit_plan() {
+        let mut visitor = PlanNames {
+            names: vec![],
+            skip_below: "Selection",
+        };
+        visit_plan(&plan(), &mut visitor).unwrap();
+        assert_eq!(
+            vec![
+                "pre Limit",
+                "pre Aggregate",
+                "pre Selection",
+                "post Selection",
+                "post Aggregate",
+                "post Limit",
+            ],
+            visitor.names
+        );
+    }
+
+    /// Removes selections
+    struct RemoveSelections;
+
+    impl PlanRewriter for RemoveSelections {
+        fn rewrite(&mut self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+            match plan.as_ref() {
+                LogicalPlan::Selection { ref input, .. } => Ok(input.clone()),
+                _ => Ok(plan.clone()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_rewrite_plan() {
+        let plan = plan();
+        let new_plan = rewrite_plan(&plan, &mut RemoveSelections).unwrap();
+        assert_eq!(
+            "Limit: 10\
+             \n  Aggregate: groupBy=[[#0]], aggr=[[MAX(#1)]]\
+             \n    TableScan: t projection=None",
+            format!("{:?}", new_plan)
+        );
+
+        // plans that are not rewritten are not copied
+        let scan = plan.inputs()[0].inputs()[0].inputs()[0].clone();
+        let new_scan = rewrite_plan(&scan, &mut RemoveSelections).unwrap();
+        assert!(Rc::ptr_eq(&scan, &new_scan));
+    }
+
+    /// Collects the column indices in the order that they are visited
+    struct Columns(Vec<usize>);
+
+    impl ExprVisitor for Columns {
+        fn pre_visit(&mut self, expr: &Expr) -> Result<bool> {
+            if let Expr::Column(i) = expr {
+                self.0.push(*i);
+            }
+            Ok(true)
+        }
+    }
+
+    /// Replaces literals with their value plus one
+    struct Increment;
+
+    impl ExprRewriter for Increment {
+        fn rewrite(&mut self, expr: Expr) -> Result<Expr> {
+            match expr {
+                Expr::Literal(ScalarValue::Int64(n)) => {
+                    Ok(Expr::Literal(ScalarValue::Int64(n + 1)))
+                }
+                other => Ok(other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_visit_and_rewrite_expr() {
+        let expr = Expr::BinaryExpr {
+            left: Rc::new(Expr::Column(2)),
+            op: Operator::Plus,
+            right: Rc::new(Expr::ScalarFunction {
+                name: "f".to_string(),
+                args: vec![Expr::Column(0), Expr::Literal(ScalarValue::Int64(1))],
+                return_type: DataType::Int64,
+            }),
+        };
+        let mut columns = Columns(vec![]);
+        visit_expr(&expr, &mut columns).unwrap();
+        assert_eq!(vec![2, 0], columns.0);
+
+        let new_expr = rewrite_expr(&expr, &mut Increment).unwrap();
+        assert_eq!("#2 Plus f(#0, Int64(2))", format!("{:?}", new_expr));
+
+        // a function call that is replaced before its arguments are rewritten
+        let new_expr = rewrite_expr(&expr, &mut ReplaceFunctions).unwrap();
+        assert_eq!("#2 Plus Int64(0)", format!("{:?}", new_expr));
+    }
+
+    /// Replaces function calls with zero, and increments other literals
+    struct ReplaceFunctions;
+
+    impl ExprRewriter for ReplaceFunctions {
+        fn pre_rewrite(&mut self, expr: &Expr) -> Result<Option<Expr>> {
+            match expr {
+                Expr::ScalarFunction { .. } => Ok(Some(Expr::Literal(ScalarValue::Int64(0)))),
+                _ => Ok(None),
+            }
+        }
+
+        fn rewrite(&mut self, expr: Expr) -> Result<Expr> {
+            Increment.rewrite(expr)
+        }
+    }
+
+    #[test]
+    fn test_rewrite_plan_exprs() {
+        let new_plan = rewrite_plan_exprs(&plan(), &mut Increment).unwrap();
+        assert_eq!(
+            "Limit: 10\
+             \n  Aggregate: groupBy=[[#0]], aggr=[[MAX(#1)]]\
+             \n    Selection: #1 Lt Int64(6)\
+             \n      TableScan: t projection=None",
+            format!("{:?}", new_plan)
+        );
+    }
+
+    #[test]
+    fn test_plan_helpers() {
+        let plan = plan();
+        let aggregate = plan.inputs()[0];
+        assert_eq!(2, aggregate.expressions().len());
+        assert!(Rc::ptr_eq(
+            aggregate.inputs()[0].schema(),
+            aggregate.expression_schema()
+        ));
+        let new_aggregate = aggregate.with_new_expressions(vec![
+            Expr::Column(1),
+            Expr::AggregateFunction {
+                name: "MIN".to_string(),
+                args: vec![Expr::Column(0)],
+                return_type: DataType::Int64,
+            },
+        ]);
+        assert_eq!(
+            "Aggregate: groupBy=[[#1]], aggr=[[MIN(#0)]]\
+             \n  Selection: #1 Lt Int64(5)\
+             \n    TableScan: t projection=None",
+            format!("{:?}", new_aggregate)
+        );
+
+        // a limit takes the schema of its new input
+        let empty = Rc::new(LogicalPlan::EmptyRelation {
+            schema: Rc::new(Schema::empty()),
+        });
+        let new_plan = plan.with_new_inputs(&[empty]);
+        assert_eq!(0, new_plan.schema().columns().len());
+        assert_eq!(0, new_plan.inputs()[0].inputs().len());
+    }
+}